@@ -0,0 +1,67 @@
+use bevy::{prelude::*, render::camera::Camera};
+
+fn main() {
+    App::build()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(bevy_tiled_prototype::TiledMapPlugin::default())
+        .add_system(bevy::input::system::exit_on_esc_system.system())
+        .add_startup_system(setup.system())
+        .add_system(camera_movement.system())
+        .run();
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // flat-top hexagons staggered along x on the left, pointy-top ones staggered along y on the right
+    commands.spawn_bundle(
+        bevy_tiled_prototype::TiledMapBundle::new(asset_server.load("hex-x-map.tmx"))
+            .centered()
+            .scaled(3.0)
+            .at(Vec3::new(-300.0, 0.0, 0.0)),
+    );
+    commands.spawn_bundle(
+        bevy_tiled_prototype::TiledMapBundle::new(asset_server.load("hex-y-map.tmx"))
+            .centered()
+            .scaled(3.0)
+            .at(Vec3::new(300.0, 0.0, 0.0)),
+    );
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+}
+
+fn camera_movement(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(&Camera, &mut Transform)>,
+) {
+    for (_, mut transform) in query.iter_mut() {
+        let mut direction = Vec3::ZERO;
+        let scale = transform.scale.x;
+
+        if keyboard_input.pressed(KeyCode::A) {
+            direction -= Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        if keyboard_input.pressed(KeyCode::D) {
+            direction += Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        if keyboard_input.pressed(KeyCode::W) {
+            direction += Vec3::new(0.0, 1.0, 0.0);
+        }
+
+        if keyboard_input.pressed(KeyCode::S) {
+            direction -= Vec3::new(0.0, 1.0, 0.0);
+        }
+
+        if keyboard_input.pressed(KeyCode::Z) {
+            let scale = scale + 0.1;
+            transform.scale = Vec3::new(scale, scale, scale);
+        }
+
+        if keyboard_input.pressed(KeyCode::X) && scale > 1.1 {
+            let scale = scale - 0.1;
+            transform.scale = Vec3::new(scale, scale, scale);
+        }
+
+        transform.translation += time.delta_seconds() * direction * 1000.;
+    }
+}
@@ -0,0 +1,84 @@
+use std::{path::Path, time::Instant};
+
+use bevy::{math::Rect, prelude::*};
+use bevy_tiled_prototype::Map;
+
+// this example times Map::objects_in_rect on a generated map with many objects, no window needed:
+// cargo run --release --example object_query_bench
+
+const MAP_TILES: u32 = 500;
+const TILE_SIZE: u32 = 16;
+const OBJECTS: u32 = 5000;
+const QUERIES: u32 = 10000;
+const SCALE: f32 = 4.0;
+
+fn main() {
+    let map_size = MAP_TILES * TILE_SIZE;
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="orthogonal" renderorder="right-down" width="{0}" height="{0}" tilewidth="{1}" tileheight="{1}" infinite="0" nextlayerid="2" nextobjectid="{2}">
+ <objectgroup id="1" name="objects">
+"#,
+        MAP_TILES,
+        TILE_SIZE,
+        OBJECTS + 1
+    );
+    let mut random = Lcg(1);
+    for id in 1..=OBJECTS {
+        xml += &format!(
+            "  <object id=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+            id,
+            random.next() % map_size,
+            random.next() % map_size,
+            TILE_SIZE,
+            TILE_SIZE
+        );
+    }
+    xml += " </objectgroup>\n</map>\n";
+
+    let map = Map::try_from_bytes(Path::new(""), Path::new("generated.tmx"), xml.into_bytes())
+        .expect("generated map should parse");
+    let map_transform = map.center(Transform::from_scale(Vec3::new(SCALE, SCALE, 1.0)));
+
+    // a 1280x720 view at random positions over the map
+    let world_size = map_size as f32 * SCALE;
+    let rects: Vec<Rect<f32>> = (0..QUERIES)
+        .map(|_| {
+            let left = (random.next() % map_size) as f32 * SCALE - world_size / 2.0;
+            let bottom = (random.next() % map_size) as f32 * SCALE - world_size / 2.0;
+            Rect {
+                left,
+                right: left + 1280.0,
+                top: bottom + 720.0,
+                bottom,
+            }
+        })
+        .collect();
+
+    let start = Instant::now();
+    let found: usize = rects
+        .iter()
+        .map(|rect| map.objects_in_rect(*rect, &map_transform).len())
+        .sum();
+    let elapsed = start.elapsed();
+    println!(
+        "{} queries over {} objects found {} objects, {:?} per query",
+        QUERIES,
+        OBJECTS,
+        found,
+        elapsed / QUERIES
+    );
+}
+
+// small deterministic generator, so runs are comparable
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+}
@@ -0,0 +1,86 @@
+use bevy::{prelude::*, render::camera::Camera};
+use bevy_tiled_prototype::{clamp_camera_to_bounds, MapBounds};
+
+// this example moves a player square with WASD, the camera follows it but stays within the map
+
+const SCALE: f32 = 4.0;
+
+struct Player;
+
+fn main() {
+    App::build()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(bevy_tiled_prototype::TiledMapPlugin::default())
+        .add_system(bevy::input::system::exit_on_esc_system.system())
+        .add_startup_system(setup.system())
+        .add_system(player_movement.system())
+        .add_system(camera_follow.system())
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn_bundle(
+        bevy_tiled_prototype::TiledMapBundle::new(asset_server.load("ortho-map.tmx"))
+            .centered()
+            .scaled(SCALE),
+    );
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(Color::rgb(1.0, 0.2, 0.2).into()),
+            sprite: Sprite::new(Vec2::new(16.0, 16.0) * SCALE),
+            transform: Transform::from_xyz(0.0, 0.0, 100.0),
+            ..Default::default()
+        })
+        .insert(Player);
+}
+
+fn player_movement(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<&mut Transform, With<Player>>,
+) {
+    for mut transform in query.iter_mut() {
+        let mut direction = Vec3::ZERO;
+        if keyboard_input.pressed(KeyCode::A) {
+            direction -= Vec3::new(1.0, 0.0, 0.0);
+        }
+        if keyboard_input.pressed(KeyCode::D) {
+            direction += Vec3::new(1.0, 0.0, 0.0);
+        }
+        if keyboard_input.pressed(KeyCode::W) {
+            direction += Vec3::new(0.0, 1.0, 0.0);
+        }
+        if keyboard_input.pressed(KeyCode::S) {
+            direction -= Vec3::new(0.0, 1.0, 0.0);
+        }
+        transform.translation += time.delta_seconds() * direction * 500.;
+    }
+}
+
+fn camera_follow(
+    windows: Res<Windows>,
+    player_query: Query<&Transform, (With<Player>, Without<Camera>)>,
+    bounds_query: Query<&MapBounds>,
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+) {
+    let window = windows.get_primary().unwrap();
+    let player = match player_query.iter().next() {
+        Some(player) => player,
+        None => return,
+    };
+    for mut camera_transform in camera_query.iter_mut() {
+        camera_transform.translation.x = player.translation.x;
+        camera_transform.translation.y = player.translation.y;
+        // the map bounds are added once it is spawned
+        if let Some(bounds) = bounds_query.iter().next() {
+            let viewport =
+                Vec2::new(window.width(), window.height()) * camera_transform.scale.truncate();
+            clamp_camera_to_bounds(&mut camera_transform, &bounds.0, viewport);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use bevy::{prelude::*, render::camera::Camera};
+use bevy_tiled_prototype::{Map, MapAnchor, YAxis};
+
+// this example highlights the tile under the cursor when clicking
+
+const SCALE: f32 = 4.0;
+
+struct Highlight;
+
+fn main() {
+    App::build()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(bevy_tiled_prototype::TiledMapPlugin::default())
+        .add_system(bevy::input::system::exit_on_esc_system.system())
+        .add_startup_system(setup.system())
+        .add_system(highlight_clicked_tile.system())
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn_bundle(
+        bevy_tiled_prototype::TiledMapBundle::new(asset_server.load("ortho-map.tmx"))
+            .centered()
+            .scaled(SCALE),
+    );
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(Color::rgba(1.0, 1.0, 0.0, 0.5).into()),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(Highlight);
+}
+
+fn highlight_clicked_tile(
+    windows: Res<Windows>,
+    mouse_input: Res<Input<MouseButton>>,
+    maps: Res<Assets<Map>>,
+    map_query: Query<(&Handle<Map>, &Transform, &MapAnchor, &YAxis)>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut highlight_query: Query<(&mut Transform, &mut Sprite, &mut Visible), With<Highlight>>,
+) {
+    if !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let window = windows.get_primary().unwrap();
+    let cursor_pos = match window.cursor_position() {
+        Some(cursor_pos) => cursor_pos,
+        None => return,
+    };
+    let (camera, camera_transform) = camera_query.iter().next().unwrap();
+    for (map_handle, origin, anchor, y_axis) in map_query.iter() {
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        let map_transform = map.placement(anchor, y_axis, origin.clone());
+        let tile = map.cursor_to_tile(cursor_pos, window, camera, camera_transform, &map_transform);
+        for (mut transform, mut sprite, mut visible) in highlight_query.iter_mut() {
+            visible.is_visible = tile.is_some();
+            if let Some(tile) = tile {
+                println!("clicked tile {:?}", tile);
+                let world_pos = map.tile_to_world(tile, &map_transform).unwrap();
+                transform.translation = world_pos.extend(100.0);
+                sprite.size = map.tile_size() * SCALE;
+            }
+        }
+    }
+}
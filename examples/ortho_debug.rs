@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use bevy_tiled_prototype::{DebugConfig, Object, TiledMapCenter};
+use bevy_tiled_prototype::{DebugConfig, MapAnchor, Object, ObjectOutline};
 
 // this example demonstrates debugging objects. Hit spacebar to toggle them
 
@@ -8,7 +8,7 @@ const SCALE: f32 = 2.0;
 fn main() {
     App::build()
         .add_plugins(DefaultPlugins)
-        .add_plugin(bevy_tiled_prototype::TiledMapPlugin)
+        .add_plugin(bevy_tiled_prototype::TiledMapPlugin::default())
         .add_system(bevy::input::system::exit_on_esc_system.system())
         .add_system(toggle_debug.system())
         .add_startup_system(setup.system())
@@ -18,18 +18,21 @@ fn main() {
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn_bundle(bevy_tiled_prototype::TiledMapBundle {
         map_asset: asset_server.load("ortho-map.tmx"),
-        center: TiledMapCenter(true),
+        anchor: MapAnchor::Center,
         origin: Transform::from_scale(Vec3::new(SCALE, SCALE, 1.0)),
         debug_config: DebugConfig {
             enabled: true,
-            material: None,
+            ..Default::default()
         },
         ..Default::default()
     });
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
 }
 
-fn toggle_debug(keyboard_input: Res<Input<KeyCode>>, mut query: Query<&mut Visible, With<Object>>) {
+fn toggle_debug(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<&mut Visible, Or<(With<Object>, With<ObjectOutline>)>>,
+) {
     for mut visible in query.iter_mut() {
         if keyboard_input.just_released(KeyCode::Space) {
             visible.is_visible = !visible.is_visible;
@@ -1,10 +1,10 @@
 use bevy::{app::CoreStage::PreUpdate, prelude::*, render::camera::Camera};
-use bevy_tiled_prototype::{MapReadyEvent, TiledMapCenter};
+use bevy_tiled_prototype::MapReadyEvent;
 
 fn main() {
     App::build()
         .add_plugins(DefaultPlugins)
-        .add_plugin(bevy_tiled_prototype::TiledMapPlugin)
+        .add_plugin(bevy_tiled_prototype::TiledMapPlugin::default())
         .add_system(bevy::input::system::exit_on_esc_system.system())
         .add_startup_system(setup.system())
         .add_system(camera_movement.system())
@@ -14,12 +14,11 @@ fn main() {
 }
 
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn_bundle(bevy_tiled_prototype::TiledMapBundle {
-        map_asset: asset_server.load("ortho-map.tmx"),
-        center: TiledMapCenter(true),
-        origin: Transform::from_scale(Vec3::new(4.0, 4.0, 1.0)),
-        ..Default::default()
-    });
+    commands.spawn_bundle(
+        bevy_tiled_prototype::TiledMapBundle::new(asset_server.load("ortho-map.tmx"))
+            .centered()
+            .scaled(4.0),
+    );
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
 }
 
@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use bevy_tiled_prototype::{MapRoot, TiledMapCenter};
+use bevy_tiled_prototype::{MapAnchor, MapRoot};
 
 // this example demonstrates moving the map mesh entities using
 // the MapRoot marker on a passed-in parent element
@@ -15,7 +15,7 @@ fn main() {
     App::build()
         .insert_resource(MovementData::default())
         .add_plugins(DefaultPlugins)
-        .add_plugin(bevy_tiled_prototype::TiledMapPlugin)
+        .add_plugin(bevy_tiled_prototype::TiledMapPlugin::default())
         .add_system(bevy::input::system::exit_on_esc_system.system())
         .add_system(process_input.system())
         .add_system(move_parent_entity.system())
@@ -39,7 +39,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn_bundle(bevy_tiled_prototype::TiledMapBundle {
         map_asset: asset_server.load("ortho-map.tmx"),
         parent_option: Some(parent),
-        center: TiledMapCenter(true),
+        anchor: MapAnchor::Center,
         origin: Transform::from_scale(Vec3::new(4.0, 4.0, 1.0)),
         ..Default::default()
     });
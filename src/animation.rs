@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+
+/// A frame of a Tiled tile animation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Reflect)]
+pub struct AnimationFrame {
+    /// Tile id within the tileset, which is also the sprite index in the tileset's atlas.
+    pub tile_id: u32,
+    /// Duration of the frame in seconds.
+    pub duration: f32,
+}
+
+/// How an animation goes on after its last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect_value(PartialEq)]
+pub enum AnimationMode {
+    /// Starts over from the first frame, as Tiled plays tile animations.
+    Loop,
+    /// Plays backwards to the first frame, then forwards again, showing the end frames once per bounce.
+    PingPong,
+    /// Stops on the last frame.
+    Once,
+}
+
+impl Default for AnimationMode {
+    fn default() -> Self {
+        AnimationMode::Loop
+    }
+}
+
+/// Playback of a Tiled tile animation, attached to animated tile objects.
+#[derive(Debug, Clone, Default, Reflect)]
+#[reflect(Component)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+    pub current_frame: usize,
+    // time spent on the current frame
+    pub elapsed: f32,
+    pub mode: AnimationMode,
+    /// Playing from the last frame towards the first, in `AnimationMode::PingPong`.
+    pub reversed: bool,
+    /// Set once an `AnimationMode::Once` animation reached its last frame, it doesn't advance anymore.
+    pub finished: bool,
+}
+
+impl Animation {
+    pub fn from_tile(tile: &tiled::Tile) -> Option<Animation> {
+        let frames = tile
+            .animation
+            .as_ref()?
+            .iter()
+            .map(|frame| AnimationFrame {
+                tile_id: frame.tile_id,
+                duration: frame.duration as f32 / 1000.0,
+            })
+            .collect::<Vec<_>>();
+        if frames.is_empty() {
+            return None;
+        }
+        Some(Animation {
+            frames,
+            ..Default::default()
+        })
+    }
+
+    /// Tile id of the frame currently displayed.
+    pub fn current_tile_id(&self) -> u32 {
+        self.frames[self.current_frame].tile_id
+    }
+
+    /// Advances the animation, returns true when the frame changed.
+    /// Large deltas advance as many frames as they cover, so playback follows wall-clock time at any frame rate.
+    pub fn tick(&mut self, delta_seconds: f32) -> bool {
+        if self.finished {
+            return false;
+        }
+        self.elapsed += delta_seconds;
+        // whole cycles end on the same frame, skip them instead of stepping through every frame
+        let cycle_duration = self.cycle_duration();
+        if let Some(cycle_duration) = cycle_duration {
+            // without any frame taking time, time would never run out
+            if cycle_duration <= 0.0 {
+                return false;
+            }
+            if self.elapsed >= cycle_duration {
+                self.elapsed %= cycle_duration;
+            }
+        }
+        let mut changed = false;
+        loop {
+            // zero length frames are passed as soon as they are reached
+            let duration = self.frames[self.current_frame].duration;
+            if self.elapsed < duration {
+                break;
+            }
+            let next_frame = match self.next_frame() {
+                Some(next_frame) => next_frame,
+                None => break,
+            };
+            self.elapsed -= duration;
+            self.current_frame = next_frame;
+            changed = true;
+            if self.mode == AnimationMode::Once && self.current_frame == self.frames.len() - 1 {
+                self.finished = true;
+                self.elapsed = 0.0;
+                break;
+            }
+        }
+        changed
+    }
+
+    // time to get back to the same frame going the same way, `None` when playback ends
+    fn cycle_duration(&self) -> Option<f32> {
+        let total: f32 = self.frames.iter().map(|frame| frame.duration).sum();
+        match self.mode {
+            AnimationMode::Loop => Some(total),
+            // the end frames are shown once per bounce, the others on the way there and back
+            AnimationMode::PingPong if self.frames.len() > 1 => Some(
+                2.0 * total - self.frames[0].duration - self.frames[self.frames.len() - 1].duration,
+            ),
+            AnimationMode::PingPong => Some(0.0),
+            AnimationMode::Once => None,
+        }
+    }
+
+    // frame after the current one, turning around in ping-pong mode
+    fn next_frame(&mut self) -> Option<usize> {
+        let last = self.frames.len() - 1;
+        match self.mode {
+            AnimationMode::Loop => Some((self.current_frame + 1) % self.frames.len()),
+            AnimationMode::PingPong if last == 0 => None,
+            AnimationMode::PingPong => {
+                if self.current_frame == 0 {
+                    self.reversed = false;
+                } else if self.current_frame == last {
+                    self.reversed = true;
+                }
+                if self.reversed {
+                    Some(self.current_frame - 1)
+                } else {
+                    Some(self.current_frame + 1)
+                }
+            }
+            AnimationMode::Once if self.current_frame < last => Some(self.current_frame + 1),
+            AnimationMode::Once => None,
+        }
+    }
+}
+
+/// Fired by `update` when an `AnimationMode::Once` animation reaches its last frame.
+#[cfg(not(feature = "headless"))]
+pub struct AnimationFinishedEvent {
+    pub entity: Entity,
+}
+
+/// Plays animations on the sprites of animated tile objects.
+#[cfg(not(feature = "headless"))]
+pub fn update(
+    time: Res<Time>,
+    mut finished_events: EventWriter<AnimationFinishedEvent>,
+    mut query: Query<(Entity, &mut Animation, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut animation, mut sprite) in query.iter_mut() {
+        if animation.finished {
+            continue;
+        }
+        if animation.tick(time.delta_seconds()) {
+            sprite.index = animation.current_tile_id();
+            if animation.finished {
+                finished_events.send(AnimationFinishedEvent { entity });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(durations: &[f32]) -> Animation {
+        Animation {
+            frames: durations
+                .iter()
+                .enumerate()
+                .map(|(tile_id, duration)| AnimationFrame {
+                    tile_id: tile_id as u32,
+                    duration: *duration,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn large_delta_matches_small_ticks() {
+        // a zero length frame in a 0.4s loop, 11.3s in is 0.02s into the third frame
+        let durations = [0.08, 0.0, 0.25, 0.07];
+        let mut one_delta = animation(&durations);
+        let mut small_ticks = animation(&durations);
+        assert!(one_delta.tick(11.3));
+        for _ in 0..1130 {
+            small_ticks.tick(0.01);
+        }
+        assert_eq!(one_delta.current_frame, 2);
+        assert_eq!(small_ticks.current_frame, 2);
+        assert!((one_delta.elapsed - 0.02).abs() < 1e-3);
+        assert!((small_ticks.elapsed - 0.02).abs() < 1e-3);
+    }
+
+    #[test]
+    fn zero_length_frames_are_passed() {
+        let mut animation = animation(&[0.1, 0.0, 0.1]);
+        assert!(animation.tick(0.15));
+        assert_eq!(animation.current_frame, 2);
+        // and looping keeps going past them
+        assert!(animation.tick(0.1));
+        assert_eq!(animation.current_frame, 0);
+        assert!(animation.tick(0.1));
+        assert_eq!(animation.current_frame, 2);
+    }
+
+    #[test]
+    fn zero_length_animations_stay_put() {
+        let mut animation = animation(&[0.0, 0.0]);
+        assert!(!animation.tick(1.0));
+        assert_eq!(animation.current_frame, 0);
+    }
+
+    #[test]
+    fn ping_pong_bounces_at_the_ends() {
+        let mut animation = animation(&[0.1, 0.1, 0.1]);
+        animation.mode = AnimationMode::PingPong;
+        let mut frames = Vec::new();
+        for _ in 0..6 {
+            animation.tick(0.1);
+            frames.push(animation.current_frame);
+        }
+        assert_eq!(frames, vec![1, 2, 1, 0, 1, 2]);
+        // a whole 0.4s cycle is skipped, the rest turns around on the last frame
+        animation.tick(0.55);
+        assert_eq!(animation.current_frame, 1);
+        assert!(animation.reversed);
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let mut animation = animation(&[0.1, 0.0, 0.1]);
+        animation.mode = AnimationMode::Once;
+        assert!(animation.tick(0.15));
+        assert_eq!(animation.current_frame, 2);
+        assert!(animation.finished);
+        assert!(!animation.tick(10.0));
+        assert_eq!(animation.current_frame, 2);
+    }
+}
@@ -0,0 +1,147 @@
+use bevy::{
+    prelude::*,
+    render::{pipeline::RenderPipeline, render_graph::base::MainPass},
+};
+
+use crate::{
+    CreatedMapEntities, DepthConfig, LayerTint, Map, Object, ELLIPSE_SEGMENTS,
+    TILE_MAP_PIPELINE_HANDLE,
+};
+
+#[derive(Reflect)]
+#[reflect(Component)]
+pub struct DebugConfig {
+    pub enabled: bool,
+    #[reflect(ignore)]
+    pub material: Option<Handle<ColorMaterial>>,
+    /// Material of the shape outlines drawn on top of the debug boxes.
+    #[reflect(ignore)]
+    pub outline_material: Option<Handle<ColorMaterial>>,
+    /// Number of triangles approximating ellipse objects.
+    pub ellipse_segments: usize,
+    /// Mesh of the ellipse objects, built from `ellipse_segments` when `None`.
+    #[reflect(ignore)]
+    pub ellipse_mesh: Option<Handle<Mesh>>,
+    /// Draws the outlines of the map's cells over it, in the map's orientation. The grid is built when the map
+    /// is spawned with it set; toggling it afterwards shows or hides it.
+    pub grid: bool,
+    /// Material of the grid lines.
+    #[reflect(ignore)]
+    pub grid_material: Option<Handle<ColorMaterial>>,
+}
+
+/// Marks the grid drawn over a map with `DebugConfig::grid`.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct DebugGrid;
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            material: Default::default(),
+            outline_material: Default::default(),
+            ellipse_segments: ELLIPSE_SEGMENTS,
+            ellipse_mesh: Default::default(),
+            grid: false,
+            grid_material: Default::default(),
+        }
+    }
+}
+
+impl DebugConfig {
+    // materials of the debug boxes and outlines when enabled, and the ellipse mesh
+    pub(crate) fn add_missing_assets(
+        &mut self,
+        materials: &mut Assets<ColorMaterial>,
+        meshes: &mut Assets<Mesh>,
+    ) {
+        if self.enabled && self.material.is_none() {
+            self.material =
+                Some(materials.add(ColorMaterial::from(Color::rgba(0.4, 0.4, 0.9, 0.5))));
+        }
+        if self.enabled && self.outline_material.is_none() {
+            self.outline_material =
+                Some(materials.add(ColorMaterial::from(Color::rgb(0.9, 0.9, 0.2))));
+        }
+        if self.ellipse_mesh.is_none() {
+            self.ellipse_mesh = Some(meshes.add(Object::unit_ellipse_mesh(self.ellipse_segments)));
+        }
+    }
+}
+
+/// Replaces the grid of maps being spawned with `DebugConfig::grid` set.
+pub fn spawn_debug_grids(
+    mut commands: Commands,
+    maps: Res<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(
+        &Handle<Map>,
+        &mut DebugConfig,
+        &DepthConfig,
+        &mut CreatedMapEntities,
+    )>,
+) {
+    for (map_handle, mut debug_config, depth_config, mut created_entities) in query.iter_mut() {
+        let (placement, map) = match (created_entities.spawning, maps.get(map_handle)) {
+            (Some(placement), Some(map)) => (placement, map),
+            _ => continue,
+        };
+        if let Some(grid_entity) = created_entities.grid_entity.take() {
+            commands.entity(grid_entity).despawn_recursive();
+        }
+        if !debug_config.grid {
+            continue;
+        }
+        let grid_material = debug_config
+            .grid_material
+            .get_or_insert_with(|| {
+                materials.add(ColorMaterial::from(Color::rgba(1.0, 1.0, 1.0, 0.5)))
+            })
+            .clone();
+        // above every layer of the map
+        let layer_count =
+            map.map.layers.len() + map.map.image_layers.len() + map.map.object_groups.len();
+        let grid_z = depth_config.layer_z(layer_count as u32);
+        let grid_entity = commands
+            .spawn_bundle((
+                DebugGrid,
+                map_handle.clone(),
+                meshes.add(map.grid_mesh()),
+                grid_material,
+                MainPass,
+                Draw::default(),
+                Visible {
+                    is_visible: true,
+                    is_transparent: true,
+                },
+                RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+                    TILE_MAP_PIPELINE_HANDLE.typed(),
+                )]),
+                // bound by the tile map pipeline
+                LayerTint::default(),
+                placement.content_transform * Transform::from_xyz(0.0, 0.0, grid_z),
+                GlobalTransform::default(),
+            ))
+            .id();
+        commands
+            .entity(placement.root_entity)
+            .push_children(&[grid_entity]);
+        created_entities.grid_entity = Some(grid_entity);
+    }
+}
+
+/// Shows or hides the grids of maps whose `DebugConfig::grid` changed.
+pub fn toggle_debug_grids(
+    map_query: Query<(&DebugConfig, &CreatedMapEntities), Changed<DebugConfig>>,
+    mut grid_query: Query<&mut Visible, With<DebugGrid>>,
+) {
+    for (debug_config, created_entities) in map_query.iter() {
+        if let Some(grid_entity) = created_entities.grid_entity {
+            if let Ok(mut visible) = grid_query.get_mut(grid_entity) {
+                visible.is_visible = debug_config.grid;
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+
+/// Controls how layers and objects are spread along the Z axis.
+#[derive(Reflect)]
+#[reflect(Component)]
+pub struct DepthConfig {
+    /// Z of the first layer.
+    pub base_z: f32,
+    /// Z distance between consecutive layers, raise it to insert your own entities between layers.
+    pub layer_z_step: f32,
+    /// Depth range above their group's layer used to sort objects, see `object_sort`.
+    /// Keep it below `layer_z_step` so objects never reach the next layer.
+    pub object_y_sort_range: f32,
+    /// How objects of a group are ordered within `object_y_sort_range`.
+    pub object_sort: ObjectSortMode,
+}
+
+/// Order objects of a group are drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect_value(PartialEq)]
+pub enum ObjectSortMode {
+    /// In the order of the group, later objects draw in front.
+    Index,
+    /// By the bottom edge of the object, lower objects draw in front, for top-down games.
+    YSort,
+    /// As set on each object group in Tiled: `YSort` for "Top Down" groups, `Index` for "Manual" ones.
+    FromMap,
+}
+
+impl Default for ObjectSortMode {
+    fn default() -> Self {
+        ObjectSortMode::FromMap
+    }
+}
+
+impl DepthConfig {
+    pub fn layer_z(&self, layer_index: u32) -> f32 {
+        self.base_z + layer_index as f32 * self.layer_z_step
+    }
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self {
+            base_z: 0.0,
+            layer_z_step: 1.0,
+            object_y_sort_range: 0.5,
+            object_sort: ObjectSortMode::default(),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use bevy::math::IVec2;
+
+/// Row-major grid of cells covering a map, e.g. for pathfinding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid<T> {
+    pub width: u32,
+    pub height: u32,
+    pub cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(width: u32, height: u32, value: T) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![value; (width * height) as usize],
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        if cell.x < 0 || cell.y < 0 || cell.x >= self.width as i32 || cell.y >= self.height as i32 {
+            return None;
+        }
+        Some(cell.y as usize * self.width as usize + cell.x as usize)
+    }
+
+    /// The cell at `cell`, `None` when outside the grid.
+    pub fn get(&self, cell: IVec2) -> Option<&T> {
+        self.index(cell).map(|index| &self.cells[index])
+    }
+
+    pub fn set(&mut self, cell: IVec2, value: T) {
+        if let Some(index) = self.index(cell) {
+            self.cells[index] = value;
+        }
+    }
+}
@@ -1,6 +1,17 @@
-use crate::{utils::project_iso, utils::project_ortho, LayerChunk, TileChunk};
+use crate::{
+    utils::project_hex, utils::project_iso, utils::project_ortho, view::tile_count, HexLayout,
+    LayerChunk, TileChunk,
+};
 use bevy::prelude::*;
 
+/// Position of a tile layer in `Map::map.layers`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Reflect)]
+pub struct LayerId(pub usize);
+
+/// A tileset of a map, identified by its first gid.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Reflect)]
+pub struct TilesetId(pub u32);
+
 #[derive(Debug)]
 pub struct TilesetLayer {
     pub tile_size: Vec2,
@@ -11,77 +22,109 @@ pub struct TilesetLayer {
 }
 
 impl TilesetLayer {
-    pub fn new(map: &tiled::Map, layer: &tiled::Layer, tileset: &tiled::Tileset) -> TilesetLayer {
+    /// `hex_layout` is required for hexagonal maps, see `Map::hex_layout`.
+    pub fn new(
+        map: &tiled::Map,
+        layer: &tiled::Layer,
+        tileset: &tiled::Tileset,
+        hex_layout: Option<&HexLayout>,
+        tile_offset: Vec2,
+    ) -> TilesetLayer {
         let target_chunk_x = 32;
         let target_chunk_y = 32;
 
-        let chunk_size_x = (map.width as f32 / target_chunk_x as f32).ceil().max(1.0) as usize;
-        let chunk_size_y = (map.height as f32 / target_chunk_y as f32).ceil().max(1.0) as usize;
+        // infinite layers may start at negative coordinates
+        let (origin, size) = layer_tile_bounds(map, layer);
+        let chunk_size_x = (size.x as f32 / target_chunk_x as f32).ceil().max(1.0) as usize;
+        let chunk_size_y = (size.y as f32 / target_chunk_y as f32).ceil().max(1.0) as usize;
 
         let tile_width = tileset.tile_width as f32;
         let tile_height = tileset.tile_height as f32;
-        let tile_space = tileset.spacing as f32;
+        let map_tile_width = map.tile_width as f32;
+        let map_tile_height = map.tile_height as f32;
 
-        let mut chunks = Vec::new();
+        let mut chunks = Vec::with_capacity(chunk_size_x);
         // 32 x 32 tile chunk sizes
         for chunk_x in 0..chunk_size_x {
-            let mut chunks_y = Vec::new();
+            let mut chunks_y = Vec::with_capacity(chunk_size_y);
             for chunk_y in 0..chunk_size_y {
-                let mut tiles = Vec::new();
+                let mut tiles = Vec::with_capacity(target_chunk_x);
 
                 for tile_x in 0..target_chunk_x {
-                    let mut tiles_y = Vec::new();
+                    let mut tiles_y = Vec::with_capacity(target_chunk_y);
                     for tile_y in 0..target_chunk_y {
-                        let lookup_x = (chunk_x * target_chunk_x) + tile_x;
-                        let lookup_y = (chunk_y * target_chunk_y) + tile_y;
+                        let lookup_x = origin.x + ((chunk_x * target_chunk_x) + tile_x) as i32;
+                        let lookup_y = origin.y + ((chunk_y * target_chunk_y) + tile_y) as i32;
                         let chunk_pos = Vec2::new(lookup_x as f32, lookup_y as f32);
 
                         tiles_y.push(
-                            if lookup_x < map.width as usize && lookup_y < map.height as usize {
-                                let map_tile = match &layer.tiles {
-                                    tiled::LayerData::Finite(tiles) => &tiles[lookup_y][lookup_x],
-                                    _ => panic!("Infinite maps not supported"),
-                                };
+                            if let Some(map_tile) = layer_tile(layer, lookup_x, lookup_y) {
                                 // tile not in this set
                                 if map_tile.gid < tileset.first_gid
-                                    || map_tile.gid
-                                        >= tileset.first_gid + tileset.tilecount.unwrap()
+                                    || map_tile.gid >= tileset.first_gid + tile_count(tileset)
                                 {
                                     continue;
                                 }
                                 // Calculate positions
-                                let vertex = match map.orientation {
-                                    tiled::Orientation::Orthogonal => {
-                                        let center =
-                                            project_ortho(chunk_pos, tile_width, tile_height);
-
-                                        let start = Vec2::new(
-                                            center.x,
-                                            center.y - tile_height - tile_space,
+                                let vertex = match (map.orientation, hex_layout) {
+                                    (tiled::Orientation::Orthogonal, _) => {
+                                        // cells are on the map's grid, the image sits on the bottom of the
+                                        // cell, tiles of image collections may be larger than it
+                                        let top_left = project_ortho(
+                                            chunk_pos,
+                                            map_tile_width,
+                                            map_tile_height,
                                         );
+                                        let bottom = top_left.y - map_tile_height;
 
-                                        let end =
-                                            Vec2::new(center.x + tile_width + tile_space, center.y);
+                                        let start = Vec2::new(top_left.x, bottom);
+
+                                        let end = Vec2::new(
+                                            top_left.x + tile_width,
+                                            bottom + tile_height,
+                                        );
 
                                         Vec4::new(start.x, start.y, end.x, end.y)
                                     }
-                                    tiled::Orientation::Isometric => {
-                                        let center =
-                                            project_iso(chunk_pos, tile_width, tile_height);
+                                    (tiled::Orientation::Isometric, _) => {
+                                        let top =
+                                            project_iso(chunk_pos, map_tile_width, map_tile_height);
+                                        let bottom = top.y - map_tile_height;
 
-                                        let start = Vec2::new(
-                                            center.x - tile_width / 2.0,
-                                            center.y - tile_height,
-                                        );
+                                        let start = Vec2::new(top.x - tile_width / 2.0, bottom);
 
-                                        let end = Vec2::new(center.x + tile_width / 2.0, center.y);
+                                        let end = Vec2::new(
+                                            top.x + tile_width / 2.0,
+                                            bottom + tile_height,
+                                        );
 
                                         Vec4::new(start.x, start.y, end.x, end.y)
                                     }
+                                    (tiled::Orientation::Hexagonal, Some(hex_layout)) => {
+                                        // cells are spaced by the side length, the image sits on the
+                                        // bottom of the cell's bounding box like on orthogonal maps
+                                        let top_left = project_hex(chunk_pos, hex_layout);
+                                        let bottom = top_left.y - hex_layout.tile_height;
+
+                                        Vec4::new(
+                                            top_left.x,
+                                            bottom,
+                                            top_left.x + tile_width,
+                                            bottom + tile_height,
+                                        )
+                                    }
                                     _ => {
                                         panic!("Unsupported orientation {:?}", map.orientation)
                                     }
                                 };
+                                // the tileset's tile offset points down in Tiled, up in the world
+                                let vertex = vertex
+                                    + Vec4::new(
+                                        tile_offset.x,
+                                        -tile_offset.y,
+                                        tile_offset.x,
+                                        -tile_offset.y,
+                                    );
                                 // Get chunk tile.
                                 TileChunk::from_layer_and_tileset(
                                     map_tile, tileset, chunk_pos, vertex,
@@ -96,6 +139,7 @@ impl TilesetLayer {
                                     flip_d: false,
                                     flip_h: false,
                                     flip_v: false,
+                                    color: [1.0; 4],
                                 }
                             },
                         ); // end tiles_y.push(chunk_tile);
@@ -103,9 +147,11 @@ impl TilesetLayer {
                     tiles.push(tiles_y);
                 }
 
+                let bounds = chunk_bounds(&tiles);
                 let chunk = LayerChunk {
                     position: Vec2::new(chunk_x as f32, chunk_y as f32),
                     tiles,
+                    bounds,
                 };
                 chunks_y.push(chunk);
             }
@@ -121,7 +167,154 @@ impl TilesetLayer {
         }
     }
 }
+// first tile and size in tiles of the area covered by the layer
+pub(crate) fn layer_tile_bounds(map: &tiled::Map, layer: &tiled::Layer) -> (IVec2, IVec2) {
+    match &layer.tiles {
+        tiled::LayerData::Finite(_) => {
+            (IVec2::ZERO, IVec2::new(map.width as i32, map.height as i32))
+        }
+        tiled::LayerData::Infinite(chunks) => {
+            let mut min = IVec2::new(i32::MAX, i32::MAX);
+            let mut max = IVec2::new(i32::MIN, i32::MIN);
+            for chunk in chunks.values() {
+                min = min.min(IVec2::new(chunk.x, chunk.y));
+                max = max.max(IVec2::new(
+                    chunk.x + chunk.width as i32,
+                    chunk.y + chunk.height as i32,
+                ));
+            }
+            if chunks.is_empty() {
+                (IVec2::ZERO, IVec2::ZERO)
+            } else {
+                (min, max - min)
+            }
+        }
+    }
+}
+
+fn layer_tile(layer: &tiled::Layer, x: i32, y: i32) -> Option<&tiled::LayerTile> {
+    match &layer.tiles {
+        tiled::LayerData::Finite(tiles) => {
+            if x < 0 || y < 0 {
+                return None;
+            }
+            tiles.get(y as usize)?.get(x as usize)
+        }
+        tiled::LayerData::Infinite(chunks) => {
+            // tiled chunks all share the same size and are keyed by their first tile
+            let any_chunk = chunks.values().next()?;
+            let (width, height) = (any_chunk.width as i32, any_chunk.height as i32);
+            let key = (x.div_euclid(width) * width, y.div_euclid(height) * height);
+            let chunk = chunks.get(&key)?;
+            chunk
+                .tiles
+                .get((y - chunk.y) as usize)?
+                .get((x - chunk.x) as usize)
+        }
+    }
+}
+
+// area covered by the non-empty tiles of a chunk, as min x, min y, max x, max y
+fn chunk_bounds(tiles: &[Vec<TileChunk>]) -> Vec4 {
+    let mut bounds: Option<Vec4> = None;
+    for tile in tiles.iter().flatten().filter(|tile| tile.tile_id != 0) {
+        let vertex = tile.vertex;
+        bounds = Some(match bounds {
+            None => vertex,
+            Some(bounds) => Vec4::new(
+                bounds.x.min(vertex.x),
+                bounds.y.min(vertex.y),
+                bounds.z.max(vertex.z),
+                bounds.w.max(vertex.w),
+            ),
+        });
+    }
+    bounds.unwrap_or(Vec4::ZERO)
+}
+
 #[derive(Debug)]
 pub struct MapLayer {
     pub tileset_layers: Vec<TilesetLayer>,
+    pub layer_index: u32,   // position among all map layers, determines depth
+    pub tiled_layer: usize, // position in Map::map.layers
+    pub name: String,
+    pub visible: bool, // hidden layers have no chunk meshes unless spawned with LoadHiddenLayers
+    pub class: String, // set in Tiled 1.9 and later, empty otherwise
+    pub properties: tiled::Properties,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::{Map, StaggerAxis};
+
+    // Neighbouring hexagons of a fully tiled hex map share a side, and no other hexagons share a corner,
+    // so the tiles cover the map without gaps or overlaps.
+    fn assert_hexagons_tessellate(name: &str) {
+        let bytes = fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("assets")
+                .join(name),
+        )
+        .unwrap();
+        let map = Map::try_from_bytes(Path::new(""), Path::new(name), bytes).unwrap();
+        let layout = map.hex_layout.as_ref().unwrap();
+
+        // the tile quads are the bounding boxes of the hexagons, with corners relative to their top left
+        let hexagons: Vec<Vec<(i32, i32)>> = map.layers[0]
+            .tileset_layers
+            .iter()
+            .flat_map(|tileset_layer| tileset_layer.chunks.iter().flatten())
+            .flat_map(|chunk| chunk.tiles.iter().flatten())
+            .filter(|tile| tile.tile_id != 0)
+            .map(|tile| {
+                layout
+                    .corners()
+                    .iter()
+                    .map(|corner| {
+                        let (x, y) = (tile.vertex.x + corner.x, tile.vertex.w - corner.y);
+                        ((x * 1000.0).round() as i32, (y * 1000.0).round() as i32)
+                    })
+                    .collect()
+            })
+            .collect();
+        let (width, height) = (map.map.width, map.map.height);
+        assert_eq!(hexagons.len(), (width * height) as usize);
+
+        let mut shared_sides = 0;
+        for (i, hexagon) in hexagons.iter().enumerate() {
+            for other in hexagons[i + 1..].iter() {
+                let shared = hexagon
+                    .iter()
+                    .filter(|corner| other.contains(corner))
+                    .count();
+                assert!(
+                    shared == 0 || shared == 2,
+                    "{}: hexagons sharing {} corners",
+                    name,
+                    shared
+                );
+                if shared == 2 {
+                    shared_sides += 1;
+                }
+            }
+        }
+        // a line of hexagons along the stagger axis touches the next line 2 * length - 1 times
+        let expected = match layout.stagger_axis {
+            StaggerAxis::X => width * (height - 1) + (width - 1) * (2 * height - 1),
+            StaggerAxis::Y => height * (width - 1) + (height - 1) * (2 * width - 1),
+        };
+        assert_eq!(shared_sides, expected, "{}", name);
+    }
+
+    #[test]
+    fn hexagons_staggered_along_x_tessellate() {
+        assert_hexagons_tessellate("hex-x-map.tmx");
+    }
+
+    #[test]
+    fn hexagons_staggered_along_y_tessellate() {
+        assert_hexagons_tessellate("hex-y-map.tmx");
+    }
 }
@@ -1,38 +1,237 @@
-use bevy::{asset::AssetServerSettings, prelude::*};
+use std::sync::Arc;
+
+#[cfg(not(feature = "headless"))]
+use bevy::render::texture::TextureFormat;
+use bevy::{ecs::system::EntityCommands, prelude::*};
 
 mod utils;
 pub use utils::*;
 
+pub mod animation;
+#[cfg(not(feature = "headless"))]
+pub use animation::AnimationFinishedEvent;
+pub use animation::{Animation, AnimationFrame, AnimationMode};
+
+#[cfg(not(feature = "headless"))]
+mod debug;
+#[cfg(not(feature = "headless"))]
+pub use debug::*;
+mod depth;
+pub use depth::*;
+mod grid;
+pub use grid::*;
 mod loader;
+pub use loader::{MapLoadErrorEvent, MapLoadErrors};
 mod map;
 pub use map::*;
 mod layers;
 pub use layers::*;
 mod objects;
 pub use objects::*;
+mod object_index;
+pub use object_index::*;
+mod properties;
+pub use properties::*;
+mod spawn;
+pub use spawn::*;
+#[cfg(not(feature = "headless"))]
+mod streaming;
+#[cfg(not(feature = "headless"))]
+pub use streaming::*;
+mod templates;
+mod tiles;
+pub use tiles::*;
+mod tilesets;
+mod tmx;
+mod writer;
 
 mod view;
 pub use view::*;
 /// Adds support for GLTF file loading to Apps
 #[derive(Default)]
-pub struct TiledMapPlugin;
+pub struct TiledMapPlugin {
+    object_callbacks: ObjectTypeCallbacks,
+    tile_hooks: TileHooks,
+    tile_clicks: bool,
+    property_prefix: Option<String>,
+    map_stats: bool,
+    #[cfg(not(feature = "headless"))]
+    color_format: Option<TextureFormat>,
+}
+
+impl TiledMapPlugin {
+    /// Registers a callback run for every object of `obj_type` when it is spawned.
+    /// The callback receives the object's entity, its data and its transform.
+    pub fn on_object_type<F>(mut self, obj_type: &str, callback: F) -> Self
+    where
+        F: Fn(&mut EntityCommands, &Object, &Transform) + Send + Sync + 'static,
+    {
+        self.object_callbacks.register(obj_type, Arc::new(callback));
+        self
+    }
+
+    /// Registers a hook run for every tile entity spawned with `SpawnTileEntities`, e.g. to insert
+    /// gameplay components. The hook receives the tile's entity, its gid and its properties, empty if it has none.
+    pub fn with_tile_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut EntityCommands, u32, &Properties) + Send + Sync + 'static,
+    {
+        self.tile_hooks.register(Arc::new(hook));
+        self
+    }
+
+    /// Sends a `TileClicked` event when the left mouse button is pressed over a tile.
+    pub fn send_tile_clicks(mut self) -> Self {
+        self.tile_clicks = true;
+        self
+    }
+
+    /// Copies the map properties named with `prefix` into the `TiledMapProperties` resource
+    /// when a map becomes ready, so systems can read them with `Res`.
+    pub fn map_properties_as_resource(mut self, prefix: &str) -> Self {
+        self.property_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Format of the color targets tile chunks are drawn to. Defaults to Bevy's `TextureFormat::default()`,
+    /// the format of the window swap chain and of Bevy's own pipelines; set it when drawing to other targets.
+    #[cfg(not(feature = "headless"))]
+    pub fn color_format(mut self, format: TextureFormat) -> Self {
+        self.color_format = Some(format);
+        self
+    }
+
+    /// Logs the `MapStats` of each map at info level when it is loaded.
+    pub fn log_map_stats(mut self) -> Self {
+        self.map_stats = true;
+        self
+    }
+}
 
 impl Plugin for TiledMapPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        let asset_folder = app
-            .world()
-            .get_resource::<AssetServerSettings>()
-            .unwrap()
-            .asset_folder
-            .clone();
-
+        let load_errors = MapLoadErrors::default();
         app.add_asset::<map::Map>()
-            .add_asset_loader(loader::TiledMapLoader::new(asset_folder))
+            .add_asset_loader(loader::TiledMapLoader::new(load_errors.clone()))
             .add_event::<ObjectReadyEvent>()
             .add_event::<MapReadyEvent>()
-            .add_system(process_loaded_tile_maps.system());
+            .add_event::<MapLoadErrorEvent>()
+            .add_event::<MapLoadProgressEvent>()
+            .add_event::<TileClicked>()
+            .add_event::<ReloadMap>()
+            .insert_resource(self.object_callbacks.clone())
+            .insert_resource(self.tile_hooks.clone())
+            .insert_resource(load_errors)
+            .register_type::<Object>()
+            .register_type::<ObjectId>()
+            .register_type::<TileMapChunk>()
+            .register_type::<ChunkInfo>()
+            .register_type::<ChunkTileset>()
+            .register_type::<LayerId>()
+            .register_type::<TilesetId>()
+            .register_type::<TiledMapCenter>()
+            .register_type::<MapRoot>()
+            .register_type::<MapChunkRoot>()
+            .register_type::<DepthConfig>()
+            .register_type::<SpawnTileEntities>()
+            .register_type::<TilePos>()
+            .register_type::<TileGid>()
+            .register_type::<TileFlip>()
+            .register_type::<TriggerZone>()
+            .register_type::<Path>()
+            .register_type::<Animation>()
+            .register_type::<AnimationFrame>()
+            .register_type::<AnimationMode>()
+            .register_type::<DrawOrder>()
+            .register_type::<ObjectAlignment>()
+            .register_type::<ObjectSortMode>()
+            .register_type::<MapAnchor>()
+            .register_type::<YAxis>()
+            .register_type::<ShapeObjects>()
+            .register_type::<LoadHiddenLayers>()
+            .register_type::<MapBounds>()
+            .register_type::<MapProperties>()
+            .register_type::<LayerProperties>()
+            .register_type::<MapClass>()
+            .register_type::<LayerClass>()
+            .add_system(loader::send_map_load_errors.system())
+            .add_system(reload_maps.system())
+            .add_system(prepare_map_spawns.system().label(MapSpawnSystem::Prepare))
+            .add_system(
+                spawn_map_objects
+                    .system()
+                    .label(MapSpawnSystem::Spawn)
+                    .after(MapSpawnSystem::Prepare),
+            )
+            .add_system(
+                finish_map_spawns
+                    .system()
+                    .label(MapSpawnSystem::Finish)
+                    .after(MapSpawnSystem::Spawn),
+            );
+        if let Some(prefix) = &self.property_prefix {
+            app.insert_resource(MapPropertyPrefix(prefix.clone()))
+                .init_resource::<TiledMapProperties>()
+                .add_system(insert_map_property_resources.system());
+        }
+        if self.map_stats {
+            app.add_system(log_map_stats.system());
+        }
 
-        let world = app.world_mut();
-        add_tile_map_graph(world);
+        #[cfg(not(feature = "headless"))]
+        {
+            app.add_asset::<TileMapArrayMaterial>()
+                .init_resource::<TilesetMaterials>()
+                .add_event::<TilesetReadyEvent>()
+                .add_event::<AnimationFinishedEvent>()
+                .register_type::<DebugConfig>()
+                .register_type::<DebugGrid>()
+                .register_type::<LayerFilter>()
+                .register_type::<StreamChunks>()
+                .register_type::<TilesetTextureArray>()
+                .register_type::<PremultipliedAlpha>()
+                .register_type::<LayerTint>()
+                .register_type::<ObjectOutline>()
+                .add_system(
+                    update_tileset_materials
+                        .system()
+                        .label(MapSpawnSystem::Prepare),
+                )
+                .add_system(
+                    spawn_map_chunks
+                        .system()
+                        .label(MapSpawnSystem::Spawn)
+                        .after(MapSpawnSystem::Prepare),
+                )
+                .add_system(
+                    spawn_array_chunks
+                        .system()
+                        .label(MapSpawnSystem::Spawn)
+                        .after(MapSpawnSystem::Prepare),
+                )
+                .add_system(
+                    spawn_tile_entities
+                        .system()
+                        .label(MapSpawnSystem::Spawn)
+                        .after(MapSpawnSystem::Prepare),
+                )
+                .add_system(
+                    spawn_debug_grids
+                        .system()
+                        .label(MapSpawnSystem::Spawn)
+                        .after(MapSpawnSystem::Prepare),
+                )
+                .add_system(show_loaded_object_sprites.system())
+                .add_system(animation::update.system())
+                .add_system(track_map_load_progress.system())
+                .add_system(toggle_debug_grids.system())
+                .add_system(apply_transparent_colors.system())
+                .add_system(build_tileset_array_textures.system())
+                .add_system(stream_map_chunks.system().after(MapSpawnSystem::Finish));
+            if self.tile_clicks {
+                app.add_system(send_tile_clicked_events.system());
+            }
+            add_tile_map_graph(app.world_mut(), self.color_format.unwrap_or_default());
+        }
     }
 }
@@ -1,20 +1,46 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
-use crate::map::Map;
+use crate::{map::Map, tmx::TmxDocument};
 use anyhow::Result;
+#[cfg(not(feature = "headless"))]
+use bevy::asset::AssetPath;
 use bevy::{
-    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
-    utils::BoxedFuture,
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
 };
+
+/// Fired when a map fails to load, so the failure can be shown to the player.
+#[derive(Debug, Clone)]
+pub struct MapLoadErrorEvent {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Errors collected by the loader, which runs outside of the schedule, until they are sent as events.
+#[derive(Default, Clone)]
+pub struct MapLoadErrors(Arc<Mutex<Vec<MapLoadErrorEvent>>>);
+
+pub fn send_map_load_errors(
+    load_errors: Res<MapLoadErrors>,
+    mut error_events: EventWriter<MapLoadErrorEvent>,
+) {
+    let mut errors = load_errors.0.lock().unwrap();
+    for error in errors.drain(..) {
+        error_events.send(error);
+    }
+}
+
 pub struct TiledMapLoader {
-    asset_folder: PathBuf,
+    load_errors: MapLoadErrors,
 }
 
 impl TiledMapLoader {
-    pub fn new<P: AsRef<Path>>(path: P) -> Self {
-        TiledMapLoader {
-            asset_folder: path.as_ref().to_path_buf(),
-        }
+    pub fn new(load_errors: MapLoadErrors) -> Self {
+        TiledMapLoader { load_errors }
     }
 
     pub fn remove_tile_flags(tile: u32) -> u32 {
@@ -23,12 +49,24 @@ impl TiledMapLoader {
     }
 }
 
-const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x80000000;
-const FLIPPED_VERTICALLY_FLAG: u32 = 0x40000000;
-const FLIPPED_DIAGONALLY_FLAG: u32 = 0x20000000;
+pub(crate) const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x80000000;
+pub(crate) const FLIPPED_VERTICALLY_FLAG: u32 = 0x40000000;
+pub(crate) const FLIPPED_DIAGONALLY_FLAG: u32 = 0x20000000;
 const ALL_FLIP_FLAGS: u32 =
     FLIPPED_HORIZONTALLY_FLAG | FLIPPED_VERTICALLY_FLAG | FLIPPED_DIAGONALLY_FLAG;
 
+// external tilesets and templates are read through the asset io like the map, so they load on every platform
+async fn load_map(load_context: &LoadContext<'_>, path: &Path, bytes: &[u8]) -> Result<Map> {
+    let document = TmxDocument::parse(bytes)?;
+    let mut files = HashMap::default();
+    for file in document.referenced_files(path) {
+        if let Ok(file_bytes) = load_context.read_asset_bytes(&file).await {
+            files.insert(file, file_bytes);
+        }
+    }
+    Map::from_document(path, bytes.into(), document, &files)
+}
+
 impl AssetLoader for TiledMapLoader {
     fn load<'a>(
         &'a self,
@@ -36,18 +74,32 @@ impl AssetLoader for TiledMapLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let path = load_context.path();
-            let mut map = Map::try_from_bytes(self.asset_folder.as_path(), path, bytes.into())?;
-            let dependencies = map
+            let path = load_context.path().to_path_buf();
+            let map = match load_map(load_context, &path, bytes).await {
+                Ok(map) => map,
+                Err(error) => {
+                    self.load_errors.0.lock().unwrap().push(MapLoadErrorEvent {
+                        path,
+                        error: error.to_string(),
+                    });
+                    return Err(error);
+                }
+            };
+            // keep the list on the map so load progress can be tracked,
+            // without rendering there is nothing to load the images with
+            #[cfg(not(feature = "headless"))]
+            let dependencies: Vec<AssetPath<'static>> = map
                 .asset_dependencies
-                .drain(..)
+                .iter()
                 .map(|image_path| {
                     // add tileset to dependencies
-                    AssetPath::new(image_path, None)
+                    AssetPath::new(image_path.clone(), None)
                 })
                 .collect();
             let loaded_asset = LoadedAsset::new(map);
-            load_context.set_default_asset(loaded_asset.with_dependencies(dependencies));
+            #[cfg(not(feature = "headless"))]
+            let loaded_asset = loaded_asset.with_dependencies(dependencies);
+            load_context.set_default_asset(loaded_asset);
             Ok(())
         })
     }
@@ -1,14 +1,39 @@
 use crate::{
-    objects::ObjectGroup, utils::project_iso, utils::project_ortho, ChunkBundle, MapLayer,
-    TilesetLayer,
+    layers::layer_tile_bounds,
+    loader::TiledMapLoader,
+    objects::{Object, ObjectGroup},
+    templates::apply_templates,
+    tilesets::split_tileset_images,
+    tmx::TmxDocument,
+    utils::project_hex,
+    utils::project_iso,
+    utils::project_ortho,
+    utils::unproject_hex,
+    utils::unproject_iso,
+    utils::unproject_ortho,
+    utils::{HexLayout, StaggerAxis},
+    view::{atlas_cell, tile_count},
+    Grid, LayerId, MapLayer, ObjectIndex, PropertiesExt, TileFlip, TilesetLayer,
 };
-use anyhow::Result;
+#[cfg(not(feature = "headless"))]
+use crate::{screen_to_world, view::compact_indices};
+use anyhow::{anyhow, Result};
+#[cfg(all(not(target_arch = "wasm32"), not(target_os = "android")))]
+use bevy::asset::{AssetIo, FileAssetIo};
+#[cfg(not(feature = "headless"))]
+use bevy::render::{camera::Camera, mesh::VertexAttributeValues, pipeline::PrimitiveTopology};
 use bevy::{
     prelude::*,
     reflect::TypeUuid,
     utils::{HashMap, HashSet},
 };
+#[cfg(not(feature = "headless"))]
 use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+use std::{
+    future::Future,
     io::BufReader,
     path::{Path, PathBuf},
 };
@@ -24,54 +49,802 @@ pub use tiled::PropertyValue;
 #[uuid = "5f6fbac8-3f52-424e-a928-561667fea074"]
 pub struct Map {
     pub map: tiled::Map,
-    pub meshes: Vec<(u32, u32, Mesh)>,
+    // chunk meshes of the visible layers as (index in `layers`, tileset first gid, chunk origin, mesh),
+    // moved into Assets<Mesh> when the map is spawned, see `Map::take_meshes`
+    #[cfg(not(feature = "headless"))]
+    pub meshes: Vec<(u32, u32, Vec2, Mesh)>,
+    // vertices of `meshes` as built, they are gone from the map once spawned
+    mesh_vertex_count: usize,
+    // hash of everything the spawned chunks and objects depend on except the tileset images,
+    // to tell image-only changes from geometry changes, see `geometry_hash`
+    #[cfg(not(feature = "headless"))]
+    pub(crate) geometry_hash: u64,
     pub layers: Vec<MapLayer>,
     pub groups: Vec<ObjectGroup>,
+    pub object_index: ObjectIndex,
+    // maps tile gid to the collision objects drawn on it in the tileset editor
+    pub tile_collisions: HashMap<u32, Vec<Object>>,
     pub tile_size: Vec2,
+    // read from the map tag for hexagonal maps, tiled doesn't parse it
+    pub hex_layout: Option<HexLayout>,
+    // set in Tiled 1.9 and later, empty otherwise
+    pub class: String,
     pub image_folder: std::path::PathBuf,
     pub asset_dependencies: Vec<PathBuf>,
+    // object templates (.tx files) used by the map, relative to the asset folder
+    pub template_paths: Vec<PathBuf>,
 }
 
 impl Map {
     pub fn center(&self, origin: Transform) -> Transform {
+        self.anchor(&MapAnchor::Center, origin)
+    }
+
+    /// Transform of the map content so that the `anchor` point of the map is at `origin`.
+    pub fn anchor(&self, anchor: &MapAnchor, origin: Transform) -> Transform {
+        let normalized = anchor.normalized();
+        if normalized == Vec2::ZERO {
+            return origin;
+        }
         let tile_size = Vec2::new(self.map.tile_width as f32, self.map.tile_height as f32);
-        let map_center = Vec2::new(self.map.width as f32 / 2.0, self.map.height as f32 / 2.0);
-        match self.map.orientation {
-            tiled::Orientation::Orthogonal => {
-                let center = project_ortho(map_center, tile_size.x, tile_size.y);
-                Transform::from_matrix(
-                    origin.compute_matrix() * Mat4::from_translation(-center.extend(0.0)),
+        let map_point = Vec2::new(self.map.width as f32, self.map.height as f32) * normalized;
+        let anchor_point = match self.map.orientation {
+            tiled::Orientation::Orthogonal => project_ortho(map_point, tile_size.x, tile_size.y),
+            tiled::Orientation::Isometric => project_iso(map_point, tile_size.x, tile_size.y),
+            tiled::Orientation::Hexagonal => {
+                // staggered cells don't line up with the grid, anchor within the bounding box
+                let bounds = self.bounds(&Transform::identity());
+                Vec2::new(
+                    bounds.left + (bounds.right - bounds.left) * normalized.x,
+                    bounds.top - (bounds.top - bounds.bottom) * normalized.y,
                 )
             }
+            _ => panic!("Unsupported orientation {:?}", self.map.orientation),
+        };
+        Transform::from_matrix(
+            origin.compute_matrix() * Mat4::from_translation(-anchor_point.extend(0.0)),
+        )
+    }
+
+    /// Transform of the map content so that the `anchor` point of the map is at `origin`, with y pointing
+    /// the way `y_axis` says. `Map::anchor` is this with `YAxis::FlipToBevy`.
+    pub fn placement(&self, anchor: &MapAnchor, y_axis: &YAxis, origin: Transform) -> Transform {
+        match y_axis {
+            YAxis::FlipToBevy => self.anchor(anchor, origin),
+            YAxis::KeepTiled => self.anchor(
+                anchor,
+                origin * Transform::from_scale(Vec3::new(1.0, -1.0, 1.0)),
+            ),
+        }
+    }
+
+    /// Orientation of the map's grid, to pick the projection of tile coordinates.
+    pub fn orientation(&self) -> MapOrientation {
+        self.map.orientation.into()
+    }
+
+    /// Size of the map's grid cells in pixels, tilesets may have larger tiles.
+    pub fn tile_size(&self) -> Vec2 {
+        self.tile_size
+    }
+
+    /// Names of the tile layers, in drawing order, hidden layers included.
+    pub fn layer_names(&self) -> impl Iterator<Item = &str> {
+        self.layers.iter().map(|layer| layer.name.as_str())
+    }
+
+    /// Names of the object groups, in drawing order.
+    pub fn object_group_names(&self) -> impl Iterator<Item = &str> {
+        self.groups.iter().map(|group| group.name.as_str())
+    }
+
+    pub fn tileset_count(&self) -> usize {
+        self.map.tilesets.len()
+    }
+
+    pub fn tileset_names(&self) -> impl Iterator<Item = &str> {
+        self.map
+            .tilesets
+            .iter()
+            .map(|tileset| tileset.name.as_str())
+    }
+
+    /// Takes the chunk meshes of the visible layers, as (index in `layers`, tileset first gid, chunk origin, mesh),
+    /// for rendering the map without spawning it. Mesh positions are relative to the chunk origin, which is in
+    /// layer space, see `LayerChunk::origin`. When they were already taken, by an earlier call or by
+    /// spawning the map, they are built again from the layers.
+    #[cfg(not(feature = "headless"))]
+    pub fn take_meshes(&mut self) -> Vec<(u32, u32, Vec2, Mesh)> {
+        if self.meshes.is_empty() {
+            build_chunk_meshes(&self.layers)
+        } else {
+            std::mem::take(&mut self.meshes)
+        }
+    }
+
+    /// Path of a tileset image within the asset folder, as it is loaded with the asset server.
+    pub fn image_path(&self, image: &tiled::Image) -> PathBuf {
+        let path = self.image_folder.join(image.source.as_str());
+        normalize_asset_path(&path).unwrap_or(path)
+    }
+
+    /// Counts of what the map contains, to see what makes a map slow to load or draw.
+    pub fn stats(&self) -> MapStats {
+        let mut stats = MapStats {
+            layer_count: self.layers.len(),
+            tileset_count: self.tileset_count(),
+            object_count: self.groups.iter().map(|group| group.objects.len()).sum(),
+            ..Default::default()
+        };
+        for layer in self.layers.iter() {
+            let tiles = layer
+                .tileset_layers
+                .iter()
+                .flat_map(|tileset_layer| {
+                    tileset_layer
+                        .chunks
+                        .iter()
+                        .flatten()
+                        .flat_map(|chunk| chunk.tiles.iter().flatten())
+                        .filter(move |tile| tile.tile_id >= tileset_layer.tileset_guid)
+                })
+                .count();
+            stats.tile_count += tiles;
+            if layer.visible {
+                stats.visible_tile_count += tiles;
+            }
+        }
+        stats.mesh_vertex_count = self.mesh_vertex_count;
+        stats
+    }
+
+    /// Returns the first object with the given name across all object groups.
+    pub fn object_by_name(&self, name: &str) -> Option<&Object> {
+        self.groups.iter().find_map(|g| g.object_by_name(name))
+    }
+
+    pub fn object_by_id(&self, id: u32) -> Option<&Object> {
+        self.groups
+            .iter()
+            .flat_map(|g| g.objects.iter())
+            .find(|o| o.id == id)
+    }
+
+    pub fn objects_by_type<'a>(&'a self, obj_type: &'a str) -> impl Iterator<Item = &'a Object> {
+        self.groups
+            .iter()
+            .flat_map(move |g| g.objects_by_type(obj_type))
+    }
+
+    /// Objects whose world-space bounds intersect `rect`, none on maps that aren't orthogonal.
+    /// `map_transform` is the transform the map is spawned with, i.e. after `Map::anchor`.
+    pub fn objects_in_rect(&self, rect: Rect<f32>, map_transform: &Transform) -> Vec<&Object> {
+        let intersects = |object: &&Object| {
+            object
+                .world_bounds(&self.map, map_transform)
+                .map_or(false, |bounds| {
+                    bounds.left <= rect.right
+                        && bounds.right >= rect.left
+                        && bounds.bottom <= rect.top
+                        && bounds.top >= rect.bottom
+                })
+        };
+        // the index is axis aligned in map space, rotated maps check every object
+        if map_transform.rotation != Quat::IDENTITY {
+            return self
+                .groups
+                .iter()
+                .flat_map(|g| g.objects.iter())
+                .filter(intersects)
+                .collect();
+        }
+        let to_map = map_transform.compute_matrix().inverse();
+        let a = to_map
+            .transform_point3(Vec3::new(rect.left, rect.bottom, 0.0))
+            .truncate();
+        let b = to_map
+            .transform_point3(Vec3::new(rect.right, rect.top, 0.0))
+            .truncate();
+        let map_rect = Rect {
+            left: a.x.min(b.x),
+            right: a.x.max(b.x),
+            top: a.y.max(b.y),
+            bottom: a.y.min(b.y),
+        };
+        self.object_index
+            .candidates(&map_rect)
+            .into_iter()
+            .map(|(group_index, object_index)| &self.groups[group_index].objects[object_index])
+            .filter(intersects)
+            .collect()
+    }
+
+    /// World-space bounding box of all the map's tiles, infinite layers included.
+    /// `map_transform` is the transform the map is spawned with, i.e. after `Map::anchor`.
+    /// World y points up, so `top` is the larger y.
+    pub fn bounds(&self, map_transform: &Transform) -> Rect<f32> {
+        let (min, max) = self.tile_extent();
+        let (min, max) = (min.as_f32(), max.as_f32());
+        let tile_size = Vec2::new(self.map.tile_width as f32, self.map.tile_height as f32);
+
+        // corners of the tiles in map space, iso tiles are diamonds below their projected point
+        let tile_corners = [
+            Vec2::new(min.x, min.y),
+            Vec2::new(max.x, min.y),
+            Vec2::new(min.x, max.y),
+            Vec2::new(max.x, max.y),
+        ];
+        let corners = match (&self.map.orientation, &self.hex_layout) {
+            (tiled::Orientation::Isometric, _) => tile_corners
+                .iter()
+                .map(|corner| project_iso(*corner, tile_size.x, tile_size.y))
+                .collect::<Vec<_>>(),
+            (tiled::Orientation::Hexagonal, Some(layout)) => {
+                // staggered cells stick out of the rectangle, so include a neighbour of each corner
+                let cells = |min: f32, max: f32| {
+                    let last = (max - 1.0).max(min);
+                    [min, (min + 1.0).min(last), (last - 1.0).max(min), last]
+                };
+                let mut corners = Vec::new();
+                for x in cells(min.x, max.x).iter() {
+                    for y in cells(min.y, max.y).iter() {
+                        let top_left = project_hex(Vec2::new(*x, *y), layout);
+                        corners.push(top_left);
+                        corners.push(top_left + Vec2::new(tile_size.x, -tile_size.y));
+                    }
+                }
+                corners
+            }
+            _ => tile_corners
+                .iter()
+                .map(|corner| project_ortho(*corner, tile_size.x, tile_size.y))
+                .collect(),
+        };
+
+        let matrix = map_transform.compute_matrix();
+        let mut world_min = Vec2::new(f32::MAX, f32::MAX);
+        let mut world_max = Vec2::new(f32::MIN, f32::MIN);
+        for corner in corners.iter() {
+            let world = matrix.transform_point3(corner.extend(0.0)).truncate();
+            world_min = world_min.min(world);
+            world_max = world_max.max(world);
+        }
+        Rect {
+            left: world_min.x,
+            right: world_max.x,
+            top: world_max.y,
+            bottom: world_min.y,
+        }
+    }
+
+    // first tile and the tile past the last one covered by the layers, the map size without layers
+    fn tile_extent(&self) -> (IVec2, IVec2) {
+        let mut min = IVec2::new(i32::MAX, i32::MAX);
+        let mut max = IVec2::new(i32::MIN, i32::MIN);
+        for layer in self.map.layers.iter() {
+            let (origin, size) = layer_tile_bounds(&self.map, layer);
+            min = min.min(origin);
+            max = max.max(origin + size);
+        }
+        if min.x > max.x {
+            min = IVec2::ZERO;
+            max = IVec2::new(self.map.width as i32, self.map.height as i32);
+        }
+        (min, max)
+    }
+
+    /// Line mesh of the outlines of the map's cells, in the space of the map content: squares on orthogonal maps,
+    /// diamonds on isometric maps and hexagons on hexagonal maps. Drawn over maps by `DebugConfig::grid`.
+    #[cfg(not(feature = "headless"))]
+    pub fn grid_mesh(&self) -> Mesh {
+        let (min, max) = self.tile_extent();
+        let tile_size = Vec2::new(self.map.tile_width as f32, self.map.tile_height as f32);
+        let mut lines: Vec<(Vec2, Vec2)> = Vec::new();
+        match (&self.map.orientation, &self.hex_layout) {
+            (tiled::Orientation::Hexagonal, Some(layout)) => {
+                let corners = layout.corners();
+                for x in min.x..max.x {
+                    for y in min.y..max.y {
+                        let top_left = project_hex(Vec2::new(x as f32, y as f32), layout);
+                        let corner = |i: usize| {
+                            let corner = corners[i % corners.len()];
+                            top_left + Vec2::new(corner.x, -corner.y)
+                        };
+                        for i in 0..corners.len() {
+                            lines.push((corner(i), corner(i + 1)));
+                        }
+                    }
+                }
+            }
+            (orientation, _) => {
+                // grid lines run along both axes of the map, projected to squares or diamonds
+                let project = |x: i32, y: i32| match orientation {
+                    tiled::Orientation::Isometric => {
+                        project_iso(Vec2::new(x as f32, y as f32), tile_size.x, tile_size.y)
+                    }
+                    _ => project_ortho(Vec2::new(x as f32, y as f32), tile_size.x, tile_size.y),
+                };
+                for x in min.x..=max.x {
+                    lines.push((project(x, min.y), project(x, max.y)));
+                }
+                for y in min.y..=max.y {
+                    lines.push((project(min.x, y), project(max.x, y)));
+                }
+            }
+        }
+
+        let positions: Vec<[f32; 3]> = lines
+            .iter()
+            .flat_map(|(start, end)| vec![[start.x, start.y, 0.0], [end.x, end.y, 0.0]])
+            .collect();
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+        let colors = vec![[1.0; 4]; positions.len()];
+        let indices = compact_indices((0..positions.len() as u32).collect(), positions.len());
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        mesh.set_attribute("Vertex_Position", VertexAttributeValues::Float3(positions));
+        mesh.set_attribute("Vertex_Uv", VertexAttributeValues::Float2(uvs));
+        mesh.set_attribute("Vertex_Color", VertexAttributeValues::Float4(colors));
+        mesh.set_indices(Some(indices));
+        mesh
+    }
+
+    /// Index of the tileset a gid belongs to, and the column and row of its tile in the tileset image.
+    /// Flip flags in the gid are ignored.
+    pub fn tile_atlas_cell(&self, gid: u32) -> Option<(u32, u32, u32)> {
+        let gid = TiledMapLoader::remove_tile_flags(gid);
+        let (index, tileset) = self.map.tilesets.iter().enumerate().find(|(_, tileset)| {
+            gid >= tileset.first_gid && gid < tileset.first_gid + tile_count(tileset)
+        })?;
+        let (column, row) = atlas_cell(tileset, gid - tileset.first_gid);
+        Some((index as u32, column, row))
+    }
+
+    /// Collision objects drawn on the tile with the given gid in Tiled's tileset editor.
+    /// Positions are relative to the tile's top left corner.
+    pub fn tile_collision_shapes(&self, gid: u32) -> &[Object] {
+        self.tile_collisions
+            .get(&gid)
+            .map(|objects| objects.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The gid at `tile` in the given layer, `None` when outside the layer.
+    /// Gid 0 means the cell is empty.
+    pub fn tile_gid(&self, layer: LayerId, tile: IVec2) -> Option<u32> {
+        self.layer_tile(layer, tile)
+            .map(|layer_tile| layer_tile.gid)
+    }
+
+    /// How the tile at `tile` in the given layer is flipped, `None` when outside the layer.
+    pub fn tile_flip(&self, layer: LayerId, tile: IVec2) -> Option<TileFlip> {
+        self.layer_tile(layer, tile).map(TileFlip::from)
+    }
+
+    fn layer_tile(&self, layer: LayerId, tile: IVec2) -> Option<&tiled::LayerTile> {
+        let layer = self.map.layers.get(layer.0)?;
+        match &layer.tiles {
+            tiled::LayerData::Finite(tiles) => {
+                if tile.x < 0 || tile.y < 0 {
+                    return None;
+                }
+                tiles
+                    .get(tile.y as usize)
+                    .and_then(|row| row.get(tile.x as usize))
+            }
+            tiled::LayerData::Infinite(chunks) => chunks.values().find_map(|chunk| {
+                let x = tile.x - chunk.x;
+                let y = tile.y - chunk.y;
+                if x < 0 || y < 0 || x >= chunk.width as i32 || y >= chunk.height as i32 {
+                    return None;
+                }
+                Some(&chunk.tiles[y as usize][x as usize])
+            }),
+        }
+    }
+
+    /// Coordinates and gids of every non-empty tile in the given layer, read from the layer data as iterated.
+    pub fn iter_tiles(&self, layer: LayerId) -> impl Iterator<Item = (IVec2, u32)> + '_ {
+        let (rows, chunks) = match self.map.layers.get(layer.0).map(|layer| &layer.tiles) {
+            Some(tiled::LayerData::Finite(rows)) => (Some(rows), None),
+            Some(tiled::LayerData::Infinite(chunks)) => (None, Some(chunks)),
+            None => (None, None),
+        };
+        let finite = rows.into_iter().flat_map(|rows| {
+            rows.iter().enumerate().flat_map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(move |(x, tile)| (IVec2::new(x as i32, y as i32), tile.gid))
+            })
+        });
+        let infinite = chunks.into_iter().flat_map(|chunks| {
+            chunks.values().flat_map(|chunk| {
+                chunk.tiles.iter().enumerate().flat_map(move |(y, row)| {
+                    row.iter().enumerate().map(move |(x, tile)| {
+                        (IVec2::new(chunk.x + x as i32, chunk.y + y as i32), tile.gid)
+                    })
+                })
+            })
+        });
+        finite.chain(infinite).filter(|(_, gid)| *gid != 0)
+    }
+
+    /// All tiles 4-connected to `start` that share its gid, including `start` itself.
+    pub fn flood_region(&self, layer: LayerId, start: IVec2) -> HashSet<IVec2> {
+        let mut region = HashSet::default();
+        let gid = match self.tile_gid(layer, start) {
+            Some(gid) => gid,
+            None => return region,
+        };
+
+        let mut open = vec![start];
+        region.insert(start);
+        while let Some(tile) = open.pop() {
+            for offset in [
+                IVec2::new(1, 0),
+                IVec2::new(-1, 0),
+                IVec2::new(0, 1),
+                IVec2::new(0, -1),
+            ]
+            .iter()
+            {
+                let neighbor = tile + *offset;
+                if !region.contains(&neighbor) && self.tile_gid(layer, neighbor) == Some(gid) {
+                    region.insert(neighbor);
+                    open.push(neighbor);
+                }
+            }
+        }
+        region
+    }
+
+    /// World-space rectangles covering the tiles of a layer on orthogonal maps, as colliders for layers where every
+    /// tile is a solid cell. With `merge`, neighbouring tiles are merged into larger rectangles, greedily along rows
+    /// then down, so a wall becomes a few rectangles instead of one per tile. Empty for other orientations.
+    /// `map_transform` is the transform the map is spawned with, i.e. after `Map::anchor`.
+    pub fn solid_tile_rects(
+        &self,
+        layer: LayerId,
+        merge: bool,
+        map_transform: &Transform,
+    ) -> Vec<Rect<f32>> {
+        if self.map.orientation != tiled::Orientation::Orthogonal {
+            return Vec::new();
+        }
+        let mut tiles: Vec<IVec2> = self.iter_tiles(layer).map(|(tile, _)| tile).collect();
+        tiles.sort_by_key(|tile| (tile.y, tile.x));
+        let mut free: HashSet<IVec2> = tiles.iter().copied().collect();
+
+        // top left tile and size in tiles of each rectangle
+        let mut cells = Vec::new();
+        for tile in tiles {
+            if !free.remove(&tile) {
+                continue;
+            }
+            let mut size = IVec2::new(1, 1);
+            if merge {
+                while free.remove(&(tile + IVec2::new(size.x, 0))) {
+                    size.x += 1;
+                }
+                loop {
+                    let row = tile.y + size.y;
+                    if !(tile.x..tile.x + size.x).all(|x| free.contains(&IVec2::new(x, row))) {
+                        break;
+                    }
+                    for x in tile.x..tile.x + size.x {
+                        free.remove(&IVec2::new(x, row));
+                    }
+                    size.y += 1;
+                }
+            }
+            cells.push((tile, size));
+        }
+
+        let tile_size = Vec2::new(self.map.tile_width as f32, self.map.tile_height as f32);
+        let matrix = map_transform.compute_matrix();
+        cells
+            .into_iter()
+            .map(|(tile, size)| {
+                let to_world = |tile: IVec2| {
+                    let local = project_ortho(tile.as_f32(), tile_size.x, tile_size.y);
+                    matrix.transform_point3(local.extend(0.0)).truncate()
+                };
+                let (a, b) = (to_world(tile), to_world(tile + size));
+                Rect {
+                    left: a.x.min(b.x),
+                    right: a.x.max(b.x),
+                    top: a.y.max(b.y),
+                    bottom: a.y.min(b.y),
+                }
+            })
+            .collect()
+    }
+
+    /// Custom properties of the tile with the given gid, if it has any.
+    /// Combine with `PropertiesExt` for typed access.
+    pub fn tile_properties(&self, gid: u32) -> Option<&Properties> {
+        let tileset = self.map.get_tileset_by_gid(gid)?;
+        let tile_id = gid - tileset.first_gid;
+        tileset
+            .tiles
+            .iter()
+            .find(|tile| tile.id == tile_id)
+            .map(|tile| &tile.properties)
+    }
+
+    /// Probability set on the tile in Tiled for its random mode, 1.0 when the tile has none.
+    pub fn tile_probability(&self, gid: u32) -> f32 {
+        self.map
+            .get_tileset_by_gid(gid)
+            .and_then(|tileset| {
+                let tile_id = gid - tileset.first_gid;
+                tileset.tiles.iter().find(|tile| tile.id == tile_id)
+            })
+            .map_or(1.0, |tile| tile.probability)
+    }
+
+    /// Picks one of `gids` weighted by their probabilities, `roll` being a random number in `0.0..1.0`.
+    /// `None` when `gids` is empty or all weights are zero.
+    pub fn pick_tile_by_probability(&self, gids: &[u32], roll: f32) -> Option<u32> {
+        let total: f32 = gids.iter().map(|gid| self.tile_probability(*gid)).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut remaining = roll * total;
+        for gid in gids {
+            let probability = self.tile_probability(*gid);
+            if remaining < probability {
+                return Some(*gid);
+            }
+            remaining -= probability;
+        }
+        // rounding can leave a little over, it belongs to the last weighted tile
+        gids.iter()
+            .rev()
+            .find(|gid| self.tile_probability(**gid) > 0.0)
+            .copied()
+    }
+
+    /// Tile at a world position, `None` outside the map or for unsupported orientations.
+    /// `map_transform` is the transform the map is spawned with, i.e. after `Map::anchor`.
+    pub fn world_to_tile(&self, world_pos: Vec2, map_transform: &Transform) -> Option<IVec2> {
+        let local = map_transform
+            .compute_matrix()
+            .inverse()
+            .transform_point3(world_pos.extend(0.0))
+            .truncate();
+        let tile_width = self.map.tile_width as f32;
+        let tile_height = self.map.tile_height as f32;
+        let tile = match self.map.orientation {
+            tiled::Orientation::Orthogonal => {
+                unproject_ortho(local, tile_width, tile_height).floor()
+            }
+            // unproject_iso rounds to the nearest top corner, shift to the diamond center first
+            tiled::Orientation::Isometric => unproject_iso(
+                local + Vec2::new(0.0, tile_height / 2.0),
+                tile_width,
+                tile_height,
+            ),
+            tiled::Orientation::Hexagonal => unproject_hex(local, self.hex_layout.as_ref()?),
+            _ => return None,
+        };
+        let tile = IVec2::new(tile.x as i32, tile.y as i32);
+        if !self.contains_tile(tile) {
+            return None;
+        }
+        Some(tile)
+    }
+
+    /// Whether the tile is inside the map, always true for infinite maps.
+    pub fn contains_tile(&self, tile: IVec2) -> bool {
+        self.map.infinite
+            || (tile.x >= 0
+                && tile.y >= 0
+                && tile.x < self.map.width as i32
+                && tile.y < self.map.height as i32)
+    }
+
+    /// In-bounds neighbors of a tile: 4, or 8 with `diagonal`, on orthogonal and isometric maps,
+    /// and the 6 adjacent hexagons on hexagonal maps, where `diagonal` is ignored.
+    /// Empty for staggered maps.
+    pub fn neighbors(&self, tile: IVec2, diagonal: bool) -> Vec<IVec2> {
+        let offsets: &[(i32, i32)] = match self.map.orientation {
+            tiled::Orientation::Orthogonal | tiled::Orientation::Isometric => {
+                if diagonal {
+                    &[
+                        (1, 0),
+                        (-1, 0),
+                        (0, 1),
+                        (0, -1),
+                        (1, 1),
+                        (1, -1),
+                        (-1, 1),
+                        (-1, -1),
+                    ]
+                } else {
+                    &[(1, 0), (-1, 0), (0, 1), (0, -1)]
+                }
+            }
+            tiled::Orientation::Hexagonal => match &self.hex_layout {
+                Some(layout) => match layout.stagger_axis {
+                    // shifted rows touch the next column above and below, others the previous one
+                    StaggerAxis::Y if layout.is_staggered(tile.y) => {
+                        &[(-1, 0), (1, 0), (0, -1), (1, -1), (0, 1), (1, 1)]
+                    }
+                    StaggerAxis::Y => &[(-1, 0), (1, 0), (-1, -1), (0, -1), (-1, 1), (0, 1)],
+                    StaggerAxis::X if layout.is_staggered(tile.x) => {
+                        &[(0, -1), (0, 1), (-1, 0), (-1, 1), (1, 0), (1, 1)]
+                    }
+                    StaggerAxis::X => &[(0, -1), (0, 1), (-1, -1), (-1, 0), (1, -1), (1, 0)],
+                },
+                None => &[],
+            },
+            tiled::Orientation::Staggered => &[],
+        };
+        offsets
+            .iter()
+            .map(|(x, y)| tile + IVec2::new(*x, *y))
+            .filter(|neighbor| self.contains_tile(*neighbor))
+            .collect()
+    }
+
+    /// World position of the center of a tile, `None` for unsupported orientations.
+    pub fn tile_to_world(&self, tile: IVec2, map_transform: &Transform) -> Option<Vec2> {
+        let tile_width = self.map.tile_width as f32;
+        let tile_height = self.map.tile_height as f32;
+        let tile = Vec2::new(tile.x as f32, tile.y as f32);
+        let local = match self.map.orientation {
+            tiled::Orientation::Orthogonal => {
+                project_ortho(tile + Vec2::splat(0.5), tile_width, tile_height)
+            }
             tiled::Orientation::Isometric => {
-                let center = project_iso(map_center, tile_size.x, tile_size.y);
-                Transform::from_matrix(
-                    origin.compute_matrix() * Mat4::from_translation(-center.extend(0.0)),
-                )
+                project_iso(tile, tile_width, tile_height) - Vec2::new(0.0, tile_height / 2.0)
             }
-            _ => panic!("Unsupported orientation {:?}", self.map.orientation),
+            tiled::Orientation::Hexagonal => {
+                project_hex(tile, self.hex_layout.as_ref()?)
+                    + Vec2::new(tile_width / 2.0, -tile_height / 2.0)
+            }
+            _ => return None,
+        };
+        Some(map_transform.mul_vec3(local.extend(0.0)).truncate())
+    }
+
+    /// Tile under a window position such as the cursor, seen through the given camera.
+    #[cfg(not(feature = "headless"))]
+    pub fn cursor_to_tile(
+        &self,
+        cursor_pos: Vec2,
+        window: &Window,
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        map_transform: &Transform,
+    ) -> Option<IVec2> {
+        let world_pos = screen_to_world(cursor_pos, window, camera, camera_transform);
+        self.world_to_tile(world_pos, map_transform)
+    }
+
+    /// Grid over the map where a cell is solid when its tile in the given layer
+    /// has the bool property `solid_property` set to true.
+    pub fn collision_grid(&self, layer: LayerId, solid_property: &str) -> Grid<bool> {
+        let mut grid = Grid::new(self.map.width, self.map.height, false);
+        for (tile, gid) in self.iter_tiles(layer) {
+            let solid = self
+                .tile_properties(gid)
+                .and_then(|properties| properties.get_bool(solid_property))
+                .unwrap_or(false);
+            grid.set(tile, solid);
         }
+        grid
     }
 
+    /// Grid over the map where a cell is solid when the given layer has any tile there.
+    pub fn occupancy_grid(&self, layer: LayerId) -> Grid<bool> {
+        let mut grid = Grid::new(self.map.width, self.map.height, false);
+        for (tile, _) in self.iter_tiles(layer) {
+            grid.set(tile, true);
+        }
+        grid
+    }
+
+    /// Writes the current state of the parsed map back to Tiled's .tmx XML format.
+    pub fn to_tmx_string(&self) -> Result<String> {
+        crate::writer::write_tmx(&self.map)
+    }
+
+    /// Reads and parses the map at `asset_path` in `asset_folder` like the asset loader does, but without an
+    /// app, for tools and custom pipelines. Run it on a task pool to parse maps in the background,
+    /// e.g. `task_pool.spawn(Map::load_async("assets", "ortho-map.tmx"))`.
+    /// External tilesets and templates are read along with the map; tileset images are only listed,
+    /// in `asset_dependencies`.
+    #[cfg(all(not(target_arch = "wasm32"), not(target_os = "android")))]
+    pub fn load_async(
+        asset_folder: impl AsRef<Path>,
+        asset_path: impl AsRef<Path>,
+    ) -> impl Future<Output = Result<Map>> + Send {
+        let asset_folder = asset_folder.as_ref().to_path_buf();
+        let asset_path = asset_path.as_ref().to_path_buf();
+        async move {
+            let asset_io = FileAssetIo::new(&asset_folder);
+            let bytes = asset_io.load_path(&asset_path).await?;
+            let document = TmxDocument::parse(&bytes)?;
+            let mut files = HashMap::default();
+            for file in document.referenced_files(&asset_path) {
+                if let Ok(file_bytes) = asset_io.load_path(&file).await {
+                    files.insert(file, file_bytes);
+                }
+            }
+            Map::from_document(&asset_path, bytes, document, &files)
+        }
+    }
+
+    /// Parses the map at `asset_path` in `asset_folder` from its bytes, reading its external tilesets and
+    /// templates from the file system.
     pub fn try_from_bytes(asset_folder: &Path, asset_path: &Path, bytes: Vec<u8>) -> Result<Map> {
         #[cfg(all(not(target_arch = "wasm32"), not(target_os = "android")))]
         let root_dir = bevy::asset::FileAssetIo::get_root_path();
         #[cfg(any(target_arch = "wasm32", target_os = "android"))]
         let root_dir = PathBuf::from("");
 
-        let map = tiled::parse_with_path(
-            BufReader::new(bytes.as_slice()),
-            &root_dir.join(&asset_folder.join(asset_path)),
-        )?;
+        let document = TmxDocument::parse(&bytes)?;
+        let files = document
+            .referenced_files(asset_path)
+            .into_iter()
+            .filter_map(|file| {
+                let file_bytes = std::fs::read(root_dir.join(asset_folder).join(&file)).ok()?;
+                Some((file, file_bytes))
+            })
+            .collect();
+        Map::from_document(asset_path, bytes, document, &files)
+    }
+
+    /// Builds the map from its parsed XML and the files it references by asset path, see
+    /// `TmxDocument::referenced_files`. A missing external tileset fails the load, a missing template is
+    /// skipped with a warning.
+    pub(crate) fn from_document(
+        asset_path: &Path,
+        bytes: Vec<u8>,
+        mut document: TmxDocument,
+        files: &HashMap<PathBuf, Vec<u8>>,
+    ) -> Result<Map> {
+        let xml = document.embed_tilesets(&bytes, asset_path, files)?;
+        let mut map = tiled::parse(BufReader::new(xml.as_ref()))?;
+        // tiles are cut from a single image per tileset
+        let tileset_origins = split_tileset_images(&mut map)?;
+        let hex_layout = match map.orientation {
+            tiled::Orientation::Hexagonal => Some(document.hex_layout(&map).ok_or_else(
+                || anyhow!("Hexagonal map without a valid hexsidelength, it is needed to lay out the tiles"),
+            )?),
+            _ => None,
+        };
+        let mut alignments = document.tileset_alignments();
+        let mut tile_offsets = document.tileset_offsets();
+        let mut grids = document.tileset_grids();
+        // split tilesets share the settings of the tileset they come from
+        for (first_gid, origin) in tileset_origins.iter() {
+            if let Some(alignment) = alignments.get(origin).copied() {
+                alignments.insert(*first_gid, alignment);
+            }
+            if let Some(offset) = tile_offsets.get(origin).copied() {
+                tile_offsets.insert(*first_gid, offset);
+            }
+            if let Some(grid) = grids.get(origin).copied() {
+                grids.insert(*first_gid, grid);
+            }
+        }
 
-        let mut layers = Vec::new();
-        let mut groups = Vec::new();
+        let mut layers = Vec::with_capacity(map.layers.len());
+        let mut groups = Vec::with_capacity(map.object_groups.len());
 
         // this only works if gids are uniques across all maps used - todo move into ObjectGroup?
         let mut tile_gids: HashMap<u32, u32> = Default::default();
 
-        for tileset in &map.tilesets {
-            for i in tileset.first_gid..(tileset.first_gid + tileset.tilecount.unwrap_or(1)) {
+        for (index, tileset) in map.tilesets.iter().enumerate() {
+            // tilesets are ordered by first gid, a tileset's range ends where the next one starts
+            let next_first_gid = map
+                .tilesets
+                .get(index + 1)
+                .map_or(u32::MAX, |next| next.first_gid);
+            let last_gid = (tileset.first_gid + tile_count(tileset)).min(next_first_gid);
+            for i in tileset.first_gid..last_gid {
                 tile_gids.insert(i, tileset.first_gid);
             }
         }
@@ -79,406 +852,445 @@ impl Map {
         let mut object_gids: HashSet<u32> = Default::default();
         for object_group in map.object_groups.iter() {
             // recursively creates objects in the groups:
-            let tiled_o_g = ObjectGroup::new_with_tile_ids(object_group, &tile_gids);
+            groups.push(ObjectGroup::new_with_tile_ids(object_group, &tile_gids));
+        }
+        let template_paths = apply_templates(&document, asset_path, files, &mut groups, &tile_gids);
+        for tiled_o_g in groups.iter_mut() {
+            if let Some(class) = document.layer_classes.get(&tiled_o_g.layer_index) {
+                tiled_o_g.class = class.clone();
+            }
+            if let Some(draw_order) = document.draw_orders.get(&tiled_o_g.layer_index) {
+                tiled_o_g.draw_order = *draw_order;
+            }
+            for object in tiled_o_g.objects.iter_mut() {
+                object.draw_order = tiled_o_g.draw_order;
+                if let Some(alignment) = object
+                    .tileset_gid
+                    .and_then(|first_gid| alignments.get(&first_gid))
+                {
+                    object.alignment = *alignment;
+                }
+                object.grid = object
+                    .tileset_gid
+                    .and_then(|first_gid| grids.get(&first_gid))
+                    .copied();
+                if let Some(class) = document.object_classes.remove(&object.id) {
+                    // Tiled 1.9 writes the object type as its class
+                    if object.obj_type.is_empty() {
+                        object.obj_type = class.clone();
+                    }
+                    object.class = class;
+                }
+            }
             // keep track of which objects will need to have tiles loaded
             tiled_o_g.objects.iter().for_each(|o| {
                 tile_gids.get(&o.gid).map(|first_gid| {
                     object_gids.insert(*first_gid);
                 });
             });
-            groups.push(tiled_o_g);
+        }
+
+        let mut tile_collisions = HashMap::default();
+        for tileset in map.tilesets.iter() {
+            for tile in tileset.tiles.iter() {
+                if let Some(object_group) = &tile.objectgroup {
+                    let objects = object_group.objects.iter().map(Object::new).collect();
+                    tile_collisions.insert(tileset.first_gid + tile.id, objects);
+                }
+            }
         }
 
         let tile_size = Vec2::new(map.tile_width as f32, map.tile_height as f32);
         let image_folder: PathBuf = asset_path.parent().unwrap().into();
         let mut asset_dependencies = Vec::new();
-
-        for layer in map.layers.iter() {
-            if !layer.visible {
-                continue;
+        // the asset server only loads files within the asset folder, on wasm and android in particular
+        for tileset in map.tilesets.iter() {
+            let source = tileset.images.first().unwrap().source.as_str();
+            let image_path = normalize_asset_path(&image_folder.join(source)).ok_or_else(|| {
+                anyhow!(
+                    "Image \"{}\" of tileset \"{}\" is outside the asset folder, keep tileset images within it",
+                    source,
+                    tileset.name
+                )
+            })?;
+            if !asset_dependencies.contains(&image_path) {
+                asset_dependencies.push(image_path);
             }
-            let mut tileset_layers = Vec::new();
+        }
 
-            for tileset in map.tilesets.iter() {
-                let tile_path = image_folder.join(tileset.images.first().unwrap().source.as_str());
-                asset_dependencies.push(tile_path);
+        for (tiled_layer, layer) in map.layers.iter().enumerate() {
+            let mut tileset_layers = Vec::with_capacity(map.tilesets.len());
 
-                tileset_layers.push(TilesetLayer::new(&map, &layer, &tileset));
+            for tileset in map.tilesets.iter() {
+                tileset_layers.push(TilesetLayer::new(
+                    &map,
+                    &layer,
+                    &tileset,
+                    hex_layout.as_ref(),
+                    tile_offsets
+                        .get(&tileset.first_gid)
+                        .copied()
+                        .unwrap_or_default(),
+                ));
             }
 
-            let layer = MapLayer { tileset_layers };
+            let layer = MapLayer {
+                tileset_layers,
+                layer_index: layer.layer_index,
+                tiled_layer,
+                name: layer.name.clone(),
+                visible: layer.visible,
+                class: document
+                    .layer_classes
+                    .get(&layer.layer_index)
+                    .cloned()
+                    .unwrap_or_default(),
+                properties: layer.properties.clone(),
+            };
             layers.push(layer);
         }
 
-        let mut meshes = Vec::new();
-        for (layer_id, layer) in layers.iter().enumerate() {
-            for tileset_layer in layer.tileset_layers.iter() {
-                for x in 0..tileset_layer.chunks.len() {
-                    let chunk_x = &tileset_layer.chunks[x];
-                    for y in 0..chunk_x.len() {
-                        if let Some(mesh) = chunk_x[y].build_uv_mesh(tileset_layer.tileset_guid) {
-                            meshes.push((layer_id as u32, tileset_layer.tileset_guid, mesh));
-                        };
-                    }
-                }
-            }
-        }
+        #[cfg(not(feature = "headless"))]
+        let meshes = build_chunk_meshes(&layers);
+        #[cfg(not(feature = "headless"))]
+        let mesh_vertex_count = meshes
+            .iter()
+            .map(|(_, _, _, mesh)| mesh.count_vertices())
+            .sum();
+        #[cfg(feature = "headless")]
+        let mesh_vertex_count = 0;
 
+        let object_index = ObjectIndex::new(&map, &groups);
+        #[cfg(not(feature = "headless"))]
+        let geometry_hash = geometry_hash(&map, &bytes)?;
         let map = Map {
             map,
+            #[cfg(not(feature = "headless"))]
             meshes,
+            mesh_vertex_count,
+            #[cfg(not(feature = "headless"))]
+            geometry_hash,
             layers,
             groups,
+            object_index,
+            tile_collisions,
             tile_size,
+            hex_layout,
+            class: document.class(),
             image_folder,
             asset_dependencies,
+            template_paths,
         };
 
         Ok(map)
     }
 }
 
-#[derive(Default)]
-pub struct TiledMapCenter(pub bool);
-
-pub struct MapRoot; // used so consuming application can query for parent
-
-pub struct DebugConfig {
-    pub enabled: bool,
-    pub material: Option<Handle<ColorMaterial>>,
+// The map file covers what is read from its XML directly, such as classes and tile offsets, and the written
+// map covers external tilesets. Neither changes when only a tileset image does.
+#[cfg(not(feature = "headless"))]
+fn geometry_hash(map: &tiled::Map, bytes: &[u8]) -> Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    crate::writer::write_tmx(map)?.hash(&mut hasher);
+    Ok(hasher.finish())
 }
 
-impl Default for DebugConfig {
-    fn default() -> Self {
-        Self {
-            enabled: false,
-            material: Default::default(),
+// chunk meshes of the visible layers, with their layer and tileset
+#[cfg(not(feature = "headless"))]
+fn build_chunk_meshes(layers: &[MapLayer]) -> Vec<(u32, u32, Vec2, Mesh)> {
+    let mut meshes = Vec::new();
+    for (layer_id, layer) in layers.iter().enumerate() {
+        // hidden layers are only meshed when spawned with LoadHiddenLayers
+        if !layer.visible {
+            continue;
+        }
+        for tileset_layer in layer.tileset_layers.iter() {
+            for x in 0..tileset_layer.chunks.len() {
+                let chunk_x = &tileset_layer.chunks[x];
+                for y in 0..chunk_x.len() {
+                    if let Some(mesh) = chunk_x[y].build_uv_mesh(tileset_layer.tileset_guid) {
+                        meshes.push((
+                            layer_id as u32,
+                            tileset_layer.tileset_guid,
+                            chunk_x[y].origin(),
+                            mesh,
+                        ));
+                    };
+                }
+            }
         }
     }
+    meshes
 }
 
-/// A bundle of tiled map entities.
-#[derive(Bundle)]
-pub struct TiledMapBundle {
-    pub map_asset: Handle<Map>,
-    pub parent_option: Option<Entity>,
-    pub materials: HashMap<u32, Handle<ColorMaterial>>,
-    pub atlases: HashMap<u32, Handle<TextureAtlas>>,
-    pub origin: Transform,
-    pub center: TiledMapCenter,
-    pub debug_config: DebugConfig,
-    pub created_entities: CreatedMapEntities,
-}
-
-impl Default for TiledMapBundle {
-    fn default() -> Self {
-        Self {
-            map_asset: Handle::default(),
-            parent_option: None,
-            materials: HashMap::default(),
-            atlases: HashMap::default(),
-            center: TiledMapCenter::default(),
-            origin: Transform::default(),
-            debug_config: Default::default(),
-            created_entities: Default::default(),
+// path without `.` and `..` components, `None` when it is absolute or leaves the folder it is relative to
+pub(crate) fn normalize_asset_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(name) => normalized.push(name),
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            std::path::Component::RootDir | std::path::Component::Prefix(_) => return None,
         }
     }
+    Some(normalized)
 }
 
-#[derive(Default, Debug)]
-pub struct CreatedMapEntities {
-    // maps layer id and tileset_gid to mesh entities
-    created_layer_entities: HashMap<(usize, u32), Vec<Entity>>,
-    // maps object guid to texture atlas sprite entity
-    created_object_entities: HashMap<u32, Vec<Entity>>,
+/// Orientation of a map, `tiled::Orientation` without depending on the `tiled` crate version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapOrientation {
+    Orthogonal,
+    Isometric,
+    Staggered,
+    Hexagonal,
 }
 
-pub fn process_loaded_tile_maps(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut map_events: EventReader<AssetEvent<Map>>,
-    mut ready_events: EventWriter<ObjectReadyEvent>,
-    mut map_ready_events: EventWriter<MapReadyEvent>,
-    mut maps: ResMut<Assets<Map>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut query: Query<(
-        Entity,
-        &TiledMapCenter,
-        &Handle<Map>,
-        &Option<Entity>,
-        &mut HashMap<u32, Handle<ColorMaterial>>,
-        &mut HashMap<u32, Handle<TextureAtlas>>,
-        &Transform,
-        &mut DebugConfig,
-        &mut CreatedMapEntities,
-    )>,
-) {
-    let mut changed_maps = HashSet::<Handle<Map>>::default();
-    for event in map_events.iter() {
-        match event {
-            AssetEvent::Created { handle } => {
-                changed_maps.insert(handle.clone());
-            }
-            AssetEvent::Modified { handle } => {
-                changed_maps.insert(handle.clone());
-            }
-            AssetEvent::Removed { handle } => {
-                // if mesh was modified and removed in the same update, ignore the modification
-                // events are ordered so future modification events are ok
-                changed_maps.remove(handle);
-            }
+impl From<tiled::Orientation> for MapOrientation {
+    fn from(orientation: tiled::Orientation) -> Self {
+        match orientation {
+            tiled::Orientation::Orthogonal => MapOrientation::Orthogonal,
+            tiled::Orientation::Isometric => MapOrientation::Isometric,
+            tiled::Orientation::Staggered => MapOrientation::Staggered,
+            tiled::Orientation::Hexagonal => MapOrientation::Hexagonal,
         }
     }
+}
 
-    let mut new_meshes = HashMap::<&Handle<Map>, Vec<(u32, u32, Handle<Mesh>)>>::default();
-
-    for changed_map in changed_maps.iter() {
-        let map = maps.get_mut(changed_map).unwrap();
-
-        for (_, _, map_handle, _, mut materials_map, mut texture_atlas_map, _, _, _) in
-            query.iter_mut()
-        {
-            // only deal with currently changed map
-            if map_handle != changed_map {
-                continue;
-            }
+/// Centers the map on its origin when set, otherwise the top left of the map is at the origin.
+/// Superseded by `MapAnchor`, which it converts into.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct TiledMapCenter(pub bool);
 
-            for tileset in &map.map.tilesets {
-                if !materials_map.contains_key(&tileset.first_gid) {
-                    let texture_path = map
-                        .image_folder
-                        .join(tileset.images.first().unwrap().source.as_str());
-                    let texture_handle = asset_server.load(texture_path);
-                    materials_map.insert(
-                        tileset.first_gid,
-                        materials.add(texture_handle.clone().into()),
-                    );
-
-                    // only generate texture_atlas for tilesets used in objects
-                    let object_gids: Vec<_> = map
-                        .groups
-                        .iter()
-                        .flat_map(|og| og.objects.iter().map(|o| o.tileset_gid))
-                        .collect();
-                    if object_gids.contains(&Some(tileset.first_gid)) {
-                        // For simplicity use textureAtlasSprite for object layers
-                        // these insertions should be limited to sprites referenced by objects
-                        let tile_width = tileset.tile_width as f32;
-                        let tile_height = tileset.tile_height as f32;
-                        let image = tileset.images.first().unwrap();
-                        let texture_width = image.width as f32;
-                        let texture_height = image.height as f32;
-                        let columns = (texture_width / tile_width).floor() as usize;
-                        let rows = (texture_height / tile_height).floor() as usize;
-
-                        let has_new = (0..(columns * rows) as u32).fold(false, |total, next| {
-                            total || !texture_atlas_map.contains_key(&(tileset.first_gid + next))
-                        });
-                        if has_new {
-                            let atlas = TextureAtlas::from_grid(
-                                texture_handle.clone(),
-                                Vec2::new(tile_width, tile_height),
-                                columns,
-                                rows,
-                            );
-                            let atlas_handle = texture_atlases.add(atlas);
-                            for i in 0..(columns * rows) as u32 {
-                                if texture_atlas_map.contains_key(&(tileset.first_gid + i)) {
-                                    continue;
-                                }
-                                // println!("insert: {}", tileset.first_gid + i);
-                                texture_atlas_map
-                                    .insert(tileset.first_gid + i, atlas_handle.clone());
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// The point of the map placed at its origin. Points are in the map's tile grid, so for isometric
+/// maps the corners are those of the diamond: `TopLeft` is its top and `BottomLeft` its left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect_value(Component, PartialEq)]
+pub enum MapAnchor {
+    TopLeft,
+    Center,
+    BottomLeft,
+    /// Normalized position in the map, from (0, 0) at the top left to (1, 1) at the bottom right.
+    Custom(Vec2),
+}
 
-        for mesh in map.meshes.drain(0..map.meshes.len()) {
-            let handle = meshes.add(mesh.2);
-            if new_meshes.contains_key(changed_map) {
-                let mesh_list = new_meshes.get_mut(changed_map).unwrap();
-                mesh_list.push((mesh.0, mesh.1, handle));
-            } else {
-                let mut mesh_list = Vec::new();
-                mesh_list.push((mesh.0, mesh.1, handle));
-                new_meshes.insert(changed_map, mesh_list);
-            }
+impl MapAnchor {
+    /// Position of the anchor in the map, from (0, 0) at the top left to (1, 1) at the bottom right.
+    pub fn normalized(&self) -> Vec2 {
+        match self {
+            MapAnchor::TopLeft => Vec2::ZERO,
+            MapAnchor::Center => Vec2::new(0.5, 0.5),
+            MapAnchor::BottomLeft => Vec2::new(0.0, 1.0),
+            MapAnchor::Custom(anchor) => *anchor,
         }
     }
+}
 
-    for (
-        _,
-        center,
-        map_handle,
-        optional_parent,
-        materials_map,
-        texture_atlas_map,
-        origin,
-        mut debug_config,
-        mut created_entities,
-    ) in query.iter_mut()
-    {
-        if new_meshes.contains_key(map_handle) {
-            let map = maps.get(map_handle).unwrap();
-
-            let tile_map_transform = if center.0 {
-                map.center(origin.clone())
-            } else {
-                origin.clone()
-            };
+impl Default for MapAnchor {
+    fn default() -> Self {
+        MapAnchor::TopLeft
+    }
+}
 
-            let mesh_list = new_meshes.get_mut(map_handle).unwrap();
+/// Which way y points in the map content. Tiled's y points down while Bevy's points up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect_value(Component, PartialEq)]
+pub enum YAxis {
+    /// Rows of the map go down the screen in Bevy's y-up world: the map looks like in Tiled and the
+    /// content has negative y, e.g. the bottom left of a 10 x 10 map of 16px tiles is at (0, -160).
+    FlipToBevy,
+    /// The content keeps Tiled's pixel coordinates, e.g. that corner is at (0, 160). In a y-up world
+    /// the map is then upside down, so use it with a camera whose y points down.
+    KeepTiled,
+}
 
-            for (layer_id, layer) in map.layers.iter().enumerate() {
-                for tileset_layer in layer.tileset_layers.iter() {
-                    let material_handle = materials_map.get(&tileset_layer.tileset_guid).unwrap();
-                    // let mut mesh_list = mesh_list.iter_mut().filter(|(mesh_layer_id, _)| *mesh_layer_id == layer_id as u32).drain(0..mesh_list.len()).collect::<Vec<_>>();
-                    let chunk_mesh_list = mesh_list
-                        .iter()
-                        .filter(|(mesh_layer_id, tileset_guid, _)| {
-                            *mesh_layer_id == layer_id as u32
-                                && *tileset_guid == tileset_layer.tileset_guid
-                        })
-                        .collect::<Vec<_>>();
-
-                    // removing entities consumes the record of created entities
-                    created_entities
-                        .created_layer_entities
-                        .remove(&(layer_id, tileset_layer.tileset_guid))
-                        .map(|entities| {
-                            // println!("Despawning previously-created mesh for this chunk");
-                            for entity in entities.iter() {
-                                // println!("calling despawn on {:?}", entity);
-                                commands.entity(*entity).despawn();
-                            }
-                        });
-                    let mut chunk_entities: Vec<Entity> = Default::default();
-                    let layer_transform = tile_map_transform
-                        * Transform::from_translation(Vec3::new(
-                            tileset_layer.offset_x,
-                            -tileset_layer.offset_y,
-                            layer_id as f32,
-                        ));
+impl Default for YAxis {
+    fn default() -> Self {
+        YAxis::FlipToBevy
+    }
+}
 
-                    for (_, tileset_guid, mesh) in chunk_mesh_list.iter() {
-                        // TODO: Sadly bevy doesn't support multiple meshes on a single entity with multiple materials.
-                        // Change this once it does.
-
-                        // Instead for now spawn a new entity per chunk.
-                        let chunk_entity = commands
-                            .spawn_bundle(ChunkBundle {
-                                material: material_handle.clone(),
-                                mesh: mesh.clone(),
-                                map_parent: map_handle.clone(),
-                                transform: layer_transform,
-                                ..Default::default()
-                            })
-                            .id();
-
-                        // println!("added created_entry after spawn");
-                        created_entities
-                            .created_layer_entities
-                            .entry((layer_id, *tileset_guid))
-                            .or_insert_with(|| Vec::new())
-                            .push(chunk_entity);
-                        chunk_entities.push(chunk_entity);
-                    }
-                    // if parent was passed in add children and mark it as MapRoot (temp until map bundle returns real entity)
-                    if let Some(parent_entity) = optional_parent {
-                        commands
-                            .entity(parent_entity.clone())
-                            .push_children(&chunk_entities)
-                            .insert(MapRoot);
-                    }
-                }
-            }
+impl From<TiledMapCenter> for MapAnchor {
+    fn from(center: TiledMapCenter) -> Self {
+        if center.0 {
+            MapAnchor::Center
+        } else {
+            MapAnchor::TopLeft
+        }
+    }
+}
 
-            if debug_config.enabled && debug_config.material.is_none() {
-                debug_config.material =
-                    Some(materials.add(ColorMaterial::from(Color::rgba(0.4, 0.4, 0.9, 0.5))));
-            }
-            for object_group in map.groups.iter() {
-                for object in object_group.objects.iter() {
-                    created_entities
-                        .created_object_entities
-                        .remove(&object.gid)
-                        .map(|entities| {
-                            // println!("Despawning previously-created object sprite");
-                            for entity in entities.iter() {
-                                // println!("calling despawn on {:?}", entity);
-                                commands.entity(*entity).despawn();
-                            }
-                        });
-                }
-                if !object_group.visible {
-                    continue;
-                }
+/// Bounding box of the map's tiles, see `Map::bounds`. Inserted on the map's root entity when it is spawned,
+/// in world space unless the map was spawned under a parent entity, then relative to that parent.
+#[derive(Debug, Default, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct MapBounds(pub Rect<f32>);
 
-                let mut object_entities: Vec<Entity> = Default::default();
-
-                // TODO: use object_group.name, opacity, colour (properties)
-                for object in object_group.objects.iter() {
-                    // println!("in object_group {}, object {:?}, grp: {}", object_group.name, &object.tileset_gid, object.gid);
-                    let atlas_handle = object
-                        .tileset_gid
-                        .and_then(|tileset_gid| texture_atlas_map.get(&tileset_gid));
-
-                    let entity = object
-                        .spawn(
-                            &mut commands,
-                            atlas_handle,
-                            &map.map,
-                            map_handle.clone(),
-                            &tile_map_transform,
-                            &debug_config,
-                        )
-                        .id();
-                    // when done spawning, fire event
-                    let evt = ObjectReadyEvent {
-                        entity: entity.clone(),
-                        map_handle: map_handle.clone(),
-                        map_entity_option: optional_parent.clone(),
-                    };
-                    ready_events.send(evt);
-
-                    created_entities
-                        .created_object_entities
-                        .entry(object.gid)
-                        .or_insert_with(|| Vec::new())
-                        .push(entity);
-                    object_entities.push(entity);
-                }
+/// Summary of a map's contents, see `Map::stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MapStats {
+    /// Tiles of all tile layers, hidden layers included.
+    pub tile_count: usize,
+    /// Tiles of the layers visible in Tiled.
+    pub visible_tile_count: usize,
+    pub layer_count: usize,
+    pub tileset_count: usize,
+    pub object_count: usize,
+    /// Vertices of the chunk meshes built for the visible layers when the map was loaded, 0 with `headless`.
+    pub mesh_vertex_count: usize,
+}
 
-                // if parent was passed in add children
-                if let Some(parent_entity) = optional_parent {
-                    commands
-                        .entity(parent_entity.clone())
-                        .push_children(&object_entities);
-                }
-            }
-            let evt = MapReadyEvent {
-                map_handle: map_handle.clone(),
-                map_entity_option: optional_parent.clone(),
+/// Logs the `MapStats` of each map when it is loaded, see `TiledMapPlugin::log_map_stats`.
+pub fn log_map_stats(
+    mut map_events: EventReader<AssetEvent<Map>>,
+    maps: Res<Assets<Map>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in map_events.iter() {
+        if let AssetEvent::Created { handle } = event {
+            let map = match maps.get(handle) {
+                Some(map) => map,
+                None => continue,
             };
-            map_ready_events.send(evt);
+            let stats = map.stats();
+            info!(
+                "Loaded map {:?}: {} tiles ({} visible) in {} layers, {} tilesets, {} objects, {} mesh vertices",
+                asset_server.get_handle_path(handle).map(|path| path.path().to_path_buf()),
+                stats.tile_count,
+                stats.visible_tile_count,
+                stats.layer_count,
+                stats.tileset_count,
+                stats.object_count,
+                stats.mesh_vertex_count
+            );
         }
     }
 }
 
-// events fired when entity has been created
-
-pub struct ObjectReadyEvent {
-    pub entity: Entity,
-    pub map_handle: Handle<Map>,
-    pub map_entity_option: Option<Entity>,
+/// Parses `xml` like the loader parses a `test.tmx` at the root of the asset folder, for the tests of every module.
+#[cfg(test)]
+pub(crate) fn parse_test_map(xml: &str) -> Result<Map> {
+    Map::try_from_bytes(
+        Path::new(""),
+        Path::new("test.tmx"),
+        xml.as_bytes().to_vec(),
+    )
 }
 
-pub struct MapReadyEvent {
-    pub map_handle: Handle<Map>,
-    pub map_entity_option: Option<Entity>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iter_tiles_skips_empty_cells() {
+        let map = parse_test_map(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="orthogonal" renderorder="right-down" width="3" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,0,2,
+0,3,0
+</data>
+ </layer>
+</map>
+"#,
+        )
+        .unwrap();
+        let tiles: Vec<_> = map.iter_tiles(LayerId(0)).collect();
+        assert_eq!(
+            tiles,
+            vec![
+                (IVec2::new(0, 0), 1),
+                (IVec2::new(2, 0), 2),
+                (IVec2::new(1, 1), 3)
+            ]
+        );
+        assert_eq!(map.iter_tiles(LayerId(1)).count(), 0);
+    }
+
+    #[test]
+    fn iter_tiles_offsets_infinite_chunks() {
+        let map = parse_test_map(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="1" nextlayerid="2" nextobjectid="1">
+ <layer id="1" name="ground" width="2" height="2">
+  <data encoding="csv">
+   <chunk x="-16" y="16" width="2" height="2">
+0,4,
+5,0
+</chunk>
+  </data>
+ </layer>
+</map>
+"#,
+        )
+        .unwrap();
+        let mut tiles: Vec<_> = map.iter_tiles(LayerId(0)).collect();
+        tiles.sort_by_key(|(tile, _)| (tile.y, tile.x));
+        assert_eq!(
+            tiles,
+            vec![(IVec2::new(-15, 16), 4), (IVec2::new(-16, 17), 5)]
+        );
+    }
+
+    #[cfg(not(feature = "headless"))]
+    #[test]
+    fn stats_count_the_vertices_of_the_built_meshes() {
+        let mut map = parse_test_map(include_str!("../tests/fixtures/hidden-layer.tmx")).unwrap();
+        let built: usize = map
+            .meshes
+            .iter()
+            .map(|(_, _, _, mesh)| mesh.count_vertices())
+            .sum();
+        assert_eq!(map.stats().mesh_vertex_count, built);
+        assert_eq!(map.stats().visible_tile_count, 3);
+        // still counted once the meshes are handed over
+        map.take_meshes();
+        assert_eq!(map.stats().mesh_vertex_count, built);
+    }
+
+    #[cfg(not(feature = "headless"))]
+    #[test]
+    fn geometry_hash_ignores_property_order_but_not_tiles() {
+        let xml = |tiles: &str| {
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="orthogonal" renderorder="right-down" width="2" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+ </tileset>
+ <layer id="1" name="ground" width="2" height="1">
+  <properties>
+   <property name="a" value="1"/>
+   <property name="b" value="2"/>
+   <property name="c" value="3"/>
+  </properties>
+  <data encoding="csv">
+{}
+</data>
+ </layer>
+</map>
+"#,
+                tiles
+            )
+        };
+        // every parse has its own property map, and with it its own iteration order
+        let first = parse_test_map(&xml("1,2")).unwrap();
+        for _ in 0..8 {
+            assert_eq!(
+                parse_test_map(&xml("1,2")).unwrap().geometry_hash,
+                first.geometry_hash
+            );
+        }
+        assert_ne!(
+            parse_test_map(&xml("1,3")).unwrap().geometry_hash,
+            first.geometry_hash
+        );
+    }
 }
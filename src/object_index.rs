@@ -0,0 +1,126 @@
+use bevy::{math::Rect, prelude::*, utils::HashMap};
+
+use crate::ObjectGroup;
+
+// width and height of a cell, in tiles
+const CELL_TILES: f32 = 8.0;
+
+/// Uniform grid over the bounds of a map's objects, so rect queries only check the objects near the rect.
+/// Built when the map is loaded and used by `Map::objects_in_rect`. Bounds are in map space: the map
+/// spawned at the origin without centering or scale, y up. Changes to `Map::groups` aren't indexed,
+/// nor are the objects of maps that aren't orthogonal, see `Object::world_bounds`.
+#[derive(Debug, Default)]
+pub struct ObjectIndex {
+    cell_size: Vec2,
+    // group and object indices of the objects overlapping each cell
+    cells: HashMap<IVec2, Vec<(usize, usize)>>,
+    min_cell: IVec2,
+    max_cell: IVec2,
+}
+
+impl ObjectIndex {
+    pub fn new(map: &tiled::Map, groups: &[ObjectGroup]) -> Self {
+        let tile_size = Vec2::new(map.tile_width as f32, map.tile_height as f32);
+        let cell_size = (tile_size * CELL_TILES).max(Vec2::ONE);
+        let mut index = ObjectIndex {
+            cell_size,
+            cells: HashMap::default(),
+            min_cell: IVec2::new(i32::MAX, i32::MAX),
+            max_cell: IVec2::new(i32::MIN, i32::MIN),
+        };
+        for (group_index, group) in groups.iter().enumerate() {
+            for (object_index, object) in group.objects.iter().enumerate() {
+                let bounds = match object.world_bounds(map, &Transform::identity()) {
+                    Some(bounds) => bounds,
+                    None => continue,
+                };
+                let (min, max) = index.cell_range(&bounds);
+                index.min_cell = index.min_cell.min(min);
+                index.max_cell = index.max_cell.max(max);
+                for y in min.y..=max.y {
+                    for x in min.x..=max.x {
+                        index
+                            .cells
+                            .entry(IVec2::new(x, y))
+                            .or_insert_with(Vec::new)
+                            .push((group_index, object_index));
+                    }
+                }
+            }
+        }
+        index
+    }
+
+    /// Group and object indices of the objects whose cells `rect` overlaps, a superset of the objects
+    /// intersecting it. `rect` is in map space. Each object is listed once, in group and object order.
+    pub fn candidates(&self, rect: &Rect<f32>) -> Vec<(usize, usize)> {
+        let (min, max) = self.cell_range(rect);
+        // don't walk the empty cells of rects reaching far outside the objects
+        let (min, max) = (min.max(self.min_cell), max.min(self.max_cell));
+        let mut found = Vec::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                if let Some(objects) = self.cells.get(&IVec2::new(x, y)) {
+                    found.extend_from_slice(objects);
+                }
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    // first and last cell covered by a rect
+    fn cell_range(&self, rect: &Rect<f32>) -> (IVec2, IVec2) {
+        let min = (Vec2::new(rect.left, rect.bottom) / self.cell_size).floor();
+        let max = (Vec2::new(rect.right, rect.top) / self.cell_size).floor();
+        (
+            IVec2::new(min.x as i32, min.y as i32),
+            IVec2::new(max.x as i32, max.y as i32),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{map::parse_test_map, Map};
+    use bevy::{math::Rect, prelude::*};
+
+    fn map_with_object(orientation: &str) -> Map {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="{}" renderorder="right-down" width="4" height="4" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="2">
+ <objectgroup id="1" name="objects">
+  <object id="1" x="16" y="16" width="16" height="16"/>
+ </objectgroup>
+</map>
+"#,
+            orientation
+        );
+        parse_test_map(&xml).unwrap()
+    }
+
+    #[test]
+    fn finds_objects_of_orthogonal_maps() {
+        let map = map_with_object("orthogonal");
+        let rect = Rect {
+            left: 20.0,
+            right: 24.0,
+            top: -20.0,
+            bottom: -24.0,
+        };
+        assert_eq!(map.objects_in_rect(rect, &Transform::identity()).len(), 1);
+    }
+
+    #[test]
+    fn skips_objects_of_isometric_maps() {
+        let map = map_with_object("isometric");
+        let rect = Rect {
+            left: -1000.0,
+            right: 1000.0,
+            top: 1000.0,
+            bottom: -1000.0,
+        };
+        assert!(map.objects_in_rect(rect, &Transform::identity()).is_empty());
+    }
+}
@@ -1,12 +1,72 @@
+use std::{
+    f32::consts::{FRAC_PI_2, TAU},
+    sync::Arc,
+};
+
+#[cfg(not(feature = "headless"))]
+use bevy::render::{
+    mesh::{Indices, VertexAttributeValues},
+    pipeline::{PrimitiveTopology, RenderPipeline},
+    render_graph::base::MainPass,
+};
 use bevy::{ecs::system::EntityCommands, prelude::*, utils::HashMap};
 
-use crate::{DebugConfig, Map, loader::TiledMapLoader};
+use crate::{
+    convex_decomposition,
+    loader::{
+        TiledMapLoader, FLIPPED_DIAGONALLY_FLAG, FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG,
+    },
+    Animation, CreatedMapEntities, DepthConfig, Map, ObjectReadyEvent, ObjectSortMode,
+    PropertiesExt, ShapeObjects,
+};
+#[cfg(not(feature = "headless"))]
+use crate::{DebugConfig, LayerTint, TILE_MAP_PIPELINE_HANDLE};
+
+// segments used to approximate ellipse outlines, and ellipse debug meshes by default
+pub const ELLIPSE_SEGMENTS: usize = 32;
+// size of the cross drawn for point objects
+#[cfg(not(feature = "headless"))]
+const POINT_CROSS_SIZE: f32 = 8.0;
+
+/// Callback invoked for every spawned object of a registered type.
+pub type ObjectSpawnFn = Arc<dyn Fn(&mut EntityCommands, &Object, &Transform) + Send + Sync>;
+
+/// Spawn callbacks keyed by object type, registered through `TiledMapPlugin::on_object_type`.
+#[derive(Default, Clone)]
+pub struct ObjectTypeCallbacks {
+    callbacks: HashMap<String, Vec<ObjectSpawnFn>>,
+}
+
+impl ObjectTypeCallbacks {
+    pub fn register(&mut self, obj_type: &str, callback: ObjectSpawnFn) {
+        self.callbacks
+            .entry(obj_type.to_string())
+            .or_insert_with(Vec::new)
+            .push(callback);
+    }
+
+    pub fn run(
+        &self,
+        entity_commands: &mut EntityCommands,
+        object: &Object,
+        transform: &Transform,
+    ) {
+        if let Some(callbacks) = self.callbacks.get(&object.obj_type) {
+            for callback in callbacks.iter() {
+                callback(entity_commands, object, transform);
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct ObjectGroup {
     pub name: String,
+    pub class: String, // set in Tiled 1.9 and later, empty otherwise
     pub opacity: f32,
     pub visible: bool,
+    pub layer_index: u32, // position among all map layers, determines depth
+    pub draw_order: DrawOrder,
     pub objects: Vec<Object>,
 }
 
@@ -16,31 +76,224 @@ impl ObjectGroup {
         tile_gids: &HashMap<u32, u32>,
     ) -> ObjectGroup {
         // println!("grp {}", inner.name.to_string());
+        let layer_index = inner.layer_index.unwrap_or(0);
         ObjectGroup {
             name: inner.name.to_string(),
+            class: String::new(),
             opacity: inner.opacity,
             visible: inner.visible,
+            layer_index,
+            draw_order: DrawOrder::default(),
             objects: inner
                 .objects
                 .iter()
-                .map(|obj| Object::new_with_tile_ids(obj, tile_gids))
+                .enumerate()
+                .map(|(i, obj)| {
+                    let mut o = Object::new_with_tile_ids(obj, tile_gids);
+                    o.layer_index = layer_index;
+                    o.opacity = inner.opacity;
+                    o.group_order = i as f32 / inner.objects.len() as f32;
+                    o
+                })
                 .collect(),
         }
     }
+
+    pub fn object_by_name(&self, name: &str) -> Option<&Object> {
+        self.objects.iter().find(|o| o.name == name)
+    }
+
+    pub fn objects_by_type<'a>(&'a self, obj_type: &'a str) -> impl Iterator<Item = &'a Object> {
+        self.objects.iter().filter(move |o| o.obj_type == obj_type)
+    }
+}
+
+/// Order Tiled draws the objects of a group in, set as the group's `draworder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect_value(PartialEq)]
+pub enum DrawOrder {
+    /// By y, lower objects in front. Tiled's default.
+    TopDown,
+    /// In the order of the group, later objects in front.
+    Index,
+}
+
+impl DrawOrder {
+    pub fn from_name(name: &str) -> DrawOrder {
+        match name {
+            "index" => DrawOrder::Index,
+            _ => DrawOrder::TopDown,
+        }
+    }
+}
+
+impl Default for DrawOrder {
+    fn default() -> Self {
+        DrawOrder::TopDown
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Where a tile object's position sits on its image, from its tileset's `objectalignment`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect_value(PartialEq)]
+pub enum ObjectAlignment {
+    Unspecified,
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Default for ObjectAlignment {
+    fn default() -> Self {
+        ObjectAlignment::Unspecified
+    }
+}
+
+impl ObjectAlignment {
+    pub fn from_name(name: &str) -> ObjectAlignment {
+        match name {
+            "topleft" => ObjectAlignment::TopLeft,
+            "top" => ObjectAlignment::Top,
+            "topright" => ObjectAlignment::TopRight,
+            "left" => ObjectAlignment::Left,
+            "center" => ObjectAlignment::Center,
+            "right" => ObjectAlignment::Right,
+            "bottomleft" => ObjectAlignment::BottomLeft,
+            "bottom" => ObjectAlignment::Bottom,
+            "bottomright" => ObjectAlignment::BottomRight,
+            _ => ObjectAlignment::Unspecified,
+        }
+    }
+
+    /// Anchor as a fraction of the object size from its top left corner, y pointing down.
+    /// Unspecified anchors at the bottom left, as Tiled does on orthogonal maps.
+    pub fn anchor(&self) -> Vec2 {
+        match self {
+            ObjectAlignment::TopLeft => Vec2::new(0.0, 0.0),
+            ObjectAlignment::Top => Vec2::new(0.5, 0.0),
+            ObjectAlignment::TopRight => Vec2::new(1.0, 0.0),
+            ObjectAlignment::Left => Vec2::new(0.0, 0.5),
+            ObjectAlignment::Center => Vec2::new(0.5, 0.5),
+            ObjectAlignment::Right => Vec2::new(1.0, 0.5),
+            ObjectAlignment::Unspecified | ObjectAlignment::BottomLeft => Vec2::new(0.0, 1.0),
+            ObjectAlignment::Bottom => Vec2::new(0.5, 1.0),
+            ObjectAlignment::BottomRight => Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+/// Grid of a tileset, from its `<grid>` element. Tiled writes it for image collections and isometric tilesets,
+/// whose tiles are aligned to an isometric grid of `size` cells rather than to their image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TilesetGrid {
+    pub orientation: tiled::Orientation,
+    pub size: Vec2,
+}
+
+#[derive(Debug, Clone, Reflect)]
+#[reflect(Component)]
 pub struct Object {
+    #[reflect(ignore)]
     pub shape: tiled::ObjectShape,
+    #[reflect(ignore)]
     pub props: tiled::Properties,
     pub position: Vec2,
     pub size: Vec2,
+    pub id: u32, // unique and stable id of the object in the map
     pub name: String,
     pub obj_type: String,
+    pub class: String, // set in Tiled 1.9 and later, which also uses it as the object type
     pub visible: bool,
     pub gid: u32,                 // sprite ID from tiled::Object
     pub tileset_gid: Option<u32>, // AKA first_gid
     pub sprite_index: Option<u32>,
+    pub layer_index: u32,      // layer index of the containing object group
+    pub opacity: f32,          // opacity of the containing object group
+    pub group_order: f32, // position in the containing object group, from 0 for the first to below 1
+    pub draw_order: DrawOrder, // of the containing object group
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+    pub alignment: ObjectAlignment, // of the object's tileset, for tile objects
+    #[reflect(ignore)]
+    pub grid: Option<TilesetGrid>, // of the object's tileset, for tile objects
+}
+
+/// Shape object spawned as a gameplay zone rather than a debug sprite, see `ShapeObjects::TriggerZone`.
+/// `size` is in map pixels, scaled by the entity's transform like the debug boxes.
+#[derive(Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct TriggerZone {
+    pub size: Vec2,
+    #[reflect(ignore)]
+    pub shape: tiled::ObjectShape,
+    pub name: String,
+    pub obj_type: String,
+    #[reflect(ignore)]
+    pub properties: tiled::Properties,
+}
+
+impl Default for TriggerZone {
+    fn default() -> Self {
+        Self {
+            size: Vec2::ZERO,
+            shape: tiled::ObjectShape::Rect {
+                width: 0.0,
+                height: 0.0,
+            },
+            name: String::new(),
+            obj_type: String::new(),
+            properties: Default::default(),
+        }
+    }
+}
+
+/// Route authored as a polyline object, such as a patrol, inserted on the polyline's entity.
+/// `points` are in world space, relative to the map's parent entity if it was spawned under one.
+#[derive(Debug, Default, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Path {
+    pub name: String,
+    pub points: Vec<Vec2>,
+}
+
+/// Tiled id of a spawned object, to find a specific object entity after load.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+#[reflect(Component)]
+pub struct ObjectId(pub u32);
+
+impl Default for Object {
+    fn default() -> Self {
+        Object {
+            shape: tiled::ObjectShape::Point(0.0, 0.0),
+            props: Default::default(),
+            position: Vec2::ZERO,
+            size: Vec2::ZERO,
+            id: 0,
+            name: Default::default(),
+            obj_type: Default::default(),
+            class: Default::default(),
+            visible: true,
+            gid: 0,
+            tileset_gid: None,
+            sprite_index: None,
+            layer_index: 0,
+            opacity: 1.0,
+            group_order: 0.0,
+            draw_order: DrawOrder::TopDown,
+            flip_h: false,
+            flip_v: false,
+            flip_d: false,
+            alignment: ObjectAlignment::Unspecified,
+            grid: None,
+        }
+    }
 }
 
 impl Object {
@@ -55,8 +308,20 @@ impl Object {
             sprite_index: None,
             position: Vec2::new(original_object.x, original_object.y),
             size: Vec2::new(original_object.width, original_object.height),
+            id: original_object.id,
             name: original_object.name.clone(),
             obj_type: original_object.obj_type.clone(),
+            class: String::new(),
+            layer_index: 0,
+            opacity: 1.0,
+            group_order: 0.0,
+            draw_order: DrawOrder::TopDown,
+            // tile objects carry flip flags in the high bits of their gid
+            flip_h: original_object.gid & FLIPPED_HORIZONTALLY_FLAG != 0,
+            flip_v: original_object.gid & FLIPPED_VERTICALLY_FLAG != 0,
+            flip_d: original_object.gid & FLIPPED_DIAGONALLY_FLAG != 0,
+            alignment: ObjectAlignment::Unspecified,
+            grid: None,
         }
     }
 
@@ -64,6 +329,32 @@ impl Object {
         self.tileset_gid.is_none()
     }
 
+    /// Whether the object is a point, a zero-size marker such as a waypoint or spawn location.
+    pub fn is_point(&self) -> bool {
+        matches!(self.shape, tiled::ObjectShape::Point(_, _))
+    }
+
+    pub fn prop_int(&self, key: &str) -> Option<i32> {
+        self.props.get_int(key)
+    }
+
+    pub fn prop_float(&self, key: &str) -> Option<f32> {
+        self.props.get_float(key)
+    }
+
+    pub fn prop_bool(&self, key: &str) -> Option<bool> {
+        self.props.get_bool(key)
+    }
+
+    pub fn prop_string(&self, key: &str) -> Option<&str> {
+        self.props.get_string(key)
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn prop_color(&self, key: &str) -> Option<Color> {
+        self.props.get_color(key)
+    }
+
     pub fn new_with_tile_ids(
         original_object: &tiled::Object,
         tile_gids: &HashMap<u32, u32>,
@@ -78,11 +369,13 @@ impl Object {
         self.sprite_index = self.tileset_gid.map(|first_gid| &self.gid - first_gid);
     }
 
+    /// Panics on maps that aren't orthogonal, whose objects can't be placed yet.
     pub fn transform_from_map(
         &self,
         map: &tiled::Map,
         map_transform: &Transform,
         tile_scale: Option<Vec3>,
+        depth_config: &DepthConfig,
     ) -> Transform {
         // tile scale being None means this is not a tile object
 
@@ -96,50 +389,123 @@ impl Object {
         // transform.translation -= map_transform.scale * Vec3::new(map_tile_width, -map_tile_height, 0.0) / 2.0;
 
         let map_orientation: tiled::Orientation = map.orientation;
-        // replacing map Z with something far in front for objects -- should probably be configurable
-        // transform.translation.z = 1000.0;
-        let z_relative_to_map = 15.0; // used for a range of 5-25 above tile Z coordinate for items (max 20k map)
-        match self.shape {
-            tiled::ObjectShape::Rect { width, height } => {
-                match map_orientation {
-                    tiled::Orientation::Orthogonal => {
-                        let mut center_offset = Vec2::new(self.position.x, -self.position.y);
-                        match tile_scale {
-                            None => {
-                                // shape object x/y represent top left corner
-                                center_offset += Vec2::new(width, -height) / 2.0;
-                            }
-                            Some(tile_scale) => {
-                                // tile object x/y represents bottom left corner
-                                center_offset += Vec2::new(width, height) / 2.0;
-                                // tile object scale based on map scale and passed-in scale from image dimensions
-                                transform.scale = tile_scale * transform.scale;
-                            }
-                        }
-                        // apply map scale to object position, if this is a tile
-                        center_offset *= map_transform.scale.truncate();
-                        // offset transform by object position
-                        transform.translation +=
-                            center_offset.extend(z_relative_to_map - center_offset.y / 2000.0);
-                        // ^ HACK only support up to 20k pixels maps, TODO: configure in API
+        // objects sit at their group's layer depth, ordered within the sort range
+        let sort_mode = match (depth_config.object_sort, self.draw_order) {
+            (ObjectSortMode::FromMap, DrawOrder::TopDown) => ObjectSortMode::YSort,
+            (ObjectSortMode::FromMap, DrawOrder::Index) => ObjectSortMode::Index,
+            (sort_mode, _) => sort_mode,
+        };
+        let sort = match sort_mode {
+            ObjectSortMode::Index | ObjectSortMode::FromMap => self.group_order,
+            ObjectSortMode::YSort => {
+                let map_height = (map.height * map.tile_height) as f32;
+                (self.bottom_y() / map_height).clamp(0.0, 1.0)
+            }
+        };
+        let z_relative_to_map =
+            depth_config.layer_z(self.layer_index) + sort * depth_config.object_y_sort_range;
+        match map_orientation {
+            tiled::Orientation::Orthogonal => {
+                let mut center_offset = Vec2::new(self.position.x, -self.position.y);
+                match tile_scale {
+                    None => {
+                        // shape object x/y represent top left corner, points are relative to it
+                        let (min, size) = self.local_bounds();
+                        center_offset += Vec2::new(min.x + size.x / 2.0, -(min.y + size.y / 2.0));
+                    }
+                    Some(tile_scale) => {
+                        // tile object x/y is its tileset's anchor, bottom left by default
+                        let anchor = self.alignment.anchor();
+                        center_offset += self.size * Vec2::new(0.5 - anchor.x, anchor.y - 0.5);
+                        // tile object scale based on map scale and passed-in scale from image dimensions
+                        transform.scale = tile_scale * transform.scale;
                     }
-                    // tiled::Orientation::Isometric => {
-
-                    // }
-                    _ => panic!("Sorry, {:?} objects aren't supported -- please hide this object layer for now.", map_orientation),
                 }
+                // apply map scale to object position, if this is a tile
+                center_offset *= map_transform.scale.truncate();
+                // offset transform by object position
+                transform.translation +=
+                    center_offset.extend(z_relative_to_map * map_transform.scale.z);
             }
-            tiled::ObjectShape::Ellipse {
-                width: _,
-                height: _,
-            } => {}
-            tiled::ObjectShape::Polyline { points: _ } => {}
-            tiled::ObjectShape::Polygon { points: _ } => {}
-            tiled::ObjectShape::Point(_, _) => {}
+            // tiled::Orientation::Isometric => {
+
+            // }
+            _ => panic!(
+                "Sorry, {:?} objects aren't supported -- please hide this object layer for now.",
+                map_orientation
+            ),
         }
         transform
     }
 
+    /// Y of the object's bottom edge in tiled's y-down space, where it stands in top-down views.
+    pub fn bottom_y(&self) -> f32 {
+        if self.is_shape() {
+            let (min, size) = self.local_bounds();
+            self.position.y + min.y + size.y
+        } else {
+            self.position.y + self.size.y * (1.0 - self.alignment.anchor().y)
+        }
+    }
+
+    pub fn tile_scale(&self, map: &tiled::Map) -> Option<Vec3> {
+        let tileset_gid = self.tileset_gid?;
+        // fetch tile for this object if it exists
+        let object_tile_size = map
+            .tilesets
+            .iter()
+            .find(|ts| ts.first_gid == tileset_gid)
+            .map(|ts| Vec2::new(ts.tile_width as f32, ts.tile_height as f32));
+        // object dimensions
+        let dims = self.dimensions();
+        // use object dimensions and tile size to determine extra scale to apply for tile objects
+        if let (Some(dims), Some(size)) = (dims, object_tile_size) {
+            Some((dims / size).extend(1.0))
+        } else {
+            None
+        }
+    }
+
+    /// The transform the object is spawned with.
+    pub fn spawn_transform(
+        &self,
+        map: &tiled::Map,
+        tile_map_transform: &Transform,
+        depth_config: &DepthConfig,
+    ) -> Transform {
+        let mut transform =
+            self.transform_from_map(map, tile_map_transform, self.tile_scale(map), depth_config);
+        if !self.is_shape() && self.flip_d {
+            // tiled swaps x/y before flipping, which is a quarter turn on top of the sprite flips
+            let angle = if self.flip_h == self.flip_v {
+                FRAC_PI_2
+            } else {
+                -FRAC_PI_2
+            };
+            transform.rotation = transform.rotation * Quat::from_rotation_z(angle);
+        }
+        transform
+    }
+
+    /// Animation of the tile the object shows, if the tile is animated in its tileset.
+    pub fn animation(&self, map: &tiled::Map) -> Option<Animation> {
+        let tileset_gid = self.tileset_gid?;
+        let tileset = map.tilesets.iter().find(|ts| ts.first_gid == tileset_gid)?;
+        let tile_id = self.gid - tileset_gid;
+        let tile = tileset.tiles.iter().find(|tile| tile.id == tile_id)?;
+        Animation::from_tile(tile)
+    }
+
+    /// Sprite flips matching the tile object's flip flags, see `spawn_transform` for the diagonal case.
+    pub fn sprite_flip(&self) -> (bool, bool) {
+        if self.flip_d {
+            (!self.flip_h, self.flip_v)
+        } else {
+            (self.flip_h, self.flip_v)
+        }
+    }
+
+    #[cfg(not(feature = "headless"))]
     pub fn spawn<'a, 'b>(
         &self,
         commands: &'b mut Commands<'a>,
@@ -148,74 +514,686 @@ impl Object {
         map_handle: Handle<Map>,
         tile_map_transform: &Transform,
         debug_config: &DebugConfig,
+        depth_config: &DepthConfig,
     ) -> EntityCommands<'a, 'b> {
         let mut new_entity_commands = if let Some(texture_atlas) = texture_atlas {
             let sprite_index = self.sprite_index.expect("missing sprite index");
-            let tileset_gid = self.tileset_gid.expect("missing tileset");
-
-            // fetch tile for this object if it exists
-            let object_tile_size = map
-                .tilesets
-                .iter()
-                .find(|ts| ts.first_gid == tileset_gid)
-                .map(|ts| Vec2::new(ts.tile_width as f32, ts.tile_height as f32));
-            // object dimensions
-            let dims = self.dimensions();
-            // use object dimensions and tile size to determine extra scale to apply for tile objects
-            let tile_scale = if let (Some(dims), Some(size)) = (dims, object_tile_size) {
-                Some((dims / size).extend(1.0))
-            } else {
-                None
-            };
-            commands.spawn_bundle(SpriteSheetBundle {
-                transform: self.transform_from_map(&map, tile_map_transform, tile_scale),
+            let (flip_x, flip_y) = self.sprite_flip();
+            let mut entity_commands = commands.spawn_bundle(SpriteSheetBundle {
+                transform: self.spawn_transform(&map, tile_map_transform, depth_config),
                 texture_atlas: texture_atlas.clone(),
                 sprite: TextureAtlasSprite {
+                    color: Color::rgba(1.0, 1.0, 1.0, self.opacity),
                     index: sprite_index,
+                    flip_x,
+                    flip_y,
                     ..Default::default()
                 },
+                // stays hidden until the tileset texture has loaded, see show_loaded_object_sprites
                 visible: Visible {
-                    is_visible: self.visible,
+                    is_visible: false,
                     is_transparent: true,
                     ..Default::default()
                 },
                 ..Default::default()
-            })
+            });
+            entity_commands.insert(ObjectSpriteLoading);
+            if let Some(animation) = self.animation(map) {
+                entity_commands.insert(animation);
+            }
+            entity_commands
         } else {
             // commands.spawn((self.map_transform(&map.map, &tile_map_transform, None), GlobalTransform::default()))
             let dimensions = self
                 .dimensions()
                 .expect("Don't know how to handle object without dimensions");
-            let transform = self.transform_from_map(&map, &tile_map_transform, None);
-            commands
-                // Debug box.
-                .spawn_bundle(SpriteBundle {
-                    material: debug_config
-                        .material
-                        .clone()
-                        .unwrap_or_else(|| Handle::<ColorMaterial>::default()),
-                    sprite: Sprite::new(dimensions),
-                    transform,
-                    visible: Visible {
-                        is_visible: debug_config.enabled,
-                        is_transparent: true,
-                        ..Default::default()
-                    },
+            let transform = self.transform_from_map(&map, &tile_map_transform, None, depth_config);
+            // Debug box.
+            let mut debug_bundle = SpriteBundle {
+                material: debug_config
+                    .material
+                    .clone()
+                    .unwrap_or_else(|| Handle::<ColorMaterial>::default()),
+                sprite: Sprite::new(dimensions),
+                transform,
+                visible: Visible {
+                    is_visible: debug_config.enabled,
+                    is_transparent: true,
                     ..Default::default()
-                })
+                },
+                ..Default::default()
+            };
+            // the sprite pipeline scales the unit ellipse to the object size
+            if let (tiled::ObjectShape::Ellipse { .. }, Some(ellipse_mesh)) =
+                (&self.shape, &debug_config.ellipse_mesh)
+            {
+                debug_bundle.mesh = ellipse_mesh.clone();
+            }
+            commands.spawn_bundle(debug_bundle)
         };
 
-        new_entity_commands.insert_bundle((map_handle, self.clone()));
+        new_entity_commands.insert_bundle((map_handle, self.clone(), ObjectId(self.id)));
+        new_entity_commands
+    }
+
+    /// Spawns the object with its transform only, for builds without rendering.
+    #[cfg(feature = "headless")]
+    pub fn spawn<'a, 'b>(
+        &self,
+        commands: &'b mut Commands<'a>,
+        map: &tiled::Map,
+        map_handle: Handle<Map>,
+        tile_map_transform: &Transform,
+        depth_config: &DepthConfig,
+    ) -> EntityCommands<'a, 'b> {
+        let mut new_entity_commands = commands.spawn_bundle((
+            self.spawn_transform(map, tile_map_transform, depth_config),
+            GlobalTransform::default(),
+        ));
+        new_entity_commands.insert_bundle((map_handle, self.clone(), ObjectId(self.id)));
+        new_entity_commands
+    }
+
+    /// The object as a `TriggerZone`, `None` for tile objects.
+    pub fn trigger_zone(&self) -> Option<TriggerZone> {
+        if !self.is_shape() {
+            return None;
+        }
+        Some(TriggerZone {
+            size: self.dimensions()?,
+            shape: self.shape.clone(),
+            name: self.name.clone(),
+            obj_type: self.obj_type.clone(),
+            properties: self.props.clone(),
+        })
+    }
+
+    /// Spawns a shape object with its transform and a `TriggerZone`, without any sprite.
+    pub fn spawn_trigger_zone<'a, 'b>(
+        &self,
+        commands: &'b mut Commands<'a>,
+        map: &tiled::Map,
+        map_handle: Handle<Map>,
+        tile_map_transform: &Transform,
+        depth_config: &DepthConfig,
+    ) -> EntityCommands<'a, 'b> {
+        let mut new_entity_commands = commands.spawn_bundle((
+            self.spawn_transform(map, tile_map_transform, depth_config),
+            GlobalTransform::default(),
+        ));
+        new_entity_commands.insert_bundle((map_handle, self.clone(), ObjectId(self.id)));
+        if let Some(trigger_zone) = self.trigger_zone() {
+            new_entity_commands.insert(trigger_zone);
+        }
         new_entity_commands
     }
 
+    /// World-space bounding box of the object, matching the transform it is spawned with.
+    /// World y points up, so `top` is the larger y. `None` on maps that aren't orthogonal,
+    /// whose objects can't be placed yet, see `transform_from_map`.
+    pub fn world_bounds(&self, map: &tiled::Map, map_transform: &Transform) -> Option<Rect<f32>> {
+        if map.orientation != tiled::Orientation::Orthogonal {
+            return None;
+        }
+        let dimensions = self.dimensions()?;
+        let center = self
+            .spawn_transform(map, map_transform, &DepthConfig::default())
+            .translation
+            .truncate();
+        let mut half_size = dimensions * map_transform.scale.truncate() / 2.0;
+        // tile objects flipped diagonally are turned a quarter, see spawn_transform
+        if !self.is_shape() && self.flip_d {
+            half_size = Vec2::new(half_size.y, half_size.x);
+        }
+        Some(Rect {
+            left: center.x - half_size.x,
+            right: center.x + half_size.x,
+            top: center.y + half_size.y,
+            bottom: center.y - half_size.y,
+        })
+    }
+
+    /// Points of the shape relative to the object position in tiled's y-down space,
+    /// and whether the last point connects back to the first.
+    pub fn shape_outline(&self) -> (Vec<Vec2>, bool) {
+        match &self.shape {
+            tiled::ObjectShape::Rect { width, height } => (
+                vec![
+                    Vec2::ZERO,
+                    Vec2::new(*width, 0.0),
+                    Vec2::new(*width, *height),
+                    Vec2::new(0.0, *height),
+                ],
+                true,
+            ),
+            tiled::ObjectShape::Ellipse { width, height } => {
+                let radius = Vec2::new(*width, *height) / 2.0;
+                let points = (0..ELLIPSE_SEGMENTS)
+                    .map(|i| {
+                        let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * TAU;
+                        radius + radius * Vec2::new(angle.cos(), angle.sin())
+                    })
+                    .collect();
+                (points, true)
+            }
+            tiled::ObjectShape::Polyline { points } => (
+                points.iter().map(|(x, y)| Vec2::new(*x, *y)).collect(),
+                false,
+            ),
+            tiled::ObjectShape::Polygon { points } => (
+                points.iter().map(|(x, y)| Vec2::new(*x, *y)).collect(),
+                true,
+            ),
+            tiled::ObjectShape::Point(_, _) => (vec![Vec2::ZERO], false),
+        }
+    }
+
+    // outline points relative to the spawned transform, which sits at the shape center with y up
+    fn centered_outline(&self) -> (Vec<Vec2>, bool) {
+        let (min, size) = self.local_bounds();
+        let center = min + size / 2.0;
+        let (points, closed) = self.shape_outline();
+        let points = points
+            .iter()
+            .map(|point| Vec2::new(point.x - center.x, center.y - point.y))
+            .collect();
+        (points, closed)
+    }
+
+    /// World-space outline of a shape object, matching the transform it is spawned with.
+    /// Empty for tile objects, see `world_bounds` for those, and on maps that aren't orthogonal.
+    pub fn world_points(&self, map: &tiled::Map, map_transform: &Transform) -> Vec<Vec2> {
+        if !self.is_shape() || map.orientation != tiled::Orientation::Orthogonal {
+            return Vec::new();
+        }
+        let transform = self.spawn_transform(map, map_transform, &DepthConfig::default());
+        self.centered_outline()
+            .0
+            .iter()
+            .map(|point| transform.mul_vec3(point.extend(0.0)).truncate())
+            .collect()
+    }
+
+    /// Closed shape split into convex polygons, for physics engines that need convex colliders.
+    /// Points are relative to the object position in tiled's y-down space, like `shape_outline`.
+    /// Empty for polylines and points, which have no area.
+    pub fn convex_parts(&self) -> Vec<Vec<Vec2>> {
+        match self.shape_outline() {
+            (points, true) => convex_decomposition(&points),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The polyline object as a `Path`, `None` for other objects.
+    pub fn path(&self, map: &tiled::Map, map_transform: &Transform) -> Option<Path> {
+        match self.shape {
+            tiled::ObjectShape::Polyline { .. } => Some(Path {
+                name: self.name.clone(),
+                points: self.world_points(map, map_transform),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Line mesh of the shape outline in the object's local space, points are drawn as crosses.
+    #[cfg(not(feature = "headless"))]
+    pub fn outline_mesh(&self) -> Option<Mesh> {
+        if !self.is_shape() {
+            return None;
+        }
+        let (points, closed) = self.centered_outline();
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        if let [point] = points.as_slice() {
+            let half = POINT_CROSS_SIZE / 2.0;
+            positions.push([point.x - half, point.y - half, 0.0]);
+            positions.push([point.x + half, point.y + half, 0.0]);
+            positions.push([point.x - half, point.y + half, 0.0]);
+            positions.push([point.x + half, point.y - half, 0.0]);
+        } else if points.len() > 1 {
+            let segments = if closed {
+                points.len()
+            } else {
+                points.len() - 1
+            };
+            for i in 0..segments {
+                let (start, end) = (points[i], points[(i + 1) % points.len()]);
+                positions.push([start.x, start.y, 0.0]);
+                positions.push([end.x, end.y, 0.0]);
+            }
+        } else {
+            return None;
+        }
+
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+        let colors = vec![[1.0; 4]; positions.len()];
+        let indices = (0..positions.len() as u32).collect();
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        mesh.set_attribute("Vertex_Position", VertexAttributeValues::Float3(positions));
+        mesh.set_attribute("Vertex_Uv", VertexAttributeValues::Float2(uvs));
+        mesh.set_attribute("Vertex_Color", VertexAttributeValues::Float4(colors));
+        mesh.set_indices(Some(Indices::U32(indices)));
+        Some(mesh)
+    }
+
+    /// Filled ellipse of unit width and height centered on the origin, made of `segments` triangles.
+    /// Scaled to an ellipse object's size when used as the mesh of its sprite.
+    #[cfg(not(feature = "headless"))]
+    pub fn unit_ellipse_mesh(segments: usize) -> Mesh {
+        let segments = segments.max(3);
+        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(segments + 1);
+        positions.push([0.0, 0.0, 0.0]);
+        for i in 0..segments {
+            let angle = i as f32 / segments as f32 * TAU;
+            positions.push([angle.cos() / 2.0, angle.sin() / 2.0, 0.0]);
+        }
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let uvs = positions
+            .iter()
+            .map(|position| [position[0] + 0.5, 0.5 - position[1]])
+            .collect::<Vec<_>>();
+        // a fan around the center vertex
+        let mut indices: Vec<u32> = Vec::with_capacity(segments * 3);
+        for i in 0..segments as u32 {
+            indices.extend_from_slice(&[0, i + 1, (i + 1) % segments as u32 + 1]);
+        }
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute("Vertex_Position", VertexAttributeValues::Float3(positions));
+        mesh.set_attribute("Vertex_Normal", VertexAttributeValues::Float3(normals));
+        mesh.set_attribute("Vertex_Uv", VertexAttributeValues::Float2(uvs));
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+
+    /// Top left corner and size of the shape, relative to the object position in tiled's y-down space.
+    pub fn local_bounds(&self) -> (Vec2, Vec2) {
+        match &self.shape {
+            tiled::ObjectShape::Rect { width, height }
+            | tiled::ObjectShape::Ellipse { width, height } => {
+                (Vec2::ZERO, Vec2::new(*width, *height))
+            }
+            tiled::ObjectShape::Polyline { points } | tiled::ObjectShape::Polygon { points } => {
+                let mut min = Vec2::splat(f32::MAX);
+                let mut max = Vec2::splat(f32::MIN);
+                for (x, y) in points.iter() {
+                    min = min.min(Vec2::new(*x, *y));
+                    max = max.max(Vec2::new(*x, *y));
+                }
+                if points.is_empty() {
+                    (Vec2::ZERO, Vec2::ZERO)
+                } else {
+                    (min, max - min)
+                }
+            }
+            tiled::ObjectShape::Point(_, _) => (Vec2::ZERO, Vec2::ZERO),
+        }
+    }
+
     pub fn dimensions(&self) -> Option<Vec2> {
         match self.shape {
             tiled::ObjectShape::Rect { width, height }
             | tiled::ObjectShape::Ellipse { width, height } => Some(Vec2::new(width, height)),
+            // debug boxes for polygons and polylines cover their bounds
             tiled::ObjectShape::Polyline { points: _ }
-            | tiled::ObjectShape::Polygon { points: _ }
-            | tiled::ObjectShape::Point(_, _) => Some(Vec2::splat(1.0)),
+            | tiled::ObjectShape::Polygon { points: _ } => {
+                Some(self.local_bounds().1.max(Vec2::splat(1.0)))
+            }
+            // points are markers at their exact position, without a size
+            tiled::ObjectShape::Point(_, _) => Some(Vec2::ZERO),
+        }
+    }
+}
+
+/// Marks the debug outline spawned as a child of shape objects, see `DebugConfig`.
+#[cfg(not(feature = "headless"))]
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct ObjectOutline;
+
+#[cfg(not(feature = "headless"))]
+#[derive(Bundle)]
+pub struct ObjectOutlineBundle {
+    pub outline: ObjectOutline,
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<ColorMaterial>,
+    pub main_pass: MainPass,
+    pub draw: Draw,
+    pub visible: Visible,
+    pub render_pipelines: RenderPipelines,
+    // bound by the tile map pipeline, outlines aren't tinted
+    pub tint: LayerTint,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+}
+
+#[cfg(not(feature = "headless"))]
+impl Default for ObjectOutlineBundle {
+    fn default() -> Self {
+        Self {
+            outline: ObjectOutline,
+            mesh: Handle::default(),
+            material: Handle::default(),
+            main_pass: MainPass,
+            draw: Default::default(),
+            visible: Visible {
+                is_transparent: true,
+                ..Default::default()
+            },
+            render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+                TILE_MAP_PIPELINE_HANDLE.typed(),
+            )]),
+            tint: Default::default(),
+            // just in front of the debug box
+            transform: Transform::from_xyz(0.0, 0.0, 0.001),
+            global_transform: Default::default(),
+        }
+    }
+}
+
+/// Marks object sprites whose tileset texture has not finished loading yet.
+#[cfg(not(feature = "headless"))]
+pub struct ObjectSpriteLoading;
+
+/// Reveals object sprites once their texture is loaded, so they never flash an unrelated tile.
+#[cfg(not(feature = "headless"))]
+pub fn show_loaded_object_sprites(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut query: Query<
+        (Entity, &Handle<TextureAtlas>, &Object, &mut Visible),
+        With<ObjectSpriteLoading>,
+    >,
+) {
+    for (entity, atlas_handle, object, mut visible) in query.iter_mut() {
+        let loaded = texture_atlases.get(atlas_handle).map_or(false, |atlas| {
+            asset_server.get_load_state(&atlas.texture) == bevy::asset::LoadState::Loaded
+        });
+        if loaded {
+            visible.is_visible = object.visible;
+            commands.entity(entity).remove::<ObjectSpriteLoading>();
+        }
+    }
+}
+
+/// Spawns the objects of the visible object groups of the maps being spawned, replacing those spawned before,
+/// and sends an `ObjectReadyEvent` for each. The objects of maps that aren't orthogonal are skipped with a warning,
+/// see `Object::transform_from_map`.
+// the map entity is only looked up for the atlases and debug config used with rendering
+#[cfg_attr(feature = "headless", allow(unused_variables))]
+pub fn spawn_map_objects(
+    mut commands: Commands,
+    object_callbacks: Res<ObjectTypeCallbacks>,
+    maps: Res<Assets<Map>>,
+    #[cfg(not(feature = "headless"))] mut meshes: ResMut<Assets<Mesh>>,
+    #[cfg(not(feature = "headless"))] mut materials: ResMut<Assets<ColorMaterial>>,
+    mut ready_events: EventWriter<ObjectReadyEvent>,
+    mut query: Query<(
+        Entity,
+        &Handle<Map>,
+        &DepthConfig,
+        &ShapeObjects,
+        &mut CreatedMapEntities,
+    )>,
+    #[cfg(not(feature = "headless"))] mut render_query: Query<(
+        &HashMap<u32, Handle<TextureAtlas>>,
+        &mut DebugConfig,
+    )>,
+) {
+    for (map_entity, map_handle, depth_config, shape_objects, mut created_entities) in
+        query.iter_mut()
+    {
+        let (placement, map) = match (created_entities.spawning, maps.get(map_handle)) {
+            (Some(placement), Some(map)) => (placement, map),
+            _ => continue,
+        };
+        #[cfg(not(feature = "headless"))]
+        let (texture_atlas_map, mut debug_config) = match render_query.get_mut(map_entity) {
+            Ok(components) => components,
+            Err(_) => continue,
+        };
+        #[cfg(not(feature = "headless"))]
+        debug_config.add_missing_assets(&mut materials, &mut meshes);
+
+        for entity in created_entities.object_entities.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+        if map.map.orientation != tiled::Orientation::Orthogonal {
+            if map
+                .groups
+                .iter()
+                .any(|group| group.visible && !group.objects.is_empty())
+            {
+                warn!(
+                    "Skipping the objects of a {:?} map, only objects of orthogonal maps are placed",
+                    map.map.orientation
+                );
+            }
+            continue;
         }
+        let tile_map_transform = &placement.content_transform;
+        for object_group in map.groups.iter() {
+            if !object_group.visible {
+                continue;
+            }
+
+            let mut object_entities: Vec<Entity> = Default::default();
+            // TODO: use object_group.name, colour (properties)
+            for object in object_group.objects.iter() {
+                let mut entity_commands =
+                    if object.is_shape() && *shape_objects == ShapeObjects::TriggerZone {
+                        object.spawn_trigger_zone(
+                            &mut commands,
+                            &map.map,
+                            map_handle.clone(),
+                            tile_map_transform,
+                            depth_config,
+                        )
+                    } else {
+                        #[cfg(not(feature = "headless"))]
+                        let sprite = object.spawn(
+                            &mut commands,
+                            object
+                                .tileset_gid
+                                .and_then(|tileset_gid| texture_atlas_map.get(&tileset_gid)),
+                            &map.map,
+                            map_handle.clone(),
+                            tile_map_transform,
+                            &debug_config,
+                            depth_config,
+                        );
+                        #[cfg(feature = "headless")]
+                        let sprite = object.spawn(
+                            &mut commands,
+                            &map.map,
+                            map_handle.clone(),
+                            tile_map_transform,
+                            depth_config,
+                        );
+                        sprite
+                    };
+                #[cfg(not(feature = "headless"))]
+                if debug_config.enabled {
+                    if let Some(outline_mesh) = object.outline_mesh() {
+                        let outline_bundle = ObjectOutlineBundle {
+                            mesh: meshes.add(outline_mesh),
+                            material: debug_config.outline_material.clone().unwrap(),
+                            visible: Visible {
+                                is_visible: object.visible,
+                                is_transparent: true,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        };
+                        entity_commands.with_children(|parent| {
+                            parent.spawn_bundle(outline_bundle);
+                        });
+                    }
+                }
+                if let Some(path) = object.path(&map.map, &placement.world_transform) {
+                    entity_commands.insert(path);
+                }
+                let object_transform =
+                    object.spawn_transform(&map.map, tile_map_transform, depth_config);
+                object_callbacks.run(&mut entity_commands, object, &object_transform);
+                let entity = entity_commands.id();
+                // when done spawning, fire event
+                ready_events.send(ObjectReadyEvent {
+                    entity,
+                    object_id: object.id,
+                    map_handle: map_handle.clone(),
+                    map_entity_option: Some(placement.root_entity),
+                });
+                object_entities.push(entity);
+            }
+
+            commands
+                .entity(placement.root_entity)
+                .push_children(&object_entities);
+            created_entities.object_entities.extend(object_entities);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{map::parse_test_map, Map};
+    use bevy::prelude::*;
+
+    // orthogonal map of 16px cells with a tileset of bottom left anchored 32x16 tiles and the given objects
+    fn map_with_objects(objects: &str) -> Map {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="orthogonal" renderorder="right-down" width="8" height="8" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="3">
+ <tileset firstgid="1" name="wide" tilewidth="32" tileheight="16" tilecount="4" columns="2" objectalignment="bottomleft">
+  <image source="wide.png" width="64" height="32"/>
+ </tileset>
+ <objectgroup id="1" name="objects">
+{}
+ </objectgroup>
+</map>
+"#,
+            objects
+        );
+        parse_test_map(&xml).unwrap()
+    }
+
+    #[test]
+    fn world_bounds_of_diagonally_flipped_tile_objects_are_turned() {
+        // gid 1 with the diagonal flip flag
+        let map = map_with_objects(
+            r#"  <object id="1" gid="1" x="32" y="64" width="32" height="16"/>
+  <object id="2" gid="536870913" x="32" y="64" width="32" height="16"/>"#,
+        );
+        let transform = Transform::from_scale(Vec3::new(2.0, 2.0, 1.0));
+        let bounds = |id: u32| {
+            map.object_by_id(id)
+                .unwrap()
+                .world_bounds(&map.map, &transform)
+                .unwrap()
+        };
+        let (plain, flipped) = (bounds(1), bounds(2));
+        assert_eq!(plain.right - plain.left, 64.0);
+        assert_eq!(plain.top - plain.bottom, 32.0);
+        assert_eq!(flipped.right - flipped.left, 32.0);
+        assert_eq!(flipped.top - flipped.bottom, 64.0);
+        // turned around the same center
+        assert_eq!(plain.left + plain.right, flipped.left + flipped.right);
+        assert_eq!(plain.top + plain.bottom, flipped.top + flipped.bottom);
+    }
+
+    #[test]
+    fn mirrored_tile_objects_keep_their_place() {
+        // gid 1 plain, flipped horizontally and flipped vertically, scaled to twice the tile size
+        let map = map_with_objects(
+            r#"  <object id="1" gid="1" x="32" y="64" width="64" height="32"/>
+  <object id="2" gid="2147483649" x="32" y="64" width="64" height="32"/>
+  <object id="3" gid="1073741825" x="32" y="64" width="64" height="32"/>"#,
+        );
+        let map_transform = map.center(Transform::from_scale(Vec3::new(3.0, 3.0, 1.0)));
+        let object = |id: u32| map.object_by_id(id).unwrap();
+        assert_eq!(object(1).alignment, crate::ObjectAlignment::BottomLeft);
+        let transform = |id: u32| {
+            object(id).spawn_transform(&map.map, &map_transform, &crate::DepthConfig::default())
+        };
+        assert_eq!(transform(2), transform(1));
+        assert_eq!(transform(3), transform(1));
+        assert_eq!(transform(1).scale, Vec3::new(6.0, 6.0, 1.0));
+        // the image is mirrored by the sprite, within the same rectangle
+        assert_eq!(object(1).sprite_flip(), (false, false));
+        assert_eq!(object(2).sprite_flip(), (true, false));
+        assert_eq!(object(3).sprite_flip(), (false, true));
+    }
+
+    #[test]
+    fn tile_objects_get_the_grid_of_their_tileset() {
+        let map = parse_test_map(include_str!("../tests/fixtures/iso-collection.tmx")).unwrap();
+        // the tree is the second tile of the collection, which has a tileset of its own once split
+        let tree = map.object_by_id(1).unwrap();
+        assert_eq!(tree.tileset_gid, Some(4));
+        assert_eq!(
+            tree.grid,
+            Some(super::TilesetGrid {
+                orientation: tiled::Orientation::Isometric,
+                size: Vec2::new(32.0, 16.0),
+            })
+        );
+        assert_eq!(map.object_by_id(2).unwrap().grid, None);
+    }
+
+    #[test]
+    fn objects_of_hex_maps_are_skipped() {
+        let xml = include_str!("../assets/hex-x-map.tmx").replace(
+            "</map>",
+            r#" <objectgroup id="2" name="objects">
+  <object id="1" x="28" y="24" width="16" height="16"/>
+ </objectgroup>
+</map>"#,
+        );
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<Map>()
+            .add_event::<crate::ObjectReadyEvent>()
+            .init_resource::<super::ObjectTypeCallbacks>()
+            .add_system(super::spawn_map_objects.system());
+        #[cfg(not(feature = "headless"))]
+        app.add_asset::<Mesh>().add_asset::<ColorMaterial>();
+        let map_handle = app
+            .world_mut()
+            .get_resource_mut::<Assets<Map>>()
+            .unwrap()
+            .add(parse_test_map(&xml).unwrap());
+        let root_entity = app.world_mut().spawn().id();
+        let map_entity = app
+            .world_mut()
+            .spawn()
+            .insert_bundle((
+                map_handle,
+                crate::DepthConfig::default(),
+                crate::ShapeObjects::default(),
+                crate::CreatedMapEntities {
+                    spawning: Some(crate::spawn::MapPlacement {
+                        root_entity,
+                        content_transform: Transform::default(),
+                        world_transform: Transform::default(),
+                    }),
+                    ..Default::default()
+                },
+            ))
+            .id();
+        #[cfg(not(feature = "headless"))]
+        app.world_mut().entity_mut(map_entity).insert_bundle((
+            bevy::utils::HashMap::<u32, Handle<TextureAtlas>>::default(),
+            crate::DebugConfig::default(),
+        ));
+        app.app.update();
+
+        let world = app.world_mut();
+        assert!(world
+            .get::<crate::CreatedMapEntities>(map_entity)
+            .unwrap()
+            .object_entities
+            .is_empty());
+        assert_eq!(world.query::<&super::Object>().iter(world).count(), 0);
     }
 }
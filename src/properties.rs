@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+use tiled::{Properties, PropertyValue};
+
+use crate::{Map, MapReadyEvent};
+
+/// Typed accessors for tiled custom properties.
+/// Each returns `None` when the key is missing or holds a different type.
+pub trait PropertiesExt {
+    fn get_int(&self, key: &str) -> Option<i32>;
+    fn get_float(&self, key: &str) -> Option<f32>;
+    fn get_bool(&self, key: &str) -> Option<bool>;
+    fn get_string(&self, key: &str) -> Option<&str>;
+    #[cfg(not(feature = "headless"))]
+    fn get_color(&self, key: &str) -> Option<Color>;
+}
+
+impl PropertiesExt for Properties {
+    fn get_int(&self, key: &str) -> Option<i32> {
+        match self.get(key) {
+            Some(PropertyValue::IntValue(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn get_float(&self, key: &str) -> Option<f32> {
+        match self.get(key) {
+            Some(PropertyValue::FloatValue(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key) {
+            Some(PropertyValue::BoolValue(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn get_string(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(PropertyValue::StringValue(value)) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(feature = "headless"))]
+    fn get_color(&self, key: &str) -> Option<Color> {
+        match self.get(key) {
+            // tiled stores colors as #AARRGGBB
+            Some(&PropertyValue::ColorValue(argb)) => Some(Color::rgba_u8(
+                (argb >> 16) as u8,
+                (argb >> 8) as u8,
+                argb as u8,
+                (argb >> 24) as u8,
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Custom properties of the map, inserted on the map bundle entity once the map is ready.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct MapProperties(#[reflect(ignore)] pub Properties);
+
+/// Custom properties of the layer a chunk entity was built from.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct LayerProperties(#[reflect(ignore)] pub Properties);
+
+/// Map custom properties whose name starts with the prefix passed to
+/// `TiledMapPlugin::map_properties_as_resource`, keyed by their name without the prefix.
+/// Filled in when a map becomes ready, properties of maps ready later replace those of the same name.
+#[derive(Default)]
+pub struct TiledMapProperties(pub Properties);
+
+/// Prefix of the map properties copied into `TiledMapProperties`.
+pub struct MapPropertyPrefix(pub String);
+
+pub fn insert_map_property_resources(
+    prefix: Res<MapPropertyPrefix>,
+    maps: Res<Assets<Map>>,
+    mut map_ready_events: EventReader<MapReadyEvent>,
+    mut map_properties: ResMut<TiledMapProperties>,
+) {
+    for event in map_ready_events.iter() {
+        let map = match maps.get(&event.map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        for (name, value) in map.map.properties.iter() {
+            if let Some(name) = name.strip_prefix(prefix.0.as_str()) {
+                map_properties.0.insert(name.to_string(), value.clone());
+            }
+        }
+    }
+}
+
+/// Class of the map set in Tiled 1.9 and later, inserted next to `MapProperties`.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct MapClass(pub String);
+
+/// Class of the layer a chunk entity was built from, set in Tiled 1.9 and later.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct LayerClass(pub String);
@@ -0,0 +1,609 @@
+#[cfg(not(feature = "headless"))]
+use bevy::asset::{AssetPath, LoadState};
+#[cfg(not(feature = "headless"))]
+use bevy::utils::HashMap;
+use bevy::{prelude::*, utils::HashSet};
+
+#[cfg(not(feature = "headless"))]
+use crate::{
+    CustomPipeline, DebugConfig, MapLayer, PremultipliedAlpha, StreamChunks, TilesetTextureArray,
+};
+use crate::{
+    DepthConfig, Map, MapAnchor, MapBounds, MapClass, MapProperties, SpawnTileEntities, YAxis,
+};
+
+/// Tags the root entity of a spawned map, the bundle entity or `TiledMapBundle::parent_option`, as soon as
+/// the map starts spawning. All chunks, tile entities and objects are its descendants, so transforming it
+/// moves, rotates or scales the entire map and despawning it recursively removes the map.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct MapRoot;
+
+/// What shape objects, those without a tile, are spawned as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect_value(Component, PartialEq)]
+pub enum ShapeObjects {
+    /// A sprite covering the shape, only visible with `DebugConfig::enabled`.
+    DebugBox,
+    /// An entity with a `TriggerZone` and no sprite, for triggers and regions.
+    TriggerZone,
+}
+
+impl Default for ShapeObjects {
+    fn default() -> Self {
+        ShapeObjects::DebugBox
+    }
+}
+
+/// Which tile layers of the map are spawned, by name or by index in `Map::map.layers` as in `LayerId`.
+/// Layers left out get no chunks or tile entities, their tiles stay in the `Map` data.
+#[cfg(not(feature = "headless"))]
+#[derive(Debug, Clone, PartialEq, Eq, Reflect)]
+#[reflect_value(Component, PartialEq)]
+pub enum LayerFilter {
+    All,
+    Names(Vec<String>),
+    Indices(Vec<usize>),
+}
+
+#[cfg(not(feature = "headless"))]
+impl LayerFilter {
+    pub fn includes(&self, layer: &MapLayer) -> bool {
+        match self {
+            LayerFilter::All => true,
+            LayerFilter::Names(names) => names.iter().any(|name| *name == layer.name),
+            LayerFilter::Indices(indices) => indices.contains(&layer.tiled_layer),
+        }
+    }
+}
+
+#[cfg(not(feature = "headless"))]
+impl Default for LayerFilter {
+    fn default() -> Self {
+        LayerFilter::All
+    }
+}
+
+/// When true, layers hidden in Tiled are spawned too, with invisible chunks, so they can be shown at runtime.
+/// Their tiles are part of the `Map` data either way.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct LoadHiddenLayers(pub bool);
+
+/// A bundle of tiled map entities.
+#[derive(Bundle)]
+pub struct TiledMapBundle {
+    pub map_asset: Handle<Map>,
+    /// Entity to parent the map content to, the bundle entity itself when `None`.
+    /// Either way the parent is tagged with `MapRoot`.
+    pub parent_option: Option<Entity>,
+    #[cfg(not(feature = "headless"))]
+    pub materials: HashMap<u32, Handle<ColorMaterial>>,
+    #[cfg(not(feature = "headless"))]
+    pub atlases: HashMap<u32, Handle<TextureAtlas>>,
+    pub origin: Transform,
+    pub global_transform: GlobalTransform,
+    pub anchor: MapAnchor,
+    pub y_axis: YAxis,
+    #[cfg(not(feature = "headless"))]
+    pub debug_config: DebugConfig,
+    #[cfg(not(feature = "headless"))]
+    pub texture_array: TilesetTextureArray,
+    #[cfg(not(feature = "headless"))]
+    pub stream_chunks: StreamChunks,
+    #[cfg(not(feature = "headless"))]
+    pub premultiplied_alpha: PremultipliedAlpha,
+    #[cfg(not(feature = "headless"))]
+    pub custom_pipeline: CustomPipeline,
+    pub depth_config: DepthConfig,
+    pub tile_entities: SpawnTileEntities,
+    pub load_hidden_layers: LoadHiddenLayers,
+    #[cfg(not(feature = "headless"))]
+    pub layer_filter: LayerFilter,
+    pub shape_objects: ShapeObjects,
+    pub created_entities: CreatedMapEntities,
+}
+
+impl Default for TiledMapBundle {
+    fn default() -> Self {
+        Self {
+            map_asset: Handle::default(),
+            parent_option: None,
+            #[cfg(not(feature = "headless"))]
+            materials: HashMap::default(),
+            #[cfg(not(feature = "headless"))]
+            atlases: HashMap::default(),
+            anchor: MapAnchor::default(),
+            y_axis: YAxis::default(),
+            origin: Transform::default(),
+            global_transform: GlobalTransform::default(),
+            #[cfg(not(feature = "headless"))]
+            debug_config: Default::default(),
+            #[cfg(not(feature = "headless"))]
+            texture_array: Default::default(),
+            #[cfg(not(feature = "headless"))]
+            stream_chunks: Default::default(),
+            #[cfg(not(feature = "headless"))]
+            premultiplied_alpha: Default::default(),
+            #[cfg(not(feature = "headless"))]
+            custom_pipeline: Default::default(),
+            depth_config: Default::default(),
+            tile_entities: Default::default(),
+            load_hidden_layers: Default::default(),
+            #[cfg(not(feature = "headless"))]
+            layer_filter: Default::default(),
+            shape_objects: Default::default(),
+            created_entities: Default::default(),
+        }
+    }
+}
+
+impl TiledMapBundle {
+    /// Bundle for a map with the default configuration, adjusted with the methods below,
+    /// e.g. `TiledMapBundle::new(asset_server.load("ortho-map.tmx")).centered().scaled(4.0)`.
+    pub fn new(map_asset: Handle<Map>) -> Self {
+        Self {
+            map_asset,
+            ..Default::default()
+        }
+    }
+
+    /// Centers the map on its origin.
+    pub fn centered(self) -> Self {
+        self.anchored(MapAnchor::Center)
+    }
+
+    /// Places the `anchor` point of the map at its origin.
+    pub fn anchored(mut self, anchor: MapAnchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// Sets which way y points in the map content, see `YAxis`.
+    pub fn with_y_axis(mut self, y_axis: YAxis) -> Self {
+        self.y_axis = y_axis;
+        self
+    }
+
+    /// Scales the map by `scale` in x and y.
+    pub fn scaled(mut self, scale: f32) -> Self {
+        self.origin.scale = Vec3::new(scale, scale, 1.0);
+        self
+    }
+
+    /// Moves the origin of the map to `position`.
+    pub fn at(mut self, position: Vec3) -> Self {
+        self.origin.translation = position;
+        self
+    }
+}
+
+/// Parent of a map's chunks and tile entities, under the map's root entity. Despawning it removes them all
+/// at once, which is how they are replaced when the map is modified or reloaded.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct MapChunkRoot;
+
+#[derive(Default, Debug)]
+pub struct CreatedMapEntities {
+    // where the map's content goes while the MapSpawnSystem::Spawn systems spawn it, see prepare_map_spawns
+    pub(crate) spawning: Option<MapPlacement>,
+    // parent of the chunk and tile entities, see MapChunkRoot
+    #[cfg(not(feature = "headless"))]
+    pub(crate) chunk_root: Option<Entity>,
+    pub(crate) object_entities: Vec<Entity>,
+    // maps layer id, tileset_gid and chunk coordinates to chunks spawned by stream_map_chunks
+    #[cfg(not(feature = "headless"))]
+    pub(crate) streamed_chunk_entities: HashMap<(usize, u32, usize, usize), Entity>,
+    #[cfg(not(feature = "headless"))]
+    pub(crate) streaming_started: bool,
+    // what the spawned entities were built from, to tell image-only changes from geometry changes
+    #[cfg(not(feature = "headless"))]
+    pub(crate) spawned_geometry: Option<u64>,
+    #[cfg(not(feature = "headless"))]
+    pub(crate) grid_entity: Option<Entity>,
+}
+
+// placement of a map's content, shared by the systems spawning it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MapPlacement {
+    // the map's root entity, see `TiledMapBundle::parent_option`
+    pub root_entity: Entity,
+    // transform of the content relative to the root entity, see `content_transform`
+    pub content_transform: Transform,
+    // transform of the content including the root entity's, see `world_map_transform`
+    pub world_transform: Transform,
+}
+
+/// Labels of the systems spawning the entities of loaded and modified maps, in the order they run.
+/// `Prepare` replaces the chunk root and refreshes materials, the `Spawn` systems each spawn one kind of entity,
+/// chunks, tile entities, objects or the debug grid, and `Finish` inserts the map's properties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemLabel)]
+pub enum MapSpawnSystem {
+    Prepare,
+    Spawn,
+    Finish,
+}
+
+/// Transform of the map content relative to its root entity. The bundle entity already carries
+/// `origin` as its transform, while content under a passed-in parent gets it applied directly.
+pub(crate) fn content_transform(
+    map: &Map,
+    (anchor, y_axis): (&MapAnchor, &YAxis),
+    origin: &Transform,
+    optional_parent: &Option<Entity>,
+) -> Transform {
+    let origin = match optional_parent {
+        Some(_) => origin.clone(),
+        None => Transform::identity(),
+    };
+    map.placement(anchor, y_axis, origin)
+}
+
+// transform of the map content including its root entity's, which is only known for the bundle entity
+pub(crate) fn world_map_transform(
+    origin: &Transform,
+    tile_map_transform: &Transform,
+    optional_parent: &Option<Entity>,
+) -> Transform {
+    match optional_parent {
+        Some(_) => tile_map_transform.clone(),
+        None => *origin * *tile_map_transform,
+    }
+}
+
+// despawns the chunks and tile entities of the map and spawns an empty chunk root for the new ones
+#[cfg(not(feature = "headless"))]
+fn replace_chunk_root(
+    commands: &mut Commands,
+    root_entity: Entity,
+    created_entities: &mut CreatedMapEntities,
+) -> Entity {
+    if let Some(chunk_root) = created_entities.chunk_root.take() {
+        commands.entity(chunk_root).despawn_recursive();
+    }
+    let chunk_root = commands
+        .spawn_bundle((
+            MapChunkRoot,
+            Transform::identity(),
+            GlobalTransform::default(),
+        ))
+        .id();
+    commands.entity(root_entity).push_children(&[chunk_root]);
+    created_entities.chunk_root = Some(chunk_root);
+    chunk_root
+}
+
+pub(crate) fn changed_maps(map_events: &mut EventReader<AssetEvent<Map>>) -> HashSet<Handle<Map>> {
+    let mut changed_maps = HashSet::<Handle<Map>>::default();
+    for event in map_events.iter() {
+        match event {
+            AssetEvent::Created { handle } => {
+                changed_maps.insert(handle.clone());
+            }
+            AssetEvent::Modified { handle } => {
+                changed_maps.insert(handle.clone());
+            }
+            AssetEvent::Removed { handle } => {
+                // if mesh was modified and removed in the same update, ignore the modification
+                // events are ordered so future modification events are ok
+                changed_maps.remove(handle);
+            }
+        }
+    }
+    changed_maps
+}
+
+/// Records where the content of the changed maps goes for the `MapSpawnSystem::Spawn` systems, tags their root
+/// entity with `MapRoot` and `MapBounds` and replaces their chunk root. With rendering, map entities whose map
+/// changed only in its tileset images keep their entities, `update_tileset_materials` points them at the new
+/// images.
+// the maps are only changed for their meshes, which are only built with rendering
+#[cfg_attr(feature = "headless", allow(unused_mut))]
+pub fn prepare_map_spawns(
+    mut commands: Commands,
+    mut map_events: EventReader<AssetEvent<Map>>,
+    mut maps: ResMut<Assets<Map>>,
+    mut query: Query<(
+        Entity,
+        &Handle<Map>,
+        &Option<Entity>,
+        &Transform,
+        &MapAnchor,
+        &YAxis,
+        &mut CreatedMapEntities,
+    )>,
+) {
+    let changed_maps = changed_maps(&mut map_events);
+
+    for (map_entity, map_handle, optional_parent, origin, anchor, y_axis, mut created_entities) in
+        query.iter_mut()
+    {
+        if !changed_maps.contains(map_handle) {
+            continue;
+        }
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+
+        #[cfg(not(feature = "headless"))]
+        {
+            // when only tileset images changed, the refreshed materials are enough
+            if created_entities.spawned_geometry == Some(map.geometry_hash) {
+                // the reloaded map built meshes nothing will take, getting it mutably sends one more
+                // AssetEvent::Modified, which finds the meshes gone and stops here
+                if !map.meshes.is_empty() {
+                    maps.get_mut(map_handle).unwrap().meshes.clear();
+                }
+                continue;
+            }
+            created_entities.spawned_geometry = Some(map.geometry_hash);
+
+            // streamed chunks are despawned with the chunk root and spawned again by stream_map_chunks
+            created_entities.streamed_chunk_entities.clear();
+            created_entities.streaming_started = false;
+        }
+
+        let root_entity = optional_parent.unwrap_or(map_entity);
+        #[cfg(not(feature = "headless"))]
+        replace_chunk_root(&mut commands, root_entity, &mut created_entities);
+        let content_transform = content_transform(map, (anchor, y_axis), origin, optional_parent);
+        let world_transform = world_map_transform(origin, &content_transform, optional_parent);
+        commands
+            .entity(root_entity)
+            .insert_bundle((MapRoot, MapBounds(map.bounds(&world_transform))));
+        created_entities.spawning = Some(MapPlacement {
+            root_entity,
+            content_transform,
+            world_transform,
+        });
+    }
+}
+
+/// Inserts the properties and class of the maps spawned by the `MapSpawnSystem::Spawn` systems.
+/// With rendering `MapReadyEvent` is sent by `track_map_load_progress` once all images have loaded,
+/// without it right away.
+pub fn finish_map_spawns(
+    mut commands: Commands,
+    maps: Res<Assets<Map>>,
+    #[cfg(feature = "headless")] mut map_ready_events: EventWriter<MapReadyEvent>,
+    mut query: Query<(Entity, &Handle<Map>, &mut CreatedMapEntities)>,
+) {
+    for (map_entity, map_handle, mut created_entities) in query.iter_mut() {
+        let placement = match created_entities.spawning.take() {
+            Some(placement) => placement,
+            None => continue,
+        };
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        commands.entity(map_entity).insert_bundle((
+            MapProperties(map.map.properties.clone()),
+            MapClass(map.class.clone()),
+        ));
+        #[cfg(not(feature = "headless"))]
+        commands
+            .entity(map_entity)
+            .insert(MapLoading::new(placement.root_entity));
+        #[cfg(feature = "headless")]
+        map_ready_events.send(MapReadyEvent {
+            map_handle: map_handle.clone(),
+            map_entity_option: Some(placement.root_entity),
+        });
+    }
+}
+
+/// Handles `ReloadMap` events by marking the map modified, which makes the `MapSpawnSystem` systems
+/// despawn and spawn its entities again like on a hot reload.
+pub fn reload_maps(
+    mut reload_events: EventReader<ReloadMap>,
+    mut maps: ResMut<Assets<Map>>,
+    #[cfg(not(feature = "headless"))] mut query: Query<(&Handle<Map>, &mut CreatedMapEntities)>,
+) {
+    for ReloadMap(map_handle) in reload_events.iter() {
+        // getting the map mutably sends AssetEvent::Modified
+        if maps.get_mut(map_handle).is_none() {
+            continue;
+        }
+        #[cfg(not(feature = "headless"))]
+        for (handle, mut created_entities) in query.iter_mut() {
+            if handle == map_handle {
+                // respawn even though the geometry didn't change
+                created_entities.spawned_geometry = None;
+            }
+        }
+    }
+}
+
+/// Present on a map bundle entity while the images the map depends on are loading.
+#[cfg(not(feature = "headless"))]
+pub struct MapLoading {
+    // the map's root entity, sent with MapReadyEvent
+    root_entity: Entity,
+    loaded: Option<usize>,
+    // first gids of the tilesets TilesetReadyEvent was sent for
+    ready_tilesets: HashSet<u32>,
+}
+
+#[cfg(not(feature = "headless"))]
+impl MapLoading {
+    fn new(root_entity: Entity) -> Self {
+        Self {
+            root_entity,
+            loaded: None,
+            ready_tilesets: HashSet::default(),
+        }
+    }
+}
+
+/// Sends load progress of spawned maps, TilesetReadyEvent as each tileset image loads,
+/// and MapReadyEvent once all their images are loaded.
+#[cfg(not(feature = "headless"))]
+pub fn track_map_load_progress(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<Map>>,
+    mut progress_events: EventWriter<MapLoadProgressEvent>,
+    mut tileset_ready_events: EventWriter<TilesetReadyEvent>,
+    mut map_ready_events: EventWriter<MapReadyEvent>,
+    mut query: Query<(
+        Entity,
+        &Handle<Map>,
+        &StreamChunks,
+        &CreatedMapEntities,
+        &mut MapLoading,
+    )>,
+) {
+    for (entity, map_handle, stream_chunks, created_entities, mut loading) in query.iter_mut() {
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        // streamed maps are ready once the chunks around the camera are spawned
+        let waiting_for_chunks = stream_chunks.0.is_some() && !created_entities.streaming_started;
+        let total = map.asset_dependencies.len();
+        let loaded = map
+            .asset_dependencies
+            .iter()
+            .filter(|path| {
+                asset_server.get_load_state(AssetPath::from(path.as_path())) == LoadState::Loaded
+            })
+            .count();
+
+        for tileset in map.map.tilesets.iter() {
+            if loading.ready_tilesets.contains(&tileset.first_gid) {
+                continue;
+            }
+            let texture_path = map.image_path(tileset.images.first().unwrap());
+            if asset_server.get_load_state(AssetPath::from(texture_path.as_path()))
+                == LoadState::Loaded
+            {
+                loading.ready_tilesets.insert(tileset.first_gid);
+                tileset_ready_events.send(TilesetReadyEvent {
+                    map_handle: map_handle.clone(),
+                    first_gid: tileset.first_gid,
+                    texture: asset_server.load(texture_path),
+                });
+            }
+        }
+
+        if loading.loaded != Some(loaded) {
+            loading.loaded = Some(loaded);
+            progress_events.send(MapLoadProgressEvent {
+                map_handle: map_handle.clone(),
+                loaded,
+                total,
+            });
+        }
+        if loaded == total && !waiting_for_chunks {
+            commands.entity(entity).remove::<MapLoading>();
+            map_ready_events.send(MapReadyEvent {
+                map_handle: map_handle.clone(),
+                map_entity_option: Some(loading.root_entity),
+            });
+        }
+    }
+}
+
+// events fired when entity has been created
+// `map_entity_option` is the map's root entity, see `TiledMapBundle::parent_option`
+
+pub struct ObjectReadyEvent {
+    pub entity: Entity,
+    pub object_id: u32,
+    pub map_handle: Handle<Map>,
+    pub map_entity_option: Option<Entity>,
+}
+
+pub struct MapReadyEvent {
+    pub map_handle: Handle<Map>,
+    pub map_entity_option: Option<Entity>,
+}
+
+/// Fired for each tileset of a spawned map once its image has loaded, before MapReadyEvent.
+#[cfg(not(feature = "headless"))]
+pub struct TilesetReadyEvent {
+    pub map_handle: Handle<Map>,
+    pub first_gid: u32,
+    pub texture: Handle<Texture>,
+}
+
+/// Send to respawn every map entity using the map from its loaded data, e.g. to restart a level.
+/// The existing chunks and objects are despawned first; the file isn't read again.
+pub struct ReloadMap(pub Handle<Map>);
+
+pub struct MapLoadProgressEvent {
+    pub map_handle: Handle<Map>,
+    pub loaded: usize,
+    pub total: usize,
+}
+
+impl MapLoadProgressEvent {
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.loaded as f32 / self.total as f32
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "headless")))]
+mod tests {
+    use super::*;
+    use crate::map::parse_test_map;
+
+    #[test]
+    fn image_only_changes_drop_the_rebuilt_meshes() {
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<Map>()
+            .add_system(prepare_map_spawns.system());
+        let map = parse_test_map(include_str!("../tests/fixtures/single-tile.tmx")).unwrap();
+        let map_handle = app
+            .world_mut()
+            .get_resource_mut::<Assets<Map>>()
+            .unwrap()
+            .add(map);
+        let map_entity = app
+            .world_mut()
+            .spawn()
+            .insert_bundle((
+                map_handle.clone(),
+                Option::<Entity>::None,
+                Transform::default(),
+                MapAnchor::default(),
+                YAxis::default(),
+                CreatedMapEntities::default(),
+            ))
+            .id();
+        // asset events are sent after the systems that read them
+        app.app.update();
+        app.app.update();
+        let chunk_root = app
+            .world()
+            .get::<CreatedMapEntities>(map_entity)
+            .unwrap()
+            .chunk_root;
+        assert!(chunk_root.is_some());
+
+        // like a hot reload of the same geometry, the meshes weren't taken by spawning chunks here
+        let maps = app.world().get_resource::<Assets<Map>>().unwrap();
+        assert!(!maps.get(&map_handle).unwrap().meshes.is_empty());
+        app.world_mut()
+            .get_resource_mut::<Assets<Map>>()
+            .unwrap()
+            .get_mut(&map_handle);
+        for _ in 0..4 {
+            app.app.update();
+        }
+
+        let maps = app.world().get_resource::<Assets<Map>>().unwrap();
+        assert!(maps.get(&map_handle).unwrap().meshes.is_empty());
+        let created_entities = app.world().get::<CreatedMapEntities>(map_entity).unwrap();
+        assert_eq!(created_entities.chunk_root, chunk_root);
+    }
+}
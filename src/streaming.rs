@@ -0,0 +1,165 @@
+use bevy::{
+    prelude::*,
+    render::{camera::Camera, render_graph::base::camera::CAMERA_2D},
+    utils::HashMap,
+};
+
+use crate::{
+    spawn::content_transform,
+    tile_map_pipelines,
+    view::{layer_transform, layer_visible},
+    ChunkBundle, ChunkInfo, ChunkTileset, CreatedMapEntities, CustomPipeline, DepthConfig,
+    LayerBlend, LayerClass, LayerFilter, LayerId, LayerProperties, LoadHiddenLayers, Map,
+    MapAnchor, PremultipliedAlpha, TilesetId, YAxis,
+};
+
+/// When set, only the chunks within this world distance of the 2d camera are spawned,
+/// and chunks are spawned and despawned as the camera moves. Meant for large infinite maps.
+/// Chunks are drawn with a material per tileset, `TilesetTextureArray` is ignored.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct StreamChunks(pub Option<f32>);
+
+/// Spawns the chunks within `StreamChunks` distance of the 2d camera and despawns the others.
+pub fn stream_map_chunks(
+    mut commands: Commands,
+    maps: Res<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut query: Query<(
+        &Handle<Map>,
+        &Option<Entity>,
+        &HashMap<u32, Handle<ColorMaterial>>,
+        &Transform,
+        (&MapAnchor, &YAxis),
+        &DepthConfig,
+        &StreamChunks,
+        &LoadHiddenLayers,
+        &LayerFilter,
+        (&PremultipliedAlpha, &CustomPipeline),
+        &mut CreatedMapEntities,
+    )>,
+) {
+    let camera_pos = match camera_query
+        .iter()
+        .find(|(camera, _)| camera.name.as_deref() == Some(CAMERA_2D))
+    {
+        Some((_, camera_transform)) => camera_transform.translation.truncate(),
+        None => return,
+    };
+
+    for (
+        map_handle,
+        optional_parent,
+        materials_map,
+        origin,
+        placement,
+        depth_config,
+        stream_chunks,
+        load_hidden_layers,
+        layer_filter,
+        (premultiplied_alpha, custom_pipeline),
+        mut created_entities,
+    ) in query.iter_mut()
+    {
+        let radius = match stream_chunks.0 {
+            Some(radius) => radius,
+            None => continue,
+        };
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        // materials and the chunk root are created when the map is spawned, see MapSpawnSystem
+        let chunk_root = match created_entities.chunk_root {
+            Some(chunk_root) if !materials_map.is_empty() => chunk_root,
+            _ => continue,
+        };
+
+        let tile_map_transform = content_transform(map, placement, origin, optional_parent);
+        // the bundle entity's transform is the origin, a passed-in parent isn't accounted for
+        let root_transform = match optional_parent {
+            Some(_) => Transform::identity(),
+            None => origin.clone(),
+        };
+
+        let mut chunk_entities = Vec::new();
+        for (layer_id, layer) in map.layers.iter().enumerate() {
+            if !layer_filter.includes(layer) || (!layer.visible && !load_hidden_layers.0) {
+                continue;
+            }
+            for tileset_layer in layer.tileset_layers.iter() {
+                let material_handle = match materials_map.get(&tileset_layer.tileset_guid) {
+                    Some(material_handle) => material_handle,
+                    None => continue,
+                };
+                let layer_transform =
+                    layer_transform(&tile_map_transform, layer, tileset_layer, depth_config);
+                let scale = layer_transform.scale.max_element();
+
+                for (x, chunks_x) in tileset_layer.chunks.iter().enumerate() {
+                    for (y, chunk) in chunks_x.iter().enumerate() {
+                        // chunk without tiles of this tileset
+                        if chunk.bounds == Vec4::ZERO {
+                            continue;
+                        }
+                        let key = (layer_id, tileset_layer.tileset_guid, x, y);
+                        let min = Vec2::new(chunk.bounds.x, chunk.bounds.y);
+                        let max = Vec2::new(chunk.bounds.z, chunk.bounds.w);
+                        let chunk_center = (root_transform * layer_transform)
+                            .mul_vec3(((min + max) / 2.0).extend(0.0))
+                            .truncate();
+                        let reach = radius + (max - min).length() / 2.0 * scale;
+                        let in_range = chunk_center.distance(camera_pos) <= reach;
+
+                        if !in_range {
+                            if let Some(entity) =
+                                created_entities.streamed_chunk_entities.remove(&key)
+                            {
+                                commands.entity(entity).despawn_recursive();
+                            }
+                            continue;
+                        }
+                        if created_entities.streamed_chunk_entities.contains_key(&key) {
+                            continue;
+                        }
+                        if let Some(mesh) = chunk.build_uv_mesh(tileset_layer.tileset_guid) {
+                            let chunk_entity = commands
+                                .spawn_bundle(ChunkBundle {
+                                    material: material_handle.clone(),
+                                    mesh: meshes.add(mesh),
+                                    map_parent: map_handle.clone(),
+                                    render_pipeline: tile_map_pipelines(
+                                        premultiplied_alpha,
+                                        custom_pipeline,
+                                        LayerBlend::from_properties(&layer.properties),
+                                    ),
+                                    transform: layer_transform
+                                        * Transform::from_translation(chunk.origin().extend(0.0)),
+                                    visible: layer_visible(layer),
+                                    ..Default::default()
+                                })
+                                .insert(LayerProperties(layer.properties.clone()))
+                                .insert(LayerClass(layer.class.clone()))
+                                .insert(ChunkInfo {
+                                    layer: LayerId(layer.tiled_layer),
+                                    layer_name: layer.name.clone(),
+                                    tileset: ChunkTileset::Single(TilesetId(
+                                        tileset_layer.tileset_guid,
+                                    )),
+                                })
+                                .id();
+                            created_entities
+                                .streamed_chunk_entities
+                                .insert(key, chunk_entity);
+                            chunk_entities.push(chunk_entity);
+                        }
+                    }
+                }
+            }
+        }
+
+        commands.entity(chunk_root).push_children(&chunk_entities);
+        created_entities.streaming_started = true;
+    }
+}
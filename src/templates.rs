@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{log::warn, math::Vec2, utils::HashMap};
+
+use crate::{
+    tmx::{referenced_path, template_object, TemplateInstance, TmxDocument},
+    Object, ObjectGroup,
+};
+
+// the object of a template file, with its gid already mapped to the map's tilesets
+struct Template {
+    object: tiled::Object,
+    class: Option<String>,
+}
+
+/// Fills in the objects created from Tiled templates (.tx files), which tiled doesn't resolve.
+/// Attributes set on an instance override the template's, and instance properties are merged over
+/// the template's. Templates are taken from `files` by asset path. Returns the template files the map uses.
+pub(crate) fn apply_templates(
+    document: &TmxDocument,
+    map_path: &Path,
+    files: &HashMap<PathBuf, Vec<u8>>,
+    groups: &mut [ObjectGroup],
+    tile_gids: &HashMap<u32, u32>,
+) -> Vec<PathBuf> {
+    if document.templates.is_empty() {
+        return Vec::new();
+    }
+    let map_tilesets = document.external_tilesets(map_path);
+
+    let mut templates: HashMap<PathBuf, Option<Template>> = HashMap::default();
+    for object in groups.iter_mut().flat_map(|group| group.objects.iter_mut()) {
+        let instance = match document.templates.get(&object.id) {
+            Some(instance) => instance,
+            None => continue,
+        };
+        let path = match referenced_path(map_path, &instance.template) {
+            Some(path) => path,
+            None => {
+                warn!(
+                    "Object template \"{}\" is outside the asset folder",
+                    instance.template
+                );
+                continue;
+            }
+        };
+        let template = templates.entry(path.clone()).or_insert_with(|| {
+            let template = files
+                .get(&path)
+                .and_then(|bytes| load_template(&path, bytes, &map_tilesets));
+            if template.is_none() {
+                warn!("Couldn't load object template {:?}", path);
+            }
+            template
+        });
+        if let Some(template) = template {
+            merge_template(object, instance, template, tile_gids);
+        }
+    }
+
+    let mut paths: Vec<PathBuf> = templates.into_iter().map(|(path, _)| path).collect();
+    paths.sort();
+    paths
+}
+
+fn load_template(
+    path: &Path,
+    bytes: &[u8],
+    map_tilesets: &HashMap<PathBuf, u32>,
+) -> Option<Template> {
+    let template = template_object(bytes).ok()??;
+    // tiled only parses objects within a map
+    let map_xml = format!(
+        "<?xml version=\"1.0\"?><map version=\"1.0\" orientation=\"orthogonal\" width=\"1\" height=\"1\" tilewidth=\"1\" tileheight=\"1\"><objectgroup>{}</objectgroup></map>",
+        template.xml
+    );
+    let mut object = tiled::parse(map_xml.as_bytes())
+        .ok()?
+        .object_groups
+        .pop()?
+        .objects
+        .pop()?;
+
+    // tile templates refer to their own tileset, which must also be used by the map
+    if object.gid != 0 {
+        let (template_first_gid, source) = template.tileset?;
+        let map_first_gid = map_tilesets.get(&referenced_path(path, &source)?)?;
+        let flags = object.gid & 0xE0000000;
+        let tile_id = (object.gid & !0xE0000000) - template_first_gid;
+        object.gid = (map_first_gid + tile_id) | flags;
+    }
+    Some(Template {
+        object,
+        class: template.class,
+    })
+}
+
+fn merge_template(
+    object: &mut Object,
+    instance: &TemplateInstance,
+    template: &Template,
+    tile_gids: &HashMap<u32, u32>,
+) {
+    let overrides = |name: &str| instance.attributes.contains(name);
+    let defaults = Object::new(&template.object);
+
+    if !overrides("name") {
+        object.name = defaults.name.clone();
+    }
+    if !overrides("class") {
+        if let Some(class) = &template.class {
+            object.class = class.clone();
+        }
+    }
+    if !overrides("type") && !overrides("class") {
+        object.obj_type = match (&template.class, defaults.obj_type.is_empty()) {
+            (Some(class), true) => class.clone(),
+            _ => defaults.obj_type.clone(),
+        };
+    }
+    if !overrides("visible") {
+        object.visible = defaults.visible;
+    }
+    if !overrides("gid") {
+        object.gid = defaults.gid;
+        object.flip_h = defaults.flip_h;
+        object.flip_v = defaults.flip_v;
+        object.flip_d = defaults.flip_d;
+        object.set_tile_ids(tile_gids);
+    }
+    let size = Vec2::new(
+        if overrides("width") {
+            object.size.x
+        } else {
+            defaults.size.x
+        },
+        if overrides("height") {
+            object.size.y
+        } else {
+            defaults.size.y
+        },
+    );
+    object.size = size;
+    if !instance.has_children {
+        object.shape = match &defaults.shape {
+            tiled::ObjectShape::Rect { .. } => tiled::ObjectShape::Rect {
+                width: size.x,
+                height: size.y,
+            },
+            tiled::ObjectShape::Ellipse { .. } => tiled::ObjectShape::Ellipse {
+                width: size.x,
+                height: size.y,
+            },
+            shape => shape.clone(),
+        };
+    }
+    for (name, value) in defaults.props.iter() {
+        if !object.props.contains_key(name) {
+            object.props.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use bevy::{math::Vec2, utils::HashMap};
+
+    use crate::{tmx::TmxDocument, Map, PropertyValue};
+
+    #[test]
+    fn instances_override_the_template_they_are_read_from() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.9" orientation="orthogonal" width="4" height="4" tilewidth="16" tileheight="16" nextobjectid="3">
+ <objectgroup id="1" name="objects">
+  <object id="1" template="../templates/door.tx" name="front" x="16" y="32"/>
+  <object id="2" template="../templates/door.tx" x="48" y="32" width="8">
+   <properties>
+    <property name="locked" type="bool" value="false"/>
+   </properties>
+  </object>
+ </objectgroup>
+</map>
+"#;
+        let template = r#"<?xml version="1.0" encoding="UTF-8"?>
+<template>
+ <object name="door" class="door" width="16" height="24">
+  <properties>
+   <property name="locked" type="bool" value="true"/>
+   <property name="key" value="gold"/>
+  </properties>
+ </object>
+</template>
+"#;
+        let map_path = Path::new("maps/level.tmx");
+        let mut files: HashMap<PathBuf, Vec<u8>> = HashMap::default();
+        files.insert("templates/door.tx".into(), template.as_bytes().to_vec());
+        let document = TmxDocument::parse(xml.as_bytes()).unwrap();
+        let map = Map::from_document(map_path, xml.as_bytes().to_vec(), document, &files).unwrap();
+
+        assert_eq!(map.template_paths, vec![PathBuf::from("templates/door.tx")]);
+        let objects = &map.groups[0].objects;
+        assert_eq!(objects[0].name, "front");
+        assert_eq!(objects[0].class, "door");
+        assert_eq!(objects[0].obj_type, "door");
+        assert_eq!(objects[0].size, Vec2::new(16.0, 24.0));
+        assert_eq!(objects[1].name, "door");
+        assert_eq!(objects[1].size, Vec2::new(8.0, 24.0));
+        assert_eq!(
+            objects[1].props.get("locked"),
+            Some(&PropertyValue::BoolValue(false))
+        );
+        assert_eq!(
+            objects[1].props.get("key"),
+            Some(&PropertyValue::StringValue("gold".to_string()))
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+#[cfg(not(feature = "headless"))]
+use bevy::{
+    render::{camera::Camera, render_graph::base::camera::CAMERA_2D},
+    utils::HashMap,
+};
+use tiled::Properties;
+
+#[cfg(not(feature = "headless"))]
+use crate::{
+    screen_to_world,
+    spawn::{content_transform, world_map_transform},
+    view::layer_transform,
+    ChunkInfo, CreatedMapEntities, DepthConfig, LayerFilter, LoadHiddenLayers, MapAnchor, MapLayer,
+    YAxis,
+};
+use crate::{LayerId, Map};
+
+/// When true, an entity with `TilePos`, `TileGid` and `TileFlip` is spawned for every tile, on top of the
+/// chunk meshes. Useful when tiles are gameplay entities, at the cost of many more entities on large maps.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct SpawnTileEntities(pub bool);
+
+/// Hook invoked for every spawned tile entity with its gid and the tile's properties.
+pub type TileSpawnFn = Arc<dyn Fn(&mut EntityCommands, u32, &Properties) + Send + Sync>;
+
+/// Tile entity hooks, registered through `TiledMapPlugin::with_tile_hook`.
+#[derive(Default, Clone)]
+pub struct TileHooks {
+    hooks: Vec<TileSpawnFn>,
+}
+
+impl TileHooks {
+    pub fn register(&mut self, hook: TileSpawnFn) {
+        self.hooks.push(hook);
+    }
+
+    pub fn run(&self, entity_commands: &mut EntityCommands, gid: u32, properties: &Properties) {
+        for hook in self.hooks.iter() {
+            hook(entity_commands, gid, properties);
+        }
+    }
+}
+
+/// Tile coordinates of a tile entity, see `SpawnTileEntities`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct TilePos(pub IVec2);
+
+/// Gid of a tile entity, see `SpawnTileEntities`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct TileGid(pub u32);
+
+/// Flip flags of a tile, see `Map::tile_flip`. Inserted on tile entities, see `SpawnTileEntities`.
+/// Tiled flips diagonally first, swapping x and y, then horizontally, then vertically.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct TileFlip {
+    pub horizontal: bool,
+    pub vertical: bool,
+    pub diagonal: bool,
+}
+
+impl From<&tiled::LayerTile> for TileFlip {
+    fn from(tile: &tiled::LayerTile) -> Self {
+        TileFlip {
+            horizontal: tile.flip_h,
+            vertical: tile.flip_v,
+            diagonal: tile.flip_d,
+        }
+    }
+}
+
+/// Spawns the tile entities of the maps being spawned with `SpawnTileEntities`, under their chunk root.
+#[cfg(not(feature = "headless"))]
+pub fn spawn_tile_entities(
+    mut commands: Commands,
+    tile_hooks: Res<TileHooks>,
+    maps: Res<Assets<Map>>,
+    query: Query<(
+        &Handle<Map>,
+        &DepthConfig,
+        &SpawnTileEntities,
+        &LoadHiddenLayers,
+        &LayerFilter,
+        &CreatedMapEntities,
+    )>,
+) {
+    for (
+        map_handle,
+        depth_config,
+        tile_entities,
+        load_hidden_layers,
+        layer_filter,
+        created_entities,
+    ) in query.iter()
+    {
+        let (placement, chunk_root, map) = match (
+            created_entities.spawning,
+            created_entities.chunk_root,
+            maps.get(map_handle),
+        ) {
+            (Some(placement), Some(chunk_root), Some(map)) if tile_entities.0 => {
+                (placement, chunk_root, map)
+            }
+            _ => continue,
+        };
+
+        let no_properties = Properties::default();
+        let mut entities = Vec::new();
+        for layer in map.layers.iter() {
+            if !layer_filter.includes(layer) || !(layer.visible || load_hidden_layers.0) {
+                continue;
+            }
+            for tileset_layer in layer.tileset_layers.iter() {
+                let layer_transform = layer_transform(
+                    &placement.content_transform,
+                    layer,
+                    tileset_layer,
+                    depth_config,
+                );
+                // data-only entity per tile, rendering still goes through the chunk meshes
+                for tile in tileset_layer
+                    .chunks
+                    .iter()
+                    .flatten()
+                    .flat_map(|chunk| chunk.tiles.iter().flatten())
+                {
+                    if tile.tile_id == 0 {
+                        continue;
+                    }
+                    let center =
+                        Vec2::new(tile.vertex.x + tile.vertex.z, tile.vertex.y + tile.vertex.w)
+                            / 2.0;
+                    let mut tile_commands = commands.spawn_bundle((
+                        TilePos(IVec2::new(tile.pos.x as i32, tile.pos.y as i32)),
+                        TileGid(tile.tile_id),
+                        TileFlip {
+                            horizontal: tile.flip_h,
+                            vertical: tile.flip_v,
+                            diagonal: tile.flip_d,
+                        },
+                        map_handle.clone(),
+                        layer_transform * Transform::from_translation(center.extend(0.0)),
+                        GlobalTransform::default(),
+                    ));
+                    tile_hooks.run(
+                        &mut tile_commands,
+                        tile.tile_id,
+                        map.tile_properties(tile.tile_id).unwrap_or(&no_properties),
+                    );
+                    entities.push(tile_commands.id());
+                }
+            }
+        }
+        commands.entity(chunk_root).push_children(&entities);
+    }
+}
+
+/// Fired when a tile is clicked, see `TiledMapPlugin::send_tile_clicks`.
+pub struct TileClicked {
+    pub map_handle: Handle<Map>,
+    pub layer: LayerId,
+    pub tile: IVec2,
+    pub gid: u32,
+    pub world_pos: Vec2,
+}
+
+/// Sends a `TileClicked` event for the top-most visible tile under the cursor on left click,
+/// enabled with `TiledMapPlugin::send_tile_clicks`. Only the layers spawned for the map, see `LayerFilter`
+/// and `LoadHiddenLayers`, whose chunks are visible can be clicked.
+#[cfg(not(feature = "headless"))]
+pub fn send_tile_clicked_events(
+    windows: Res<Windows>,
+    mouse_input: Res<Input<MouseButton>>,
+    maps: Res<Assets<Map>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    map_query: Query<(
+        &Handle<Map>,
+        &Option<Entity>,
+        &Transform,
+        (&MapAnchor, &YAxis),
+        &LayerFilter,
+        &LoadHiddenLayers,
+        &CreatedMapEntities,
+    )>,
+    parent_query: Query<&GlobalTransform>,
+    chunk_query: Query<(&Parent, &ChunkInfo, &Visible)>,
+    mut clicked_events: EventWriter<TileClicked>,
+) {
+    if !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let cursor_pos = match window.cursor_position() {
+        Some(cursor_pos) => cursor_pos,
+        None => return,
+    };
+    let (camera, camera_transform) = match camera_query
+        .iter()
+        .find(|(camera, _)| camera.name.as_deref() == Some(CAMERA_2D))
+    {
+        Some(camera) => camera,
+        None => return,
+    };
+    let world_pos = screen_to_world(cursor_pos, window, camera, camera_transform);
+
+    for (
+        map_handle,
+        optional_parent,
+        origin,
+        placement,
+        layer_filter,
+        load_hidden_layers,
+        created_entities,
+    ) in map_query.iter()
+    {
+        // only spawned maps can be clicked
+        let (map, chunk_root) = match (maps.get(map_handle), created_entities.chunk_root) {
+            (Some(map), Some(chunk_root)) => (map, chunk_root),
+            _ => continue,
+        };
+        let parent_transform = match optional_parent {
+            Some(parent) => match parent_query.get(*parent) {
+                Ok(parent_transform) => Some(parent_transform),
+                Err(_) => continue,
+            },
+            None => None,
+        };
+        let map_transform =
+            clicked_map_transform(map, placement, origin, optional_parent, parent_transform);
+
+        // layers shown or hidden at runtime through their chunks' Visible
+        let mut chunk_layers = HashMap::<LayerId, bool>::default();
+        for (parent, chunk_info, visible) in chunk_query.iter() {
+            if parent.0 == chunk_root {
+                *chunk_layers.entry(chunk_info.layer).or_default() |= visible.is_visible;
+            }
+        }
+        let clicked = topmost_tile(map, world_pos, &map_transform, |layer| {
+            let layer_id = LayerId(layer.tiled_layer);
+            layer_filter.includes(layer)
+                && (layer.visible || load_hidden_layers.0)
+                && chunk_layers
+                    .get(&layer_id)
+                    .copied()
+                    .unwrap_or(layer.visible)
+        });
+        if let Some((layer, tile, gid)) = clicked {
+            clicked_events.send(TileClicked {
+                map_handle: map_handle.clone(),
+                layer,
+                tile,
+                gid,
+                world_pos,
+            });
+        }
+    }
+}
+
+// transform of the content of a spawned map in the world, like `world_map_transform` but with the transform of
+// `TiledMapBundle::parent_option`, which the map is placed relative to
+#[cfg(not(feature = "headless"))]
+fn clicked_map_transform(
+    map: &Map,
+    placement: (&MapAnchor, &YAxis),
+    origin: &Transform,
+    optional_parent: &Option<Entity>,
+    parent_transform: Option<&GlobalTransform>,
+) -> Transform {
+    let content_transform = content_transform(map, placement, origin, optional_parent);
+    let map_transform = world_map_transform(origin, &content_transform, optional_parent);
+    match parent_transform {
+        Some(parent_transform) => Transform::from(*parent_transform) * map_transform,
+        None => map_transform,
+    }
+}
+
+// the tile at `world_pos` of the top-most layer `clickable` accepts, with its layer and gid
+#[cfg(not(feature = "headless"))]
+fn topmost_tile(
+    map: &Map,
+    world_pos: Vec2,
+    map_transform: &Transform,
+    clickable: impl Fn(&MapLayer) -> bool,
+) -> Option<(LayerId, IVec2, u32)> {
+    let tile = map.world_to_tile(world_pos, map_transform)?;
+    // the layer drawn last is on top
+    let mut layers = map.layers.iter().collect::<Vec<_>>();
+    layers.sort_by_key(|layer| std::cmp::Reverse(layer.layer_index));
+    layers
+        .into_iter()
+        .filter(|layer| clickable(layer))
+        .find_map(
+            |layer| match map.tile_gid(LayerId(layer.tiled_layer), tile) {
+                Some(gid) if gid != 0 => Some((LayerId(layer.tiled_layer), tile, gid)),
+                _ => None,
+            },
+        )
+}
+
+#[cfg(all(test, not(feature = "headless")))]
+mod tests {
+    use super::*;
+    use crate::map::parse_test_map;
+
+    #[test]
+    fn clicks_follow_the_parent_and_the_layer_filter() {
+        let map = parse_test_map(include_str!("../tests/fixtures/two-layers.tmx")).unwrap();
+        let parent_transform = GlobalTransform {
+            translation: Vec3::new(1000.0, 0.0, 0.0),
+            scale: Vec3::splat(2.0),
+            ..Default::default()
+        };
+        // relative to the parent
+        let origin = Transform::from_xyz(0.0, 100.0, 0.0);
+        let map_transform = clicked_map_transform(
+            &map,
+            (&MapAnchor::TopLeft, &YAxis::FlipToBevy),
+            &origin,
+            &Some(Entity::new(1)),
+            Some(&parent_transform),
+        );
+
+        // the center of tile (1, 0), (24, -8) in the map, moved by the origin and the parent
+        let world_pos = Vec2::new(1000.0 + 2.0 * 24.0, 2.0 * (100.0 - 8.0));
+        assert_eq!(
+            topmost_tile(&map, world_pos, &map_transform, |_| true),
+            Some((LayerId(1), IVec2::new(1, 0), 3))
+        );
+        let ground_only = LayerFilter::Names(vec!["ground".to_string()]);
+        assert_eq!(
+            topmost_tile(&map, world_pos, &map_transform, |layer| ground_only
+                .includes(layer)),
+            Some((LayerId(0), IVec2::new(1, 0), 2))
+        );
+    }
+}
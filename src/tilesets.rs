@@ -0,0 +1,259 @@
+use anyhow::{anyhow, Result};
+use bevy::{log::warn, utils::HashMap};
+
+use crate::{loader::TiledMapLoader, view::tile_count};
+
+/// Splits the tilesets drawn from several images into a tileset per image, so chunks, materials and atlases are
+/// built from a single image per tileset. Tilesets with more than one image are split by the tile id range of each
+/// image in order, and image collections, whose tiles have an image each, into a tileset per tile. Tiles keep their
+/// gids. Returns the first gid of the tileset each split tileset comes from, by its own first gid, and fails when a
+/// tile of the map has no image in a split tileset.
+pub(crate) fn split_tileset_images(map: &mut tiled::Map) -> Result<HashMap<u32, u32>> {
+    let mut origins = HashMap::default();
+    let mut tilesets = Vec::new();
+    // first gid of every tileset in the map, with the name and gid ranges of those that were split
+    let mut split_ranges = Vec::new();
+    for tileset in std::mem::take(&mut map.tilesets) {
+        if tileset.images.len() == 1 {
+            split_ranges.push((tileset.first_gid, None));
+            tilesets.push(tileset);
+            continue;
+        }
+        let split = if tileset.images.is_empty() {
+            image_collection_tilesets(tileset.clone())
+        } else {
+            image_tilesets(tileset.clone())
+        };
+        let ranges = split
+            .iter()
+            .map(|split| (split.first_gid, split.first_gid + tile_count(split)))
+            .collect::<Vec<_>>();
+        split_ranges.push((tileset.first_gid, Some((tileset.name.clone(), ranges))));
+        for split in split {
+            origins.insert(split.first_gid, tileset.first_gid);
+            tilesets.push(split);
+        }
+    }
+    map.tilesets = tilesets;
+
+    for gid in used_gids(map) {
+        // tilesets are ordered by first gid, a tileset's range ends where the next one starts
+        let split = split_ranges
+            .iter()
+            .rev()
+            .find(|(first_gid, _)| *first_gid <= gid)
+            .and_then(|(first_gid, split)| Some((first_gid, split.as_ref()?)));
+        if let Some((first_gid, (name, ranges))) = split {
+            if !ranges
+                .iter()
+                .any(|(start, end)| (*start..*end).contains(&gid))
+            {
+                return Err(anyhow!(
+                    "Tile {} of tileset \"{}\" has no image, only its images' tiles can be drawn",
+                    gid - first_gid,
+                    name
+                ));
+            }
+        }
+    }
+    Ok(origins)
+}
+
+// a tileset per image, each with the tiles of its image's cells
+fn image_tilesets(mut tileset: tiled::Tileset) -> Vec<tiled::Tileset> {
+    let images = std::mem::take(&mut tileset.images);
+    let tiles = std::mem::take(&mut tileset.tiles);
+    let mut first_id = 0;
+    images
+        .into_iter()
+        .map(|image| {
+            let mut split = tileset.clone();
+            split.first_gid = tileset.first_gid + first_id;
+            split.images = vec![image];
+            split.tilecount = None;
+            let count = tile_count(&split);
+            split.tilecount = Some(count);
+            split.tiles = tiles_in_range(&tileset.name, &tiles, first_id, count);
+            first_id += count;
+            split
+        })
+        .collect()
+}
+
+// a tileset per tile with an image, sized like the image
+fn image_collection_tilesets(mut tileset: tiled::Tileset) -> Vec<tiled::Tileset> {
+    let tiles = std::mem::take(&mut tileset.tiles);
+    tiles
+        .iter()
+        .filter_map(|tile| {
+            let image = tile.images.first()?;
+            let mut split = tileset.clone();
+            split.first_gid = tileset.first_gid + tile.id;
+            split.tile_width = image.width as u32;
+            split.tile_height = image.height as u32;
+            split.spacing = 0;
+            split.margin = 0;
+            split.tilecount = Some(1);
+            split.images = vec![image.clone()];
+            split.tiles = tiles_in_range(&tileset.name, &tiles, tile.id, 1);
+            Some(split)
+        })
+        .collect()
+}
+
+// tiles with ids from `first_id`, renumbered from 0 like in their own tileset
+fn tiles_in_range(
+    tileset_name: &str,
+    tiles: &[tiled::Tile],
+    first_id: u32,
+    count: u32,
+) -> Vec<tiled::Tile> {
+    let ids = first_id..first_id + count;
+    tiles
+        .iter()
+        .filter(|tile| ids.contains(&tile.id))
+        .map(|tile| {
+            let mut tile = tile.clone();
+            tile.id -= first_id;
+            if let Some(frames) = &mut tile.animation {
+                // a sprite atlas is made of one image, frames can't switch images
+                if frames.iter().all(|frame| ids.contains(&frame.tile_id)) {
+                    frames
+                        .iter_mut()
+                        .for_each(|frame| frame.tile_id -= first_id);
+                } else {
+                    warn!(
+                        "Tile {} of tileset \"{}\" is animated with tiles of other images, it isn't animated",
+                        tile.id + first_id,
+                        tileset_name
+                    );
+                    tile.animation = None;
+                }
+            }
+            tile
+        })
+        .collect()
+}
+
+// gids of the tiles of the layers and tile objects
+fn used_gids(map: &tiled::Map) -> Vec<u32> {
+    let mut gids = Vec::new();
+    for layer in map.layers.iter() {
+        match &layer.tiles {
+            tiled::LayerData::Finite(tiles) => {
+                gids.extend(tiles.iter().flatten().map(|tile| tile.gid));
+            }
+            tiled::LayerData::Infinite(chunks) => {
+                gids.extend(
+                    chunks
+                        .values()
+                        .flat_map(|chunk| chunk.tiles.iter().flatten())
+                        .map(|tile| tile.gid),
+                );
+            }
+        }
+    }
+    gids.extend(
+        map.object_groups
+            .iter()
+            .flat_map(|group| group.objects.iter())
+            .map(|object| object.gid),
+    );
+    let mut gids = gids
+        .into_iter()
+        .map(TiledMapLoader::remove_tile_flags)
+        .filter(|gid| *gid != 0)
+        .collect::<Vec<_>>();
+    gids.sort_unstable();
+    gids.dedup();
+    gids
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{map::parse_test_map, Map};
+
+    fn parse(tileset: &str, tiles: &str) -> anyhow::Result<Map> {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="orthogonal" renderorder="right-down" width="2" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+{}
+ <layer id="1" name="ground" width="2" height="1">
+  <data encoding="csv">
+{}
+</data>
+ </layer>
+</map>
+"#,
+            tileset, tiles
+        );
+        parse_test_map(&xml)
+    }
+
+    const TWO_IMAGES: &str = r#" <tileset firstgid="1" name="two" tilewidth="16" tileheight="16">
+  <image source="first.png" width="32" height="32"/>
+  <image source="second.png" width="32" height="16"/>
+  <tile id="5">
+   <properties>
+    <property name="kind" value="water"/>
+   </properties>
+  </tile>
+ </tileset>"#;
+
+    const COLLECTION: &str = r#" <tileset firstgid="1" name="collection" tilewidth="32" tileheight="48" tilecount="2">
+  <tile id="0">
+   <image width="16" height="16" source="bush.png"/>
+  </tile>
+  <tile id="3">
+   <image width="32" height="48" source="tree.png"/>
+  </tile>
+ </tileset>"#;
+
+    #[test]
+    fn images_of_a_tileset_get_a_tileset_each() {
+        let map = parse(TWO_IMAGES, "2,6").unwrap();
+        let tilesets = &map.map.tilesets;
+        assert_eq!(tilesets.len(), 2);
+        assert_eq!(tilesets[0].first_gid, 1);
+        assert_eq!(tilesets[0].images[0].source, "first.png");
+        assert_eq!(tilesets[1].first_gid, 5);
+        assert_eq!(tilesets[1].images[0].source, "second.png");
+        // tiles keep their gids
+        assert_eq!(tilesets[1].tiles[0].id, 1);
+        assert!(map.tile_properties(6).unwrap().contains_key("kind"));
+    }
+
+    #[test]
+    fn image_collection_tiles_get_a_tileset_each() {
+        let map = parse(COLLECTION, "1,4").unwrap();
+        let tilesets = &map.map.tilesets;
+        assert_eq!(tilesets.len(), 2);
+        assert_eq!(
+            (
+                tilesets[0].first_gid,
+                tilesets[0].tile_width,
+                tilesets[0].tile_height
+            ),
+            (1, 16, 16)
+        );
+        assert_eq!(
+            (
+                tilesets[1].first_gid,
+                tilesets[1].tile_width,
+                tilesets[1].tile_height
+            ),
+            (4, 32, 48)
+        );
+        // the tree stands on the bottom of its cell, the second of the row
+        let tree = map.layers[0].tileset_layers[1].chunks[0][0].tiles[1][0].vertex;
+        assert_eq!(tree, bevy::math::Vec4::new(16.0, -16.0, 48.0, 32.0));
+    }
+
+    #[test]
+    fn tiles_without_an_image_fail_the_load() {
+        let error = parse(COLLECTION, "1,3").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Tile 2 of tileset \"collection\""));
+    }
+}
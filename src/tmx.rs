@@ -0,0 +1,628 @@
+//! The parts of a map's XML that tiled 0.9 doesn't parse, read in a single pass with xml-rs. External tilesets
+//! and templates are read by the caller, through the asset io, and embedded here so tiled never opens files.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use bevy::{
+    math::Vec2,
+    utils::{HashMap, HashSet},
+};
+use xml::{
+    attribute::OwnedAttribute,
+    reader::{EventReader, XmlEvent},
+};
+
+use crate::{
+    map::normalize_asset_path,
+    utils::{HexLayout, StaggerAxis, StaggerIndex},
+    writer::escape,
+    DrawOrder, ObjectAlignment, TilesetGrid,
+};
+
+/// A `<tileset>` element of a map, embedded or referring to a .tsx file.
+pub(crate) struct TilesetElement {
+    pub first_gid: u32,
+    // path of the .tsx file, relative to the map
+    pub source: Option<String>,
+    pub alignment: Option<ObjectAlignment>,
+    pub tile_offset: Option<Vec2>,
+    pub grid: Option<TilesetGrid>,
+}
+
+/// An object created from a template, with the attributes it overrides.
+pub(crate) struct TemplateInstance {
+    // path of the .tx file, relative to the map
+    pub template: String,
+    pub attributes: HashSet<String>,
+    // instances with child elements override the template's shape and properties
+    pub has_children: bool,
+}
+
+/// The object of a template file.
+pub(crate) struct TemplateObject {
+    // the object element, with the position tiled requires and templates don't have
+    pub xml: String,
+    pub class: Option<String>,
+    // first gid and path of the tileset of tile templates, relative to the template
+    pub tileset: Option<(u32, String)>,
+}
+
+/// What the crate reads from a map's XML itself.
+#[derive(Default)]
+pub(crate) struct TmxDocument {
+    // attributes of the root element
+    attributes: HashMap<String, String>,
+    pub tilesets: Vec<TilesetElement>,
+    // by layer index, layers being counted like tiled does
+    pub layer_classes: HashMap<u32, String>,
+    pub draw_orders: HashMap<u32, DrawOrder>,
+    // by object id
+    pub object_classes: HashMap<u32, String>,
+    pub templates: HashMap<u32, TemplateInstance>,
+}
+
+impl TmxDocument {
+    pub fn parse(bytes: &[u8]) -> Result<TmxDocument> {
+        let mut document = TmxDocument::default();
+        let mut depth = 0;
+        // depth of the tileset element being read, its tiles' collision groups aren't layers
+        let mut tileset_depth = None;
+        // id and depth of the template instance being read
+        let mut instance: Option<(u32, usize)> = None;
+        let mut layer_index = 0;
+        for event in EventReader::new(bytes) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let attribute = |name: &str| attribute(&attributes, name);
+                    if depth == 0 {
+                        document.attributes = attributes
+                            .iter()
+                            .map(|a| (a.name.local_name.clone(), a.value.clone()))
+                            .collect();
+                    }
+                    if let Some((id, instance_depth)) = instance {
+                        if depth == instance_depth + 1 {
+                            if let Some(instance) = document.templates.get_mut(&id) {
+                                instance.has_children = true;
+                            }
+                        }
+                    }
+                    match (name.local_name.as_str(), tileset_depth) {
+                        ("tileset", None) => {
+                            document.tilesets.push(TilesetElement {
+                                first_gid: attribute("firstgid")
+                                    .and_then(|gid| gid.parse().ok())
+                                    .unwrap_or(0),
+                                source: attribute("source").map(str::to_string),
+                                alignment: attribute("objectalignment")
+                                    .map(ObjectAlignment::from_name),
+                                tile_offset: None,
+                                grid: None,
+                            });
+                            tileset_depth = Some(depth);
+                        }
+                        ("tileoffset", Some(tileset)) if depth == tileset + 1 => {
+                            let coordinate = |name| {
+                                attribute(name)
+                                    .and_then(|value| value.parse().ok())
+                                    .unwrap_or(0.0)
+                            };
+                            if let Some(tileset) = document.tilesets.last_mut() {
+                                tileset.tile_offset =
+                                    Some(Vec2::new(coordinate("x"), coordinate("y")));
+                            }
+                        }
+                        ("grid", Some(tileset)) if depth == tileset + 1 => {
+                            let length = |name| {
+                                attribute(name)
+                                    .and_then(|value| value.parse().ok())
+                                    .unwrap_or(0.0)
+                            };
+                            let orientation = attribute("orientation")
+                                .and_then(|orientation| orientation.parse().ok())
+                                .unwrap_or(tiled::Orientation::Orthogonal);
+                            if let Some(tileset) = document.tilesets.last_mut() {
+                                tileset.grid = Some(TilesetGrid {
+                                    orientation,
+                                    size: Vec2::new(length("width"), length("height")),
+                                });
+                            }
+                        }
+                        (_, Some(_)) => {}
+                        ("layer", None) | ("imagelayer", None) | ("objectgroup", None) => {
+                            if let Some(class) = attribute("class") {
+                                document
+                                    .layer_classes
+                                    .insert(layer_index, class.to_string());
+                            }
+                            if let Some(draw_order) = attribute("draworder") {
+                                document
+                                    .draw_orders
+                                    .insert(layer_index, DrawOrder::from_name(draw_order));
+                            }
+                            layer_index += 1;
+                        }
+                        ("object", None) => {
+                            let id = attribute("id").and_then(|id| id.parse().ok());
+                            if let (Some(id), Some(class)) = (id, attribute("class")) {
+                                document.object_classes.insert(id, class.to_string());
+                            }
+                            if let (Some(id), Some(template)) = (id, attribute("template")) {
+                                document.templates.insert(
+                                    id,
+                                    TemplateInstance {
+                                        template: template.to_string(),
+                                        attributes: attributes
+                                            .iter()
+                                            .map(|a| a.name.local_name.clone())
+                                            .collect(),
+                                        has_children: false,
+                                    },
+                                );
+                                instance = Some((id, depth));
+                            }
+                        }
+                        _ => {}
+                    }
+                    depth += 1;
+                }
+                XmlEvent::EndElement { .. } => {
+                    depth -= 1;
+                    if tileset_depth == Some(depth) {
+                        tileset_depth = None;
+                    }
+                    if instance.map(|(_, instance_depth)| instance_depth) == Some(depth) {
+                        instance = None;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(document)
+    }
+
+    /// Class of the map, set in Tiled 1.9 and later.
+    pub fn class(&self) -> String {
+        self.attributes.get("class").cloned().unwrap_or_default()
+    }
+
+    /// Layout of hexagonal maps, `None` without a valid `hexsidelength`.
+    pub fn hex_layout(&self, map: &tiled::Map) -> Option<HexLayout> {
+        let attribute = |name: &str| self.attributes.get(name).map(String::as_str);
+        Some(HexLayout {
+            tile_width: map.tile_width as f32,
+            tile_height: map.tile_height as f32,
+            side_length: attribute("hexsidelength")?.parse().ok()?,
+            stagger_axis: match attribute("staggeraxis") {
+                Some("x") => StaggerAxis::X,
+                _ => StaggerAxis::Y,
+            },
+            stagger_index: match attribute("staggerindex") {
+                Some("even") => StaggerIndex::Even,
+                _ => StaggerIndex::Odd,
+            },
+        })
+    }
+
+    /// Object alignment of each tileset by first gid.
+    pub fn tileset_alignments(&self) -> HashMap<u32, ObjectAlignment> {
+        self.tilesets
+            .iter()
+            .filter_map(|tileset| Some((tileset.first_gid, tileset.alignment?)))
+            .collect()
+    }
+
+    /// Tile offset of each tileset by first gid.
+    pub fn tileset_offsets(&self) -> HashMap<u32, Vec2> {
+        self.tilesets
+            .iter()
+            .filter_map(|tileset| Some((tileset.first_gid, tileset.tile_offset?)))
+            .collect()
+    }
+
+    /// Grid of each tileset by first gid, for the tilesets that have one.
+    pub fn tileset_grids(&self) -> HashMap<u32, TilesetGrid> {
+        self.tilesets
+            .iter()
+            .filter_map(|tileset| Some((tileset.first_gid, tileset.grid?)))
+            .collect()
+    }
+
+    /// First gid of the external tilesets by the asset path of their .tsx file.
+    pub fn external_tilesets(&self, map_path: &Path) -> HashMap<PathBuf, u32> {
+        self.tilesets
+            .iter()
+            .filter_map(|tileset| {
+                let source = tileset.source.as_ref()?;
+                Some((referenced_path(map_path, source)?, tileset.first_gid))
+            })
+            .collect()
+    }
+
+    /// Asset paths of the external tilesets and templates of the map at `map_path`, to read before
+    /// `embed_tilesets` and `apply_templates`.
+    pub fn referenced_files(&self, map_path: &Path) -> Vec<PathBuf> {
+        let tilesets = self
+            .tilesets
+            .iter()
+            .filter_map(|tileset| tileset.source.as_ref());
+        let templates = self.templates.values().map(|instance| &instance.template);
+        let mut files = tilesets
+            .chain(templates)
+            .filter_map(|source| referenced_path(map_path, source))
+            .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// The map's XML with its external tilesets embedded, their image paths made relative to the map, for
+    /// `tiled::parse`. Also reads the object alignment, tile offset and grid of the external tilesets.
+    pub fn embed_tilesets<'a>(
+        &mut self,
+        bytes: &'a [u8],
+        map_path: &Path,
+        files: &HashMap<PathBuf, Vec<u8>>,
+    ) -> Result<Cow<'a, [u8]>> {
+        if self.tilesets.iter().all(|tileset| tileset.source.is_none()) {
+            return Ok(Cow::Borrowed(bytes));
+        }
+        let mut xml = String::with_capacity(bytes.len());
+        let mut depth = 0;
+        // depth of the external tileset element being replaced
+        let mut replaced_depth = None;
+        let mut tileset_index = 0;
+        for event in EventReader::new(bytes) {
+            match event? {
+                XmlEvent::StartDocument { .. } => {
+                    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>")
+                }
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let source = match (depth, name.local_name.as_str()) {
+                        (1, "tileset") => attribute(&attributes, "source"),
+                        _ => None,
+                    };
+                    match (replaced_depth, source) {
+                        (Some(_), _) => {}
+                        (None, Some(source)) => {
+                            let path = referenced_path(map_path, source).ok_or_else(|| {
+                                anyhow!(
+                                    "External tileset \"{}\" is outside the asset folder, keep tilesets within it",
+                                    source
+                                )
+                            })?;
+                            let tsx = files.get(&path).ok_or_else(|| {
+                                anyhow!("Couldn't read external tileset {}", path.display())
+                            })?;
+                            // the map's tileset elements are in the order `parse` found them
+                            if let (Some(settings), Some(tileset)) = (
+                                TmxDocument::parse(tsx)?.tilesets.into_iter().next(),
+                                self.tilesets.get_mut(tileset_index),
+                            ) {
+                                tileset.alignment = settings.alignment;
+                                tileset.tile_offset = settings.tile_offset;
+                                tileset.grid = settings.grid;
+                            }
+                            let first_gid = attribute(&attributes, "firstgid").unwrap_or("1");
+                            write_tileset(&mut xml, tsx, first_gid, source)?;
+                            replaced_depth = Some(depth);
+                        }
+                        (None, None) => {
+                            write_start(&mut xml, &name.local_name, owned_attributes(&attributes))
+                        }
+                    }
+                    if (depth, name.local_name.as_str()) == (1, "tileset") {
+                        tileset_index += 1;
+                    }
+                    depth += 1;
+                }
+                XmlEvent::EndElement { name } => {
+                    depth -= 1;
+                    match replaced_depth {
+                        Some(replaced) if replaced == depth => replaced_depth = None,
+                        Some(_) => {}
+                        None => write_end(&mut xml, &name.local_name),
+                    }
+                }
+                XmlEvent::Characters(text) | XmlEvent::CData(text) if replaced_depth.is_none() => {
+                    xml.push_str(&escape(&text))
+                }
+                XmlEvent::Whitespace(text) if replaced_depth.is_none() => xml.push_str(&text),
+                _ => {}
+            }
+        }
+        Ok(Cow::Owned(xml.into_bytes()))
+    }
+}
+
+/// Reads the object of a template file, `None` when it has none.
+pub(crate) fn template_object(bytes: &[u8]) -> Result<Option<TemplateObject>> {
+    let mut template = TemplateObject {
+        xml: String::new(),
+        class: None,
+        tileset: None,
+    };
+    let mut depth = 0;
+    // depth of the object element while it is copied
+    let mut object_depth = None;
+    let mut found = false;
+    for event in EventReader::new(bytes) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                match (name.local_name.as_str(), object_depth) {
+                    (_, Some(_)) => write_start(
+                        &mut template.xml,
+                        &name.local_name,
+                        owned_attributes(&attributes),
+                    ),
+                    ("tileset", None) if depth == 1 => {
+                        let first_gid =
+                            attribute(&attributes, "firstgid").and_then(|gid| gid.parse().ok());
+                        if let (Some(first_gid), Some(source)) =
+                            (first_gid, attribute(&attributes, "source"))
+                        {
+                            template.tileset = Some((first_gid, source.to_string()));
+                        }
+                    }
+                    ("object", None) if depth == 1 && !found => {
+                        template.class = attribute(&attributes, "class").map(str::to_string);
+                        let position = [("x", "0"), ("y", "0")];
+                        write_start(
+                            &mut template.xml,
+                            "object",
+                            owned_attributes(&attributes)
+                                .filter(|(name, _)| *name != "x" && *name != "y")
+                                .chain(position.iter().copied()),
+                        );
+                        object_depth = Some(depth);
+                        found = true;
+                    }
+                    _ => {}
+                }
+                depth += 1;
+            }
+            XmlEvent::EndElement { name } => {
+                depth -= 1;
+                if let Some(object) = object_depth {
+                    write_end(&mut template.xml, &name.local_name);
+                    if object == depth {
+                        object_depth = None;
+                    }
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) if object_depth.is_some() => {
+                template.xml.push_str(&escape(&text))
+            }
+            XmlEvent::Whitespace(text) if object_depth.is_some() => template.xml.push_str(&text),
+            _ => {}
+        }
+    }
+    Ok(if found { Some(template) } else { None })
+}
+
+/// Asset path of a file referenced by the document at `document_path`, `None` when it is outside the asset
+/// folder.
+pub(crate) fn referenced_path(document_path: &Path, source: &str) -> Option<PathBuf> {
+    let folder = document_path.parent().unwrap_or_else(|| Path::new(""));
+    normalize_asset_path(&folder.join(source))
+}
+
+fn attribute<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.name.local_name == name)
+        .map(|attribute| attribute.value.as_str())
+}
+
+// the root tileset element of a .tsx file, with the first gid the map gives it and its image paths
+// rebased from the .tsx file's folder to the map's
+fn write_tileset(xml: &mut String, tsx: &[u8], first_gid: &str, source: &str) -> Result<()> {
+    let folder = &source[..source.rfind('/').map_or(0, |slash| slash + 1)];
+    let mut depth = 0;
+    for event in EventReader::new(tsx) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let mut attributes = attributes
+                    .into_iter()
+                    .map(|a| (a.name.local_name, a.value))
+                    .collect::<Vec<_>>();
+                match (name.local_name.as_str(), depth) {
+                    ("tileset", 0) => {
+                        attributes.retain(|(name, _)| name != "firstgid");
+                        attributes.insert(0, ("firstgid".to_string(), first_gid.to_string()));
+                    }
+                    ("image", _) => {
+                        for (name, value) in attributes.iter_mut() {
+                            if name == "source" {
+                                *value = format!("{}{}", folder, value);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                write_start(
+                    xml,
+                    &name.local_name,
+                    attributes
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value.as_str())),
+                );
+                depth += 1;
+            }
+            XmlEvent::EndElement { name } => {
+                depth -= 1;
+                write_end(xml, &name.local_name);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => xml.push_str(&escape(&text)),
+            XmlEvent::Whitespace(text) => xml.push_str(&text),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn write_start<'a>(
+    xml: &mut String,
+    name: &str,
+    attributes: impl Iterator<Item = (&'a str, &'a str)>,
+) {
+    xml.push('<');
+    xml.push_str(name);
+    for (name, value) in attributes {
+        xml.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+    }
+    xml.push('>');
+}
+
+fn write_end(xml: &mut String, name: &str) {
+    xml.push_str("</");
+    xml.push_str(name);
+    xml.push('>');
+}
+
+fn owned_attributes(attributes: &[OwnedAttribute]) -> impl Iterator<Item = (&str, &str)> {
+    attributes
+        .iter()
+        .map(|a| (a.name.local_name.as_str(), a.value.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use bevy::{math::Vec2, utils::HashMap};
+
+    use super::TmxDocument;
+    use crate::{DrawOrder, Map, ObjectAlignment, TilesetGrid};
+
+    #[test]
+    fn attributes_are_read_like_an_xml_parser_does() {
+        // `>` in quoted values, escaped values and commented out elements
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.9" class="a > b &amp; c" orientation="orthogonal" width="1" height="1" tilewidth="16" tileheight="16">
+ <!-- <object id="9" class="commented" template="commented.tx"/> -->
+ <tileset firstgid="1" name="embedded" tilewidth="16" tileheight="16">
+  <image source="embedded.png" width="16" height="16"/>
+  <tile id="0">
+   <objectgroup draworder="index">
+    <object id="1" class="collision" template="collision.tx" x="0" y="0" width="16" height="16"/>
+   </objectgroup>
+  </tile>
+ </tileset>
+ <objectgroup id="1" name="objects" class="spawns" draworder="index">
+  <object id="2" class="door > gate" x="0" y="0"/>
+  <object id="3" template="door.tx" name="front" x="0" y="0">
+   <properties>
+    <property name="locked" type="bool" value="true"/>
+   </properties>
+  </object>
+  <object id="4" template="door.tx" x="0" y="0"/>
+ </objectgroup>
+</map>
+"#;
+        let document = TmxDocument::parse(xml.as_bytes()).unwrap();
+        assert_eq!(document.class(), "a > b & c");
+        // the tile's collision group isn't a layer of the map
+        assert_eq!(document.layer_classes.get(&0).unwrap(), "spawns");
+        assert_eq!(document.draw_orders.get(&0), Some(&DrawOrder::Index));
+        assert_eq!(document.draw_orders.len(), 1);
+        assert_eq!(document.object_classes.get(&2).unwrap(), "door > gate");
+        assert!(!document.object_classes.contains_key(&1));
+        assert!(!document.object_classes.contains_key(&9));
+        assert!(!document.templates.contains_key(&1));
+        assert!(!document.templates.contains_key(&9));
+
+        let front = document.templates.get(&3).unwrap();
+        assert_eq!(front.template, "door.tx");
+        assert!(front.attributes.contains("name"));
+        assert!(!front.attributes.contains("type"));
+        assert!(front.has_children);
+        assert!(!document.templates.get(&4).unwrap().has_children);
+    }
+
+    #[test]
+    fn external_tilesets_are_read_from_the_given_files() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="orthogonal" width="1" height="1" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" source="../tilesets/ground.tsx"/>
+ <layer id="1" name="ground" width="1" height="1">
+  <data encoding="csv">
+1
+</data>
+ </layer>
+</map>
+"#;
+        let tsx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.5" name="ground" tilewidth="16" tileheight="16" tilecount="1" columns="1" objectalignment="bottom">
+ <tileoffset x="2" y="-4"/>
+ <grid orientation="isometric" width="16" height="8"/>
+ <image source="ground.png" width="16" height="16"/>
+</tileset>
+"#;
+        let map_path = Path::new("maps/level.tmx");
+        let mut files: HashMap<PathBuf, Vec<u8>> = HashMap::default();
+        files.insert("tilesets/ground.tsx".into(), tsx.as_bytes().to_vec());
+
+        let mut document = TmxDocument::parse(xml.as_bytes()).unwrap();
+        assert_eq!(
+            document.referenced_files(map_path),
+            vec![PathBuf::from("tilesets/ground.tsx")]
+        );
+        let embedded = document
+            .embed_tilesets(xml.as_bytes(), map_path, &files)
+            .unwrap();
+        let map = tiled::parse(embedded.as_ref()).unwrap();
+        assert_eq!(map.tilesets[0].first_gid, 1);
+        assert_eq!(map.tilesets[0].name, "ground");
+        // relative to the map, like the images of embedded tilesets
+        assert_eq!(map.tilesets[0].images[0].source, "../tilesets/ground.png");
+        assert_eq!(
+            document.tileset_alignments().get(&1),
+            Some(&ObjectAlignment::Bottom)
+        );
+        assert_eq!(
+            document.tileset_offsets().get(&1),
+            Some(&Vec2::new(2.0, -4.0))
+        );
+        assert_eq!(
+            document.tileset_grids().get(&1),
+            Some(&TilesetGrid {
+                orientation: tiled::Orientation::Isometric,
+                size: Vec2::new(16.0, 8.0),
+            })
+        );
+
+        let map = Map::from_document(
+            map_path,
+            xml.as_bytes().to_vec(),
+            TmxDocument::parse(xml.as_bytes()).unwrap(),
+            &files,
+        )
+        .unwrap();
+        assert_eq!(
+            map.asset_dependencies,
+            vec![PathBuf::from("tilesets/ground.png")]
+        );
+
+        let error = Map::from_document(
+            map_path,
+            xml.as_bytes().to_vec(),
+            TmxDocument::parse(xml.as_bytes()).unwrap(),
+            &HashMap::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("tilesets/ground.tsx"));
+    }
+}
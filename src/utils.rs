@@ -1,4 +1,9 @@
-use bevy::math::Vec2;
+#[cfg(not(feature = "headless"))]
+use bevy::{math::Vec4, prelude::GlobalTransform, render::camera::Camera, window::Window};
+use bevy::{
+    math::{Rect, Vec2},
+    prelude::Transform,
+};
 
 pub fn project_ortho(pos: Vec2, tile_width: f32, tile_height: f32) -> Vec2 {
     let x = tile_width * pos.x;
@@ -22,3 +27,423 @@ pub fn unproject_iso(pos: Vec2, tile_width: f32, tile_height: f32) -> Vec2 {
     let y = ((-(pos.y) / half_height) - (pos.x / half_width)) / 2.0;
     Vec2::new(x.round(), y.round())
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaggerAxis {
+    X, // flat-top hexagons, every other column is shifted down
+    Y, // pointy-top hexagons, every other row is shifted right
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaggerIndex {
+    Odd,
+    Even,
+}
+
+/// Layout of a hexagonal map, as set in Tiled's map properties.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HexLayout {
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub side_length: f32,
+    pub stagger_axis: StaggerAxis,
+    pub stagger_index: StaggerIndex,
+}
+
+impl HexLayout {
+    fn side_lengths(&self) -> Vec2 {
+        match self.stagger_axis {
+            StaggerAxis::X => Vec2::new(self.side_length, 0.0),
+            StaggerAxis::Y => Vec2::new(0.0, self.side_length),
+        }
+    }
+
+    // distance between consecutive columns and rows along the stagger axis
+    fn column_width_row_height(&self) -> Vec2 {
+        let side_lengths = self.side_lengths();
+        let side_offsets = (Vec2::new(self.tile_width, self.tile_height) - side_lengths) / 2.0;
+        side_offsets + side_lengths
+    }
+
+    /// Corners of a hexagon relative to the top left of its bounding box in y-down space, going around it.
+    pub fn corners(&self) -> Vec<Vec2> {
+        let (width, height) = (self.tile_width, self.tile_height);
+        let side_offsets = (Vec2::new(width, height) - self.side_lengths()) / 2.0;
+        let corners = vec![
+            Vec2::new(0.0, height - side_offsets.y),
+            Vec2::new(0.0, side_offsets.y),
+            Vec2::new(side_offsets.x, 0.0),
+            Vec2::new(width - side_offsets.x, 0.0),
+            Vec2::new(width, side_offsets.y),
+            Vec2::new(width, height - side_offsets.y),
+            Vec2::new(width - side_offsets.x, height),
+            Vec2::new(side_offsets.x, height),
+        ];
+        // corners meeting where the hexagon has no side along an axis
+        let mut unique: Vec<Vec2> = Vec::with_capacity(corners.len());
+        for corner in corners {
+            if unique.last() != Some(&corner) && unique.first() != Some(&corner) {
+                unique.push(corner);
+            }
+        }
+        unique
+    }
+
+    /// Whether the column (stagger axis X) or row (stagger axis Y) at `index` is shifted.
+    pub fn is_staggered(&self, index: i32) -> bool {
+        (index & 1 == 1) != (self.stagger_index == StaggerIndex::Even)
+    }
+}
+
+/// Top left corner of the hexagon's bounding box, following Tiled's hexagonal renderer.
+pub fn project_hex(pos: Vec2, layout: &HexLayout) -> Vec2 {
+    let (x, y) = (pos.x as i32, pos.y as i32);
+    let side_lengths = layout.side_lengths();
+    let column_row = layout.column_width_row_height();
+    let (pixel_x, pixel_y) = match layout.stagger_axis {
+        StaggerAxis::X => {
+            let mut pixel_y = y as f32 * (layout.tile_height + side_lengths.y);
+            if layout.is_staggered(x) {
+                pixel_y += column_row.y;
+            }
+            (x as f32 * column_row.x, pixel_y)
+        }
+        StaggerAxis::Y => {
+            let mut pixel_x = x as f32 * (layout.tile_width + side_lengths.x);
+            if layout.is_staggered(y) {
+                pixel_x += column_row.x;
+            }
+            (pixel_x, y as f32 * column_row.y)
+        }
+    };
+    Vec2::new(pixel_x, -pixel_y)
+}
+
+/// Hexagon containing the position, following Tiled's hexagonal renderer.
+pub fn unproject_hex(pos: Vec2, layout: &HexLayout) -> Vec2 {
+    let stagger_even = layout.stagger_index == StaggerIndex::Even;
+    let side_lengths = layout.side_lengths();
+    let column_row = layout.column_width_row_height();
+    let side_offsets = column_row - side_lengths;
+    let mut pixel = Vec2::new(pos.x, -pos.y);
+    match layout.stagger_axis {
+        StaggerAxis::X => {
+            pixel.x -= if stagger_even {
+                layout.tile_width
+            } else {
+                side_offsets.x
+            }
+        }
+        StaggerAxis::Y => {
+            pixel.y -= if stagger_even {
+                layout.tile_height
+            } else {
+                side_offsets.y
+            }
+        }
+    }
+
+    // start with the grid-aligned tile, covering two columns or rows
+    let mut reference = (pixel / (column_row * 2.0)).floor();
+    let relative = pixel - reference * column_row * 2.0;
+    match layout.stagger_axis {
+        StaggerAxis::X => reference.x = reference.x * 2.0 + stagger_even as i32 as f32,
+        StaggerAxis::Y => reference.y = reference.y * 2.0 + stagger_even as i32 as f32,
+    }
+
+    // the nearest hexagon center decides which neighbor of the reference tile was hit
+    let (centers, offsets) = match layout.stagger_axis {
+        StaggerAxis::X => {
+            let left = side_lengths.x / 2.0;
+            let center_x = left + column_row.x;
+            let center_y = layout.tile_height / 2.0;
+            (
+                [
+                    Vec2::new(left, center_y),
+                    Vec2::new(center_x, center_y - column_row.y),
+                    Vec2::new(center_x, center_y + column_row.y),
+                    Vec2::new(center_x + column_row.x, center_y),
+                ],
+                [
+                    Vec2::new(0.0, 0.0),
+                    Vec2::new(1.0, -1.0),
+                    Vec2::new(1.0, 0.0),
+                    Vec2::new(2.0, 0.0),
+                ],
+            )
+        }
+        StaggerAxis::Y => {
+            let top = side_lengths.y / 2.0;
+            let center_x = layout.tile_width / 2.0;
+            let center_y = top + column_row.y;
+            (
+                [
+                    Vec2::new(center_x, top),
+                    Vec2::new(center_x - column_row.x, center_y),
+                    Vec2::new(center_x + column_row.x, center_y),
+                    Vec2::new(center_x, center_y + column_row.y),
+                ],
+                [
+                    Vec2::new(0.0, 0.0),
+                    Vec2::new(-1.0, 1.0),
+                    Vec2::new(0.0, 1.0),
+                    Vec2::new(0.0, 2.0),
+                ],
+            )
+        }
+    };
+    let mut nearest = 0;
+    for i in 1..centers.len() {
+        if centers[i].distance_squared(relative) < centers[nearest].distance_squared(relative) {
+            nearest = i;
+        }
+    }
+    reference + offsets[nearest]
+}
+
+/// World position under a window position such as the cursor, for the given camera.
+/// Accounts for the camera's projection and transform, including orthographic scale.
+#[cfg(not(feature = "headless"))]
+pub fn screen_to_world(
+    screen_pos: Vec2,
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Vec2 {
+    let window_size = Vec2::new(window.width(), window.height());
+    // window positions start at the bottom left corner, like normalized device coordinates
+    let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix.inverse();
+    let world_pos = ndc_to_world * Vec4::new(ndc.x, ndc.y, 0.0, 1.0);
+    world_pos.truncate().truncate() / world_pos.w
+}
+
+/// Moves a 2d camera so the `viewport`, the world-space size it shows, stays within `bounds`, e.g. `MapBounds`.
+/// Along an axis where the map is smaller than the viewport, the camera is centered on the map instead.
+pub fn clamp_camera_to_bounds(
+    camera_transform: &mut Transform,
+    bounds: &Rect<f32>,
+    viewport: Vec2,
+) {
+    let half_viewport = viewport / 2.0;
+    let clamp_axis = |position: f32, min: f32, max: f32, half_size: f32| {
+        if max - min <= half_size * 2.0 {
+            (min + max) / 2.0
+        } else {
+            position.max(min + half_size).min(max - half_size)
+        }
+    };
+    let translation = &mut camera_transform.translation;
+    translation.x = clamp_axis(translation.x, bounds.left, bounds.right, half_viewport.x);
+    translation.y = clamp_axis(translation.y, bounds.bottom, bounds.top, half_viewport.y);
+}
+
+/// Splits a simple polygon into convex polygons, e.g. for physics engines that only take convex colliders.
+/// The polygon is triangulated by ear clipping, then neighbouring pieces are merged while they stay convex.
+/// Convex polygons are returned as is. Pieces keep the winding of `polygon`.
+pub fn convex_decomposition(polygon: &[Vec2]) -> Vec<Vec<Vec2>> {
+    if polygon.len() < 3 {
+        return Vec::new();
+    }
+    // work counter-clockwise in the math sense, so convex corners have a positive cross product
+    let reversed = signed_area(polygon) < 0.0;
+    let mut points = polygon.to_vec();
+    if reversed {
+        points.reverse();
+    }
+    if is_convex(&points) {
+        return vec![polygon.to_vec()];
+    }
+
+    let mut pieces = ear_clip(&points);
+    merge_convex_pieces(&points, &mut pieces);
+
+    pieces
+        .into_iter()
+        .map(|piece| {
+            let mut piece: Vec<Vec2> = piece.into_iter().map(|i| points[i]).collect();
+            if reversed {
+                piece.reverse();
+            }
+            piece
+        })
+        .collect()
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn signed_area(points: &[Vec2]) -> f32 {
+    let mut area = 0.0;
+    for (i, point) in points.iter().enumerate() {
+        area += cross(*point, points[(i + 1) % points.len()]);
+    }
+    area / 2.0
+}
+
+fn is_convex(points: &[Vec2]) -> bool {
+    let n = points.len();
+    (0..n).all(|i| {
+        let (a, b, c) = (points[i], points[(i + 1) % n], points[(i + 2) % n]);
+        cross(b - a, c - b) >= -f32::EPSILON
+    })
+}
+
+// triangles of a counter-clockwise polygon, as indices into it
+fn ear_clip(points: &[Vec2]) -> Vec<Vec<usize>> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (ia, ib, ic) = (
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            );
+            let (a, b, c) = (points[ia], points[ib], points[ic]);
+            if cross(b - a, c - b) <= 0.0 {
+                return false;
+            }
+            // no other corner may lie within the ear
+            remaining.iter().all(|&j| {
+                let p = points[j];
+                j == ia
+                    || j == ib
+                    || j == ic
+                    || p == a
+                    || p == b
+                    || p == c
+                    || cross(b - a, p - a) < 0.0
+                    || cross(c - b, p - b) < 0.0
+                    || cross(a - c, p - c) < 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                triangles.push(vec![
+                    remaining[(i + n - 1) % n],
+                    remaining[i],
+                    remaining[(i + 1) % n],
+                ]);
+                remaining.remove(i);
+            }
+            // self-intersecting or degenerate polygon, keep what could be clipped
+            None => return triangles,
+        }
+    }
+    triangles.push(remaining);
+    triangles
+}
+
+// merges pieces sharing an edge as long as the result is convex (Hertel-Mehlhorn)
+fn merge_convex_pieces(points: &[Vec2], pieces: &mut Vec<Vec<usize>>) {
+    let mut merged_any = true;
+    while merged_any {
+        merged_any = false;
+        'search: for p in 0..pieces.len() {
+            for q in (p + 1)..pieces.len() {
+                if let Some(merged) = merge_pieces(&pieces[p], &pieces[q]) {
+                    let merged_points: Vec<Vec2> = merged.iter().map(|i| points[*i]).collect();
+                    if is_convex(&merged_points) {
+                        pieces[p] = merged;
+                        pieces.remove(q);
+                        merged_any = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the two pieces joined along the edge they share, if any
+fn merge_pieces(p: &[usize], q: &[usize]) -> Option<Vec<usize>> {
+    for i in 0..p.len() {
+        let (a, b) = (p[i], p[(i + 1) % p.len()]);
+        // with the same winding, the shared edge runs the other way in q
+        if let Some(j) = (0..q.len()).find(|&j| q[j] == b && q[(j + 1) % q.len()] == a) {
+            // walk p from b around to a, then q from after a around to before b
+            let mut merged: Vec<usize> = (1..=p.len()).map(|k| p[(i + k) % p.len()]).collect();
+            merged.extend((2..q.len()).map(|k| q[(j + k) % q.len()]));
+            return Some(merged);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layouts() -> Vec<HexLayout> {
+        let mut layouts = Vec::new();
+        for stagger_axis in [StaggerAxis::X, StaggerAxis::Y].iter() {
+            for stagger_index in [StaggerIndex::Odd, StaggerIndex::Even].iter() {
+                layouts.push(HexLayout {
+                    tile_width: 28.0,
+                    tile_height: 24.0,
+                    side_length: 12.0,
+                    stagger_axis: *stagger_axis,
+                    stagger_index: *stagger_index,
+                });
+            }
+        }
+        layouts
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for layout in layouts() {
+            for y in 0..8 {
+                for x in 0..8 {
+                    let tile = Vec2::new(x as f32, y as f32);
+                    let center = project_hex(tile, &layout)
+                        + Vec2::new(layout.tile_width / 2.0, -layout.tile_height / 2.0);
+                    // the center and points around it, well within the hexagon
+                    for offset in [
+                        Vec2::ZERO,
+                        Vec2::new(5.0, 0.0),
+                        Vec2::new(-5.0, 0.0),
+                        Vec2::new(0.0, 5.0),
+                        Vec2::new(0.0, -5.0),
+                    ]
+                    .iter()
+                    {
+                        assert_eq!(
+                            unproject_hex(center + *offset, &layout),
+                            tile,
+                            "{:?} at {:?}",
+                            layout,
+                            offset
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hex_staggered_lines_are_shifted() {
+        for layout in layouts() {
+            let (first, second) = match layout.stagger_axis {
+                StaggerAxis::X => (Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0)),
+                StaggerAxis::Y => (Vec2::new(0.0, 0.0), Vec2::new(0.0, 1.0)),
+            };
+            let shift = project_hex(second, &layout) - project_hex(first, &layout);
+            // odd lines are shifted with StaggerIndex::Odd, even ones with StaggerIndex::Even
+            let shifted_second = layout.stagger_index == StaggerIndex::Odd;
+            match layout.stagger_axis {
+                StaggerAxis::X => {
+                    assert_eq!(shift.x, 20.0);
+                    assert_eq!(shift.y, if shifted_second { -12.0 } else { 12.0 });
+                }
+                StaggerAxis::Y => {
+                    assert_eq!(shift.y, -18.0);
+                    assert_eq!(shift.x, if shifted_second { 14.0 } else { -14.0 });
+                }
+            }
+        }
+    }
+}
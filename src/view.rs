@@ -1,6 +1,58 @@
+#[cfg(not(feature = "headless"))]
+mod array_texture;
+#[cfg(not(feature = "headless"))]
+pub use array_texture::*;
+#[cfg(not(feature = "headless"))]
+mod chunks;
+#[cfg(not(feature = "headless"))]
+pub use chunks::*;
+#[cfg(not(feature = "headless"))]
+pub(crate) use chunks::{layer_transform, layer_visible};
+#[cfg(not(feature = "headless"))]
 mod pipeline;
+#[cfg(not(feature = "headless"))]
 pub use pipeline::*;
+#[cfg(all(test, not(feature = "headless")))]
+mod mesh_snapshot;
 mod tile_chunk;
+#[cfg(not(feature = "headless"))]
+pub(crate) use tile_chunk::compact_indices;
 pub use tile_chunk::*;
+pub(crate) use tile_chunk::{atlas_cell, tile_count};
+#[cfg(not(feature = "headless"))]
+mod transparent_color;
+#[cfg(not(feature = "headless"))]
+pub use transparent_color::*;
 
+use bevy::prelude::*;
+
+use crate::{LayerId, TilesetId};
+
+#[derive(Default, Reflect)]
+#[reflect(Component)]
 pub struct TileMapChunk;
+
+/// Layer and tileset a chunk entity was built from.
+#[derive(Debug, Default, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct ChunkInfo {
+    pub layer: LayerId,
+    pub layer_name: String,
+    pub tileset: ChunkTileset,
+}
+
+/// Tilesets drawn by a chunk entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[reflect_value(PartialEq)]
+pub enum ChunkTileset {
+    /// The tiles of one tileset, drawn with its image.
+    Single(TilesetId),
+    /// The tiles of every tileset of the layer, drawn with the map's texture array, see `TilesetTextureArray`.
+    Array,
+}
+
+impl Default for ChunkTileset {
+    fn default() -> Self {
+        ChunkTileset::Single(TilesetId::default())
+    }
+}
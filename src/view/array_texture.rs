@@ -0,0 +1,216 @@
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        draw::Visible,
+        mesh::VertexAttributeValues,
+        pipeline::{PrimitiveTopology, RenderPipeline},
+        render_graph::base::MainPass,
+        renderer::RenderResources,
+        texture::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+use crate::{
+    compact_indices, LayerTint, Map, MapLayer, TileMapChunk, TransparentColorKeys,
+    TILE_MAP_ARRAY_PIPELINE_HANDLE,
+};
+
+/// When true, the tileset images of a map are packed into a single array texture
+/// so every chunk of a layer is drawn in one call, whatever the number of tilesets.
+/// All tileset images must have the same size; the `web` feature doesn't support it.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct TilesetTextureArray(pub bool);
+
+#[derive(RenderResources, Default, TypeUuid)]
+#[uuid = "53082de1-23c9-4f13-b31f-ee07f67faba8"]
+pub struct TileMapArrayMaterial {
+    pub texture: Handle<Texture>,
+}
+
+/// Inserted on a map bundle entity until its array texture is built from the tileset images.
+pub struct TilesetArrayTextureSources {
+    pub texture: Handle<Texture>,
+    pub sources: Vec<Handle<Texture>>,
+}
+
+#[derive(Bundle)]
+pub struct ArrayChunkBundle {
+    pub map_parent: Handle<Map>, // tmp:chunks might want to be child entities of a map
+    pub chunk: TileMapChunk,
+    pub main_pass: MainPass,
+    pub material: Handle<TileMapArrayMaterial>,
+    pub render_pipeline: RenderPipelines,
+    pub tint: LayerTint,
+    pub visible: Visible,
+    pub draw: Draw,
+    pub mesh: Handle<Mesh>,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+}
+
+impl Default for ArrayChunkBundle {
+    fn default() -> Self {
+        Self {
+            map_parent: Handle::default(),
+            chunk: TileMapChunk::default(),
+            tint: Default::default(),
+            visible: Visible {
+                is_transparent: true,
+                ..Default::default()
+            },
+            draw: Default::default(),
+            main_pass: MainPass,
+            mesh: Handle::default(),
+            material: Handle::default(),
+            render_pipeline: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+                TILE_MAP_ARRAY_PIPELINE_HANDLE.typed(),
+            )]),
+            transform: Default::default(),
+            global_transform: Default::default(),
+        }
+    }
+}
+
+/// Whether the tileset images can be stacked in an array texture, they need matching sizes.
+/// Always false with the `web` feature, the array shaders have no WebGL2 variant.
+pub fn can_use_texture_array(tilesets: &[tiled::Tileset]) -> bool {
+    if cfg!(feature = "web") {
+        return false;
+    }
+    let mut sizes = tilesets.iter().map(|tileset| {
+        tileset
+            .images
+            .first()
+            .map(|image| (image.width, image.height))
+    });
+    match sizes.next() {
+        Some(Some(first)) => sizes.all(|size| size == Some(first)),
+        _ => false,
+    }
+}
+
+/// Placeholder used until the tileset images are loaded, so the material always binds an array.
+pub fn empty_texture_array(layers: u32) -> Texture {
+    Texture::new_fill(
+        Extent3d::new(1, 1, layers),
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Builds one mesh per chunk holding the tiles of every tileset of the layer,
+/// `Vertex_Layer` being the index of the tile's tileset in the array texture.
+/// Positions are relative to the layer space origin returned with each mesh.
+pub fn build_array_meshes(layer: &MapLayer, tilesets: &[tiled::Tileset]) -> Vec<(Vec2, Mesh)> {
+    let mut meshes = Vec::new();
+    let chunks = match layer.tileset_layers.first() {
+        Some(tileset_layer) => &tileset_layer.chunks,
+        None => return meshes,
+    };
+
+    for x in 0..chunks.len() {
+        for y in 0..chunks[x].len() {
+            let tile_count: usize = layer
+                .tileset_layers
+                .iter()
+                .map(|tileset_layer| {
+                    tileset_layer.chunks[x][y].quad_count(tileset_layer.tileset_guid)
+                })
+                .sum();
+            // min corner of the chunks of all tilesets
+            let origin = layer
+                .tileset_layers
+                .iter()
+                .map(|tileset_layer| &tileset_layer.chunks[x][y])
+                .filter(|chunk| chunk.bounds != Vec4::ZERO)
+                .map(|chunk| chunk.origin())
+                .fold(None, |min: Option<Vec2>, origin| {
+                    Some(min.map_or(origin, |min| min.min(origin)))
+                })
+                .unwrap_or(Vec2::ZERO);
+            let mut positions: Vec<[f32; 3]> = Vec::with_capacity(tile_count * 4);
+            let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(tile_count * 4);
+            let mut colors: Vec<[f32; 4]> = Vec::with_capacity(tile_count * 4);
+            let mut texture_layers: Vec<f32> = Vec::with_capacity(tile_count * 4);
+            let mut indices: Vec<u32> = Vec::with_capacity(tile_count * 6);
+
+            for tileset_layer in layer.tileset_layers.iter() {
+                let texture_layer = tilesets
+                    .iter()
+                    .position(|tileset| tileset.first_gid == tileset_layer.tileset_guid)
+                    .unwrap_or(0) as f32;
+                tileset_layer.chunks[x][y].append_quads(
+                    tileset_layer.tileset_guid,
+                    origin,
+                    &mut positions,
+                    &mut uvs,
+                    &mut colors,
+                    &mut indices,
+                );
+                texture_layers.resize(positions.len(), texture_layer);
+            }
+
+            if positions.len() > 0 {
+                let vertex_count = positions.len();
+                let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+                mesh.set_attribute("Vertex_Position", VertexAttributeValues::Float3(positions));
+                mesh.set_attribute("Vertex_Layer", VertexAttributeValues::Float(texture_layers));
+                mesh.set_attribute("Vertex_Uv", VertexAttributeValues::Float2(uvs));
+                mesh.set_attribute("Vertex_Color", VertexAttributeValues::Float4(colors));
+                mesh.set_indices(Some(compact_indices(indices, vertex_count)));
+                meshes.push((origin, mesh));
+            }
+        }
+    }
+    meshes
+}
+
+/// Stacks the tileset images of a map into its array texture once they are all loaded.
+pub fn build_tileset_array_textures(
+    mut commands: Commands,
+    mut textures: ResMut<Assets<Texture>>,
+    // the sources are stacked once their transparent colors are applied
+    query: Query<(Entity, &TilesetArrayTextureSources), Without<TransparentColorKeys>>,
+) {
+    for (entity, array_sources) in query.iter() {
+        if array_sources
+            .sources
+            .iter()
+            .any(|source| textures.get(source).is_none())
+        {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .remove::<TilesetArrayTextureSources>();
+
+        let first = textures.get(&array_sources.sources[0]).unwrap();
+        let (size, format) = (first.size, first.format);
+        let mut data = Vec::new();
+        for source in array_sources.sources.iter() {
+            let texture = textures.get(source).unwrap();
+            if texture.size != size || texture.format != format {
+                warn!("Tileset images differ in size or format, they can't share an array texture");
+                data.clear();
+                break;
+            }
+            data.extend_from_slice(&texture.data);
+        }
+        if data.is_empty() {
+            continue;
+        }
+
+        let layers = array_sources.sources.len() as u32;
+        let mut texture = Texture::new(
+            Extent3d::new(size.width, size.height * layers, 1),
+            TextureDimension::D2,
+            data,
+            format,
+        );
+        texture.reinterpret_stacked_2d_as_array(layers);
+        textures.set_untracked(array_sources.texture.id, texture);
+    }
+}
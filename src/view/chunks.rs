@@ -0,0 +1,785 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    ecs::system::Command,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    build_array_meshes, can_use_texture_array, empty_texture_array, spawn::changed_maps,
+    tile_map_array_pipelines, tile_map_pipelines, transparent_color_keys, ArrayChunkBundle,
+    ChunkBundle, ChunkInfo, ChunkTileset, CreatedMapEntities, CustomPipeline, DepthConfig,
+    LayerBlend, LayerClass, LayerFilter, LayerId, LayerProperties, LoadHiddenLayers, Map, MapLayer,
+    PremultipliedAlpha, StreamChunks, TileMapArrayMaterial, TilesetArrayTextureSources, TilesetId,
+    TilesetLayer, TilesetTextureArray, TransparentColorKeys,
+};
+
+/// Draws a tile layer with another image for one of its tilesets, e.g. a snowy variant of the ground,
+/// without reloading the map: `commands.add(SetLayerTilesetTexture { chunk, texture })`.
+/// `chunk` is any chunk entity of the layer and the tileset is the one in its `ChunkInfo`. The image needs the
+/// tileset's layout, tiles keep their place in it. Tile objects of the map drawn from the tileset switch too,
+/// other layers and maps using the tileset keep their image. The layer gets its own material on the first swap,
+/// which later swaps update. Texture array chunks aren't supported. When the map changes, e.g. on a hot reload,
+/// and when chunks are respawned by streaming, the layer and tile objects use the tileset image again.
+pub struct SetLayerTilesetTexture {
+    pub chunk: Entity,
+    pub texture: Handle<Texture>,
+}
+
+impl Command for SetLayerTilesetTexture {
+    fn write(self: Box<Self>, world: &mut World) {
+        let (map_handle, chunk_info, chunk_material, chunk_root) = match (
+            world.get::<Handle<Map>>(self.chunk),
+            world.get::<ChunkInfo>(self.chunk),
+            world.get::<Handle<ColorMaterial>>(self.chunk),
+            world.get::<Parent>(self.chunk),
+        ) {
+            (Some(map_handle), Some(chunk_info), Some(chunk_material), Some(chunk_root)) => (
+                map_handle.clone(),
+                chunk_info.clone(),
+                chunk_material.clone(),
+                chunk_root.0,
+            ),
+            _ => return,
+        };
+        let tileset_id = match chunk_info.tileset {
+            ChunkTileset::Single(tileset_id) => tileset_id,
+            ChunkTileset::Array => {
+                warn!(
+                    "Layer \"{}\" is drawn with a texture array, its tileset textures can't be swapped",
+                    chunk_info.layer_name
+                );
+                return;
+            }
+        };
+
+        // the material shared by every layer drawn with the tileset image
+        let tileset_material = world.get_resource::<Assets<Map>>().and_then(|maps| {
+            let map = maps.get(&map_handle)?;
+            let tileset = map
+                .map
+                .tilesets
+                .iter()
+                .find(|tileset| tileset.first_gid == tileset_id.0)?;
+            let image_path = map.image_path(tileset.images.first()?);
+            world
+                .get_resource::<TilesetMaterials>()?
+                .get(&image_path)
+                .cloned()
+        });
+        let layer_material = {
+            let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+            if Some(&chunk_material) != tileset_material.as_ref() {
+                // swapped before, the layer's chunks already share their own material
+                if let Some(material) = materials.get_mut(&chunk_material) {
+                    material.texture = Some(self.texture.clone());
+                }
+                None
+            } else {
+                Some(materials.add(self.texture.clone().into()))
+            }
+        };
+        if let Some(layer_material) = layer_material {
+            let mut chunks =
+                world.query::<(&Handle<Map>, &ChunkInfo, &mut Handle<ColorMaterial>)>();
+            for (chunk_map, info, mut chunk_material) in chunks.iter_mut(world) {
+                if *chunk_map == map_handle
+                    && info.layer == chunk_info.layer
+                    && info.tileset == chunk_info.tileset
+                {
+                    *chunk_material = layer_material.clone();
+                }
+            }
+        }
+
+        // the atlas of the tile objects of the map entity the chunk belongs to
+        let mut map_entities =
+            world.query::<(&CreatedMapEntities, &HashMap<u32, Handle<TextureAtlas>>)>();
+        let atlas_handle = map_entities
+            .iter(world)
+            .find(|(created_entities, _)| created_entities.chunk_root == Some(chunk_root))
+            .and_then(|(_, texture_atlas_map)| texture_atlas_map.get(&tileset_id.0).cloned());
+        if let Some(atlas_handle) = atlas_handle {
+            if let Some(atlas) = world
+                .get_resource_mut::<Assets<TextureAtlas>>()
+                .unwrap()
+                .get_mut(&atlas_handle)
+            {
+                atlas.texture = self.texture;
+            }
+        }
+    }
+}
+
+/// Materials of the tileset images by image path, shared by all maps, so maps using the same tileset image
+/// draw their chunks with the same `ColorMaterial`. Materials are kept for the lifetime of the app.
+#[derive(Default)]
+pub struct TilesetMaterials(HashMap<PathBuf, Handle<ColorMaterial>>);
+
+impl TilesetMaterials {
+    pub fn get(&self, image_path: &Path) -> Option<&Handle<ColorMaterial>> {
+        self.0.get(image_path)
+    }
+
+    fn get_or_add(
+        &mut self,
+        image_path: &Path,
+        texture: &Handle<Texture>,
+        materials: &mut Assets<ColorMaterial>,
+    ) -> Handle<ColorMaterial> {
+        self.0
+            .entry(image_path.to_path_buf())
+            .or_insert_with(|| materials.add(texture.clone().into()))
+            .clone()
+    }
+}
+
+// hash of the vertex and index data of a mesh, equal for meshes that draw the same
+fn mesh_hash(mesh: &Mesh) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    // by name, the interleaved vertex buffer follows the random order of the mesh's attribute map
+    for name in ["Vertex_Position", "Vertex_Uv", "Vertex_Color"].iter() {
+        mesh.attribute(*name)
+            .map(|values| values.get_bytes())
+            .hash(&mut hasher);
+    }
+    mesh.get_index_buffer_bytes().hash(&mut hasher);
+    hasher.finish()
+}
+
+// atlas of a tileset image with a sprite per tile, in tile id order, skipping its margin and spacing
+fn tileset_atlas(tileset: &tiled::Tileset, texture: Handle<Texture>) -> TextureAtlas {
+    let image = tileset.images.first().unwrap();
+    let image_size = Vec2::new(image.width as f32, image.height as f32);
+    let tile_size = Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32);
+    let margin = tileset.margin as f32;
+    let spacing = tileset.spacing as f32;
+    // no spacing after the last column and row, see atlas_cell
+    let cells = ((image_size - Vec2::splat(2.0 * margin) + Vec2::splat(spacing))
+        / (tile_size + Vec2::splat(spacing)))
+    .floor()
+    .max(Vec2::ZERO);
+
+    let mut atlas = TextureAtlas::new_empty(texture, image_size);
+    for row in 0..cells.y as u32 {
+        for column in 0..cells.x as u32 {
+            let min = Vec2::splat(margin)
+                + Vec2::new(column as f32, row as f32) * (tile_size + Vec2::splat(spacing));
+            atlas.add_texture(bevy::sprite::Rect {
+                min,
+                max: min + tile_size,
+            });
+        }
+    }
+    atlas
+}
+
+// chunks of layers hidden in Tiled are spawned invisible
+pub(crate) fn layer_visible(layer: &MapLayer) -> Visible {
+    Visible {
+        is_visible: layer.visible,
+        is_transparent: true,
+        ..Default::default()
+    }
+}
+
+pub(crate) fn layer_transform(
+    tile_map_transform: &Transform,
+    layer: &MapLayer,
+    tileset_layer: &TilesetLayer,
+    depth_config: &DepthConfig,
+) -> Transform {
+    *tile_map_transform
+        * Transform::from_translation(Vec3::new(
+            tileset_layer.offset_x,
+            -tileset_layer.offset_y,
+            depth_config.layer_z(layer.layer_index),
+        ))
+}
+
+/// Points the chunks and object atlases of the changed maps at their tileset images, which may have been swapped,
+/// and adds the materials and atlases of tilesets the map entity has none for yet.
+pub fn update_tileset_materials(
+    asset_server: Res<AssetServer>,
+    mut map_events: EventReader<AssetEvent<Map>>,
+    maps: Res<Assets<Map>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut tileset_materials: ResMut<TilesetMaterials>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut chunk_query: Query<(&Handle<Map>, &ChunkInfo, &mut Handle<ColorMaterial>)>,
+    mut query: Query<(
+        &Handle<Map>,
+        &mut HashMap<u32, Handle<ColorMaterial>>,
+        &mut HashMap<u32, Handle<TextureAtlas>>,
+    )>,
+) {
+    let changed_maps = changed_maps(&mut map_events);
+
+    for (map_handle, mut materials_map, mut texture_atlas_map) in query.iter_mut() {
+        if !changed_maps.contains(map_handle) {
+            continue;
+        }
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        for tileset in &map.map.tilesets {
+            if materials_map.contains_key(&tileset.first_gid) {
+                // the tileset image may have been swapped, in the map or with SetLayerTilesetTexture,
+                // point the existing chunks at its material
+                let texture_path = map.image_path(tileset.images.first().unwrap());
+                let texture_handle = asset_server.load(texture_path.clone());
+                let image_material =
+                    tileset_materials.get_or_add(&texture_path, &texture_handle, &mut materials);
+                for (chunk_map, chunk_info, mut chunk_material) in chunk_query.iter_mut() {
+                    if chunk_map == map_handle
+                        && chunk_info.tileset == ChunkTileset::Single(TilesetId(tileset.first_gid))
+                        && *chunk_material != image_material
+                    {
+                        *chunk_material = image_material.clone();
+                    }
+                }
+                materials_map.insert(tileset.first_gid, image_material);
+                if let Some(atlas_handle) = texture_atlas_map.get(&tileset.first_gid) {
+                    if texture_atlases
+                        .get(atlas_handle)
+                        .map_or(false, |atlas| atlas.texture != texture_handle)
+                    {
+                        texture_atlases.get_mut(atlas_handle).unwrap().texture = texture_handle;
+                    }
+                }
+            } else {
+                let texture_path = map.image_path(tileset.images.first().unwrap());
+                let texture_handle = asset_server.load(texture_path.clone());
+                materials_map.insert(
+                    tileset.first_gid,
+                    tileset_materials.get_or_add(&texture_path, &texture_handle, &mut materials),
+                );
+
+                // only generate texture_atlas for tilesets used in objects
+                let object_gids: Vec<_> = map
+                    .groups
+                    .iter()
+                    .flat_map(|og| og.objects.iter().map(|o| o.tileset_gid))
+                    .collect();
+                if object_gids.contains(&Some(tileset.first_gid)) {
+                    // For simplicity use textureAtlasSprite for object layers
+                    // these insertions should be limited to sprites referenced by objects
+                    let atlas = tileset_atlas(tileset, texture_handle.clone());
+                    let tile_count = atlas.len() as u32;
+
+                    let has_new = (0..tile_count).fold(false, |total, next| {
+                        total || !texture_atlas_map.contains_key(&(tileset.first_gid + next))
+                    });
+                    if has_new {
+                        let atlas_handle = texture_atlases.add(atlas);
+                        for i in 0..tile_count {
+                            if texture_atlas_map.contains_key(&(tileset.first_gid + i)) {
+                                continue;
+                            }
+                            // println!("insert: {}", tileset.first_gid + i);
+                            texture_atlas_map.insert(tileset.first_gid + i, atlas_handle.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Spawns the chunks of the maps being spawned with a material per tileset, under their chunk root.
+/// Chunks with the same tiles share a mesh. Maps drawn with a texture array get theirs from
+/// `spawn_array_chunks`, and streamed maps from `stream_map_chunks`.
+pub fn spawn_map_chunks(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut maps: ResMut<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    query: Query<(
+        Entity,
+        &Handle<Map>,
+        &HashMap<u32, Handle<ColorMaterial>>,
+        &DepthConfig,
+        &LoadHiddenLayers,
+        &LayerFilter,
+        &TilesetTextureArray,
+        &StreamChunks,
+        &PremultipliedAlpha,
+        &CustomPipeline,
+        &CreatedMapEntities,
+    )>,
+) {
+    // layers whose chunks are spawned from the meshes built with the map, for each map being spawned
+    let mut spawned_layers = HashMap::<Handle<Map>, HashSet<u32>>::default();
+    for (
+        _,
+        map_handle,
+        _,
+        _,
+        _,
+        layer_filter,
+        texture_array,
+        stream_chunks,
+        _,
+        _,
+        created_entities,
+    ) in query.iter()
+    {
+        let map = match maps.get(map_handle) {
+            Some(map) if created_entities.spawning.is_some() => map,
+            _ => continue,
+        };
+        let layers = spawned_layers.entry(map_handle.clone()).or_default();
+        if chunks_spawned_elsewhere(map, texture_array, stream_chunks) {
+            continue;
+        }
+        for (layer_id, layer) in map.layers.iter().enumerate() {
+            if layer_filter.includes(layer) {
+                layers.insert(layer_id as u32);
+            }
+        }
+    }
+
+    // chunks with the same tiles share a mesh, their vertices are relative to the chunk origin
+    let mut map_meshes = HashMap::<Handle<Map>, Vec<(u32, u32, Vec2, Handle<Mesh>)>>::default();
+    for (map_handle, layers) in spawned_layers {
+        let map = maps.get_mut(&map_handle).unwrap();
+        // the meshes of layers left out by every LayerFilter are dropped
+        let built = if layers.is_empty() {
+            map.meshes.clear();
+            Vec::new()
+        } else {
+            map.take_meshes()
+        };
+        let mut mesh_handles = HashMap::<u64, Handle<Mesh>>::default();
+        let mesh_list = built
+            .into_iter()
+            .filter(|(layer_id, _, _, _)| layers.contains(layer_id))
+            .map(|(layer_id, tileset_guid, chunk_origin, mesh)| {
+                let handle = mesh_handles
+                    .entry(mesh_hash(&mesh))
+                    .or_insert_with(|| meshes.add(mesh))
+                    .clone();
+                (layer_id, tileset_guid, chunk_origin, handle)
+            })
+            .collect();
+        map_meshes.insert(map_handle, mesh_list);
+    }
+
+    for (
+        map_entity,
+        map_handle,
+        materials_map,
+        depth_config,
+        load_hidden_layers,
+        layer_filter,
+        texture_array,
+        stream_chunks,
+        premultiplied_alpha,
+        custom_pipeline,
+        created_entities,
+    ) in query.iter()
+    {
+        let (placement, chunk_root, map, mesh_list) = match (
+            created_entities.spawning,
+            created_entities.chunk_root,
+            maps.get(map_handle),
+            map_meshes.get(map_handle),
+        ) {
+            (Some(placement), Some(chunk_root), Some(map), Some(mesh_list)) => {
+                (placement, chunk_root, map, mesh_list)
+            }
+            _ => continue,
+        };
+
+        let color_keys = transparent_color_keys(&map.map.tilesets, |image| {
+            asset_server.load(map.image_path(image))
+        });
+        if !color_keys.is_empty() {
+            commands
+                .entity(map_entity)
+                .insert(TransparentColorKeys { keys: color_keys });
+        }
+        if chunks_spawned_elsewhere(map, texture_array, stream_chunks) {
+            continue;
+        }
+
+        let mut chunk_entities: Vec<Entity> = Default::default();
+        for (layer_id, layer) in map.layers.iter().enumerate() {
+            if !layer_filter.includes(layer) {
+                continue;
+            }
+            for tileset_layer in layer.tileset_layers.iter() {
+                let material_handle = materials_map.get(&tileset_layer.tileset_guid).unwrap();
+                let layer_transform = layer_transform(
+                    &placement.content_transform,
+                    layer,
+                    tileset_layer,
+                    depth_config,
+                );
+
+                let chunk_meshes: Vec<(Vec2, Handle<Mesh>)> = if layer.visible {
+                    mesh_list
+                        .iter()
+                        .filter(|(mesh_layer_id, tileset_guid, _, _)| {
+                            *mesh_layer_id == layer_id as u32
+                                && *tileset_guid == tileset_layer.tileset_guid
+                        })
+                        .map(|(_, _, chunk_origin, mesh)| (*chunk_origin, mesh.clone()))
+                        .collect()
+                } else if load_hidden_layers.0 {
+                    tileset_layer
+                        .chunks
+                        .iter()
+                        .flatten()
+                        .filter_map(|chunk| {
+                            let mesh = chunk.build_uv_mesh(tileset_layer.tileset_guid)?;
+                            Some((chunk.origin(), meshes.add(mesh)))
+                        })
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                for (chunk_origin, mesh) in chunk_meshes.iter() {
+                    // TODO: Sadly bevy doesn't support multiple meshes on a single entity with multiple materials.
+                    // Change this once it does.
+
+                    // Instead for now spawn a new entity per chunk.
+                    let chunk_entity = commands
+                        .spawn_bundle(ChunkBundle {
+                            material: material_handle.clone(),
+                            mesh: mesh.clone(),
+                            map_parent: map_handle.clone(),
+                            render_pipeline: tile_map_pipelines(
+                                premultiplied_alpha,
+                                custom_pipeline,
+                                LayerBlend::from_properties(&layer.properties),
+                            ),
+                            transform: layer_transform
+                                * Transform::from_translation(chunk_origin.extend(0.0)),
+                            visible: layer_visible(layer),
+                            ..Default::default()
+                        })
+                        .insert(LayerProperties(layer.properties.clone()))
+                        .insert(LayerClass(layer.class.clone()))
+                        .insert(ChunkInfo {
+                            layer: LayerId(layer.tiled_layer),
+                            layer_name: layer.name.clone(),
+                            tileset: ChunkTileset::Single(TilesetId(tileset_layer.tileset_guid)),
+                        })
+                        .id();
+                    chunk_entities.push(chunk_entity);
+                }
+            }
+        }
+        commands.entity(chunk_root).push_children(&chunk_entities);
+    }
+}
+
+/// Spawns the chunks of the maps being spawned with `TilesetTextureArray`, a mesh per chunk for the tiles of
+/// every tileset, drawn with the map's texture array. Falls back to `spawn_map_chunks` when the tileset images
+/// can't share an array texture.
+pub fn spawn_array_chunks(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut array_materials: ResMut<Assets<TileMapArrayMaterial>>,
+    query: Query<(
+        Entity,
+        &Handle<Map>,
+        &DepthConfig,
+        &LoadHiddenLayers,
+        &LayerFilter,
+        &TilesetTextureArray,
+        &StreamChunks,
+        &PremultipliedAlpha,
+        &CustomPipeline,
+        &CreatedMapEntities,
+    )>,
+) {
+    for (
+        map_entity,
+        map_handle,
+        depth_config,
+        load_hidden_layers,
+        layer_filter,
+        texture_array,
+        stream_chunks,
+        premultiplied_alpha,
+        custom_pipeline,
+        created_entities,
+    ) in query.iter()
+    {
+        let (placement, chunk_root, map) = match (
+            created_entities.spawning,
+            created_entities.chunk_root,
+            maps.get(map_handle),
+        ) {
+            (Some(placement), Some(chunk_root), Some(map))
+                if texture_array.0 && stream_chunks.0.is_none() =>
+            {
+                (placement, chunk_root, map)
+            }
+            _ => continue,
+        };
+        if !can_use_texture_array(&map.map.tilesets) {
+            warn!("Tileset images can't share an array texture, falling back to a material per tileset");
+            continue;
+        }
+
+        let sources = map
+            .map
+            .tilesets
+            .iter()
+            .map(|tileset| asset_server.load(map.image_path(tileset.images.first().unwrap())))
+            .collect::<Vec<Handle<Texture>>>();
+        let texture = textures.add(empty_texture_array(map.map.tilesets.len() as u32));
+        commands
+            .entity(map_entity)
+            .insert(TilesetArrayTextureSources {
+                texture: texture.clone(),
+                sources,
+            });
+        let array_material = array_materials.add(TileMapArrayMaterial { texture });
+
+        let mut chunk_entities: Vec<Entity> = Default::default();
+        for layer in map.layers.iter() {
+            if !layer_filter.includes(layer) || !(layer.visible || load_hidden_layers.0) {
+                continue;
+            }
+            let (offset_x, offset_y) = layer
+                .tileset_layers
+                .first()
+                .map_or((0.0, 0.0), |tileset_layer| {
+                    (tileset_layer.offset_x, tileset_layer.offset_y)
+                });
+            let layer_transform = placement.content_transform
+                * Transform::from_translation(Vec3::new(
+                    offset_x,
+                    -offset_y,
+                    depth_config.layer_z(layer.layer_index),
+                ));
+            for (chunk_origin, mesh) in build_array_meshes(layer, &map.map.tilesets) {
+                let chunk_entity = commands
+                    .spawn_bundle(ArrayChunkBundle {
+                        material: array_material.clone(),
+                        mesh: meshes.add(mesh),
+                        map_parent: map_handle.clone(),
+                        render_pipeline: tile_map_array_pipelines(
+                            premultiplied_alpha,
+                            custom_pipeline,
+                            LayerBlend::from_properties(&layer.properties),
+                        ),
+                        transform: layer_transform
+                            * Transform::from_translation(chunk_origin.extend(0.0)),
+                        visible: layer_visible(layer),
+                        ..Default::default()
+                    })
+                    .insert(LayerProperties(layer.properties.clone()))
+                    .insert(LayerClass(layer.class.clone()))
+                    .insert(ChunkInfo {
+                        layer: LayerId(layer.tiled_layer),
+                        layer_name: layer.name.clone(),
+                        tileset: ChunkTileset::Array,
+                    })
+                    .id();
+                chunk_entities.push(chunk_entity);
+            }
+        }
+        commands.entity(chunk_root).push_children(&chunk_entities);
+    }
+}
+
+// maps whose chunks are spawned by spawn_array_chunks or stream_map_chunks rather than from the built meshes
+fn chunks_spawned_elsewhere(
+    map: &Map,
+    texture_array: &TilesetTextureArray,
+    stream_chunks: &StreamChunks,
+) -> bool {
+    (texture_array.0 && can_use_texture_array(&map.map.tilesets)) || stream_chunks.0.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::parse_test_map;
+
+    #[test]
+    fn repeated_chunks_build_the_same_mesh() {
+        let mut map =
+            parse_test_map(include_str!("../../tests/fixtures/repeated-chunks.tmx")).unwrap();
+        let meshes = map.take_meshes();
+        assert_eq!(meshes.len(), 2);
+        let (_, _, first_origin, first) = &meshes[0];
+        let (_, _, second_origin, second) = &meshes[1];
+        // the same tile 32 tiles apart
+        assert_eq!(*second_origin - *first_origin, Vec2::new(32.0 * 16.0, 0.0));
+        assert_eq!(mesh_hash(first), mesh_hash(second));
+    }
+
+    #[test]
+    fn map_changes_undo_layer_tileset_swaps() {
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<Map>()
+            .add_asset::<ColorMaterial>()
+            .add_asset::<Texture>()
+            .add_asset::<TextureAtlas>()
+            .init_resource::<TilesetMaterials>()
+            .add_system(update_tileset_materials.system());
+        let map_handle = app
+            .world_mut()
+            .get_resource_mut::<Assets<Map>>()
+            .unwrap()
+            .add(parse_test_map(include_str!("../../tests/fixtures/tile-object.tmx")).unwrap());
+        let chunk_root = app.world_mut().spawn().id();
+        let map_entity = app
+            .world_mut()
+            .spawn()
+            .insert_bundle((
+                map_handle.clone(),
+                HashMap::<u32, Handle<ColorMaterial>>::default(),
+                HashMap::<u32, Handle<TextureAtlas>>::default(),
+                CreatedMapEntities {
+                    chunk_root: Some(chunk_root),
+                    ..Default::default()
+                },
+            ))
+            .id();
+        // asset events are sent after the systems that read them
+        app.app.update();
+        app.app.update();
+
+        let world = app.world_mut();
+        let image_material = world
+            .get::<HashMap<u32, Handle<ColorMaterial>>>(map_entity)
+            .unwrap()
+            .get(&1)
+            .unwrap()
+            .clone();
+        let atlas = world
+            .get::<HashMap<u32, Handle<TextureAtlas>>>(map_entity)
+            .unwrap()
+            .get(&2)
+            .unwrap()
+            .clone();
+        let image = world
+            .get_resource::<Assets<TextureAtlas>>()
+            .unwrap()
+            .get(&atlas)
+            .unwrap()
+            .texture
+            .clone();
+        let chunk = world
+            .spawn()
+            .insert_bundle((
+                map_handle.clone(),
+                ChunkInfo {
+                    layer: LayerId(0),
+                    layer_name: "ground".to_string(),
+                    tileset: ChunkTileset::Single(TilesetId(1)),
+                },
+                image_material.clone(),
+                Parent(chunk_root),
+            ))
+            .id();
+        let snow = world
+            .get_resource_mut::<Assets<Texture>>()
+            .unwrap()
+            .add(Texture::default());
+        Box::new(SetLayerTilesetTexture {
+            chunk,
+            texture: snow.clone(),
+        })
+        .write(world);
+        assert_ne!(
+            world.get::<Handle<ColorMaterial>>(chunk).unwrap(),
+            &image_material
+        );
+        assert_eq!(
+            world
+                .get_resource::<Assets<TextureAtlas>>()
+                .unwrap()
+                .get(&atlas)
+                .unwrap()
+                .texture,
+            snow
+        );
+
+        // getting the map mutably sends AssetEvent::Modified, like a hot reload
+        world
+            .get_resource_mut::<Assets<Map>>()
+            .unwrap()
+            .get_mut(&map_handle);
+        app.app.update();
+        app.app.update();
+
+        let world = app.world_mut();
+        assert_eq!(
+            world.get::<Handle<ColorMaterial>>(chunk).unwrap(),
+            &image_material
+        );
+        assert_eq!(
+            world
+                .get_resource::<Assets<TextureAtlas>>()
+                .unwrap()
+                .get(&atlas)
+                .unwrap()
+                .texture,
+            image
+        );
+    }
+
+    #[test]
+    fn negative_layer_offsets_scale_with_the_map() {
+        let map = parse_test_map(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.5" orientation="orthogonal" renderorder="right-down" width="2" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+ </tileset>
+ <layer id="1" name="ground" width="2" height="1" offsetx="-8" offsety="-4">
+  <data encoding="csv">
+0,1
+</data>
+ </layer>
+</map>
+"#,
+        )
+        .unwrap();
+        let layer = &map.layers[0];
+        let tileset_layer = &layer.tileset_layers[0];
+        let tile = tileset_layer
+            .chunks
+            .iter()
+            .flatten()
+            .flat_map(|chunk| chunk.tiles.iter().flatten())
+            .find(|tile| tile.tile_id != 0)
+            .unwrap();
+        let center = Vec3::new(
+            (tile.vertex.x + tile.vertex.z) / 2.0,
+            (tile.vertex.y + tile.vertex.w) / 2.0,
+            0.0,
+        );
+        assert_eq!(center.truncate(), Vec2::new(24.0, -8.0));
+        let origin = Transform {
+            translation: Vec3::new(100.0, 50.0, 0.0),
+            scale: Vec3::splat(2.0),
+            ..Default::default()
+        };
+        let transform = layer_transform(&origin, layer, tileset_layer, &DepthConfig::default());
+        // (24 - 8, -8 + 4) scaled by 2 around (100, 50), the offset moves the tile left and up
+        assert_eq!(
+            transform.mul_vec3(center).truncate(),
+            Vec2::new(132.0, 42.0)
+        );
+    }
+}
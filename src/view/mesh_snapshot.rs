@@ -0,0 +1,99 @@
+//! Snapshot tests of the chunk meshes built for the fixture maps in `tests/fixtures`, guarding the vertex and uv
+//! math of `TilesetLayer::new`, `TileChunk::from_layer_and_tileset` and `LayerChunk::build_uv_mesh`.
+
+use std::{fmt::Write, fs, path::Path};
+
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+
+use crate::Map;
+
+/// Builds the chunk meshes of `tests/fixtures/<name>.tmx` and compares their positions, uvs and indices with
+/// `tests/fixtures/<name>.golden`. Run with `UPDATE_SNAPSHOTS=1` to write the golden file from the current build.
+fn assert_mesh_snapshot(name: &str) {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let map_path = format!("{}.tmx", name);
+    let bytes = fs::read(fixtures.join(&map_path)).unwrap();
+    let map = Map::try_from_bytes(Path::new(""), Path::new(&map_path), bytes).unwrap();
+    let snapshot = mesh_snapshot(&map);
+
+    let golden_path = fixtures.join(format!("{}.golden", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&golden_path, snapshot).unwrap();
+        return;
+    }
+    let golden = fs::read_to_string(&golden_path).unwrap_or_else(|_| {
+        panic!(
+            "{} is missing, run with UPDATE_SNAPSHOTS=1 to write it",
+            golden_path.display()
+        )
+    });
+    // line by line, so a failure points at the first vertex that changed
+    for (line, (built, expected)) in snapshot.lines().zip(golden.lines()).enumerate() {
+        assert_eq!(built, expected, "{}.golden, line {}", name, line + 1);
+    }
+    assert_eq!(
+        snapshot.lines().count(),
+        golden.lines().count(),
+        "{}.golden, line count",
+        name
+    );
+}
+
+// a header per mesh, a line per vertex with its position and uv, and the indices
+fn mesh_snapshot(map: &Map) -> String {
+    let mut out = String::new();
+    for (layer_id, tileset_guid, origin, mesh) in map.meshes.iter() {
+        writeln!(
+            out,
+            "mesh layer {} tileset {} origin {} {}",
+            layer_id,
+            tileset_guid,
+            number(origin.x),
+            number(origin.y)
+        )
+        .unwrap();
+        let positions = match mesh.attribute("Vertex_Position") {
+            Some(VertexAttributeValues::Float3(positions)) => positions,
+            _ => panic!("chunk mesh without positions"),
+        };
+        let uvs = match mesh.attribute("Vertex_Uv") {
+            Some(VertexAttributeValues::Float2(uvs)) => uvs,
+            _ => panic!("chunk mesh without uvs"),
+        };
+        for (position, uv) in positions.iter().zip(uvs.iter()) {
+            writeln!(
+                out,
+                "{} {} uv {} {}",
+                number(position[0]),
+                number(position[1]),
+                number(uv[0]),
+                number(uv[1])
+            )
+            .unwrap();
+        }
+        let indices: Vec<String> = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|index| index.to_string()).collect(),
+            Some(Indices::U32(indices)) => indices.iter().map(|index| index.to_string()).collect(),
+            None => Vec::new(),
+        };
+        writeln!(out, "indices {}", indices.join(" ")).unwrap();
+    }
+    out
+}
+
+// rounded so golden files don't depend on the last bits of float math, and without negative zeros
+fn number(value: f32) -> String {
+    format!("{:.5}", value + 0.0)
+}
+
+#[test]
+fn flipped_tiles() {
+    // tile 2 with each combination of the diagonal, horizontal and vertical flip flags
+    assert_mesh_snapshot("flips");
+}
+
+#[test]
+fn spaced_tileset() {
+    // a tileset image with a 1px margin and 2px spacing between its tiles
+    assert_mesh_snapshot("spacing");
+}
@@ -1,20 +1,227 @@
+#[cfg(not(feature = "web"))]
+use bevy::render::render_graph::AssetRenderResourcesNode;
 use bevy::{
+    core::Bytes,
     prelude::*,
     reflect::TypeUuid,
     render::{
         pipeline::{
             BlendFactor, BlendOperation, BlendState, ColorTargetState, ColorWrite, CompareFunction,
-            DepthBiasState, DepthStencilState, PipelineDescriptor, StencilFaceState, StencilState,
+            CullMode, DepthBiasState, DepthStencilState, FrontFace, PipelineDescriptor,
+            PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelines,
+            StencilFaceState, StencilState,
         },
+        render_graph::{base, RenderGraph, RenderResourcesNode},
+        renderer::{RenderResource, RenderResourceType, RenderResources},
         shader::{ShaderStage, ShaderStages},
-        texture::TextureFormat,
+        texture::{Texture, TextureFormat},
     },
 };
 
+use crate::PropertiesExt;
+#[cfg(not(feature = "web"))]
+use crate::TileMapArrayMaterial;
+
 pub const TILE_MAP_PIPELINE_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 4129645945969645246);
 
-pub fn build_tile_map_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescriptor {
+pub const TILE_MAP_ARRAY_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 7262581304717330584);
+
+pub const TILE_MAP_PREMULTIPLIED_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 1817034580164985463);
+
+pub const TILE_MAP_ARRAY_PREMULTIPLIED_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 5404393213582409621);
+
+pub const TILE_MAP_ADDITIVE_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 6040548869667291061);
+
+pub const TILE_MAP_ARRAY_ADDITIVE_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 403054788378418849);
+
+pub const TILE_MAP_PREMULTIPLIED_ADDITIVE_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 8102549526754996118);
+
+pub const TILE_MAP_ARRAY_PREMULTIPLIED_ADDITIVE_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 7415833791350271676);
+
+pub const TILE_MAP_ARRAY_MATERIAL_NODE: &str = "tile_map_array_material";
+
+pub const LAYER_TINT_NODE: &str = "layer_tint";
+
+/// When true, the map's tileset images are treated as having premultiplied alpha, as some exporters
+/// produce, and blended with `One`/`OneMinusSrcAlpha` so tile edges don't darken.
+#[derive(Default, Reflect)]
+#[reflect(Component)]
+pub struct PremultipliedAlpha(pub bool);
+
+/// Pipeline the map's tile chunks are drawn with instead of the crate's, for maps needing their own shaders.
+/// Its shaders must take the chunk vertex attributes and the chunk material: `ColorMaterial`, or
+/// `TileMapArrayMaterial` when the map uses `TilesetTextureArray`, see the crate's tile_map shaders.
+/// They may also bind the chunk's `LayerTint`.
+/// `PremultipliedAlpha` doesn't apply to it, blending is up to the pipeline.
+#[derive(Default)]
+pub struct CustomPipeline(pub Option<Handle<PipelineDescriptor>>);
+
+/// How a layer's tiles blend with what is drawn below them. Set from a `blend` string property on the
+/// layer in Tiled: `add` draws the layer additively, for glow and light layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerBlend {
+    Alpha,
+    Additive,
+}
+
+impl LayerBlend {
+    pub fn from_properties(properties: &tiled::Properties) -> Self {
+        match properties.get_string("blend") {
+            Some("add") => LayerBlend::Additive,
+            _ => LayerBlend::Alpha,
+        }
+    }
+}
+
+impl Default for LayerBlend {
+    fn default() -> Self {
+        LayerBlend::Alpha
+    }
+}
+
+/// Color the tiles of a chunk are multiplied with, white by default, in the tile map shaders' `LayerTint`
+/// uniform. Every chunk of a layer has one, so a layer is tinted or faded by setting it on the chunks with the
+/// layer's `ChunkInfo`, and hidden with their `Visible`, without rebuilding meshes or materials. Chunks respawned
+/// by streaming or a reload are white again.
+#[derive(Debug, Clone, Reflect, RenderResources, TypeUuid)]
+#[render_resources(from_self)]
+#[uuid = "59937ba3-e97c-472d-b43b-85f25b135a92"]
+#[reflect(Component)]
+pub struct LayerTint(pub Color);
+
+impl Default for LayerTint {
+    fn default() -> Self {
+        LayerTint(Color::WHITE)
+    }
+}
+
+impl RenderResource for LayerTint {
+    fn resource_type(&self) -> Option<RenderResourceType> {
+        Some(RenderResourceType::Buffer)
+    }
+
+    fn buffer_byte_len(&self) -> Option<usize> {
+        Some(16)
+    }
+
+    // linear like the vertex colors it multiplies
+    fn write_buffer_bytes(&self, buffer: &mut [u8]) {
+        self.0.write_bytes(buffer);
+    }
+
+    fn texture(&self) -> Option<&Handle<Texture>> {
+        None
+    }
+}
+
+/// Render pipelines for the tile chunks of a layer, see `PremultipliedAlpha`, `CustomPipeline` and `LayerBlend`.
+pub fn tile_map_pipelines(
+    premultiplied: &PremultipliedAlpha,
+    custom: &CustomPipeline,
+    blend: LayerBlend,
+) -> RenderPipelines {
+    let handle = match (&custom.0, premultiplied.0, blend) {
+        (Some(handle), _, _) => handle.clone(),
+        (None, false, LayerBlend::Alpha) => TILE_MAP_PIPELINE_HANDLE.typed(),
+        (None, true, LayerBlend::Alpha) => TILE_MAP_PREMULTIPLIED_PIPELINE_HANDLE.typed(),
+        (None, false, LayerBlend::Additive) => TILE_MAP_ADDITIVE_PIPELINE_HANDLE.typed(),
+        (None, true, LayerBlend::Additive) => {
+            TILE_MAP_PREMULTIPLIED_ADDITIVE_PIPELINE_HANDLE.typed()
+        }
+    };
+    RenderPipelines::from_pipelines(vec![RenderPipeline::new(handle)])
+}
+
+/// Render pipelines for the texture array chunks of a layer, see `PremultipliedAlpha`, `CustomPipeline`
+/// and `LayerBlend`.
+pub fn tile_map_array_pipelines(
+    premultiplied: &PremultipliedAlpha,
+    custom: &CustomPipeline,
+    blend: LayerBlend,
+) -> RenderPipelines {
+    let handle = match (&custom.0, premultiplied.0, blend) {
+        (Some(handle), _, _) => handle.clone(),
+        (None, false, LayerBlend::Alpha) => TILE_MAP_ARRAY_PIPELINE_HANDLE.typed(),
+        (None, true, LayerBlend::Alpha) => TILE_MAP_ARRAY_PREMULTIPLIED_PIPELINE_HANDLE.typed(),
+        (None, false, LayerBlend::Additive) => TILE_MAP_ARRAY_ADDITIVE_PIPELINE_HANDLE.typed(),
+        (None, true, LayerBlend::Additive) => {
+            TILE_MAP_ARRAY_PREMULTIPLIED_ADDITIVE_PIPELINE_HANDLE.typed()
+        }
+    };
+    RenderPipelines::from_pipelines(vec![RenderPipeline::new(handle)])
+}
+
+pub fn build_tile_map_pipeline(
+    shaders: &mut Assets<Shader>,
+    premultiplied: bool,
+    blend: LayerBlend,
+    color_format: TextureFormat,
+) -> PipelineDescriptor {
+    build_pipeline(
+        ShaderStages {
+            vertex: shaders.add(Shader::from_glsl(
+                ShaderStage::Vertex,
+                if cfg!(feature = "web") {
+                    include_str!("tile_map_webgl2.vert")
+                } else {
+                    include_str!("tile_map.vert")
+                },
+            )),
+            fragment: Some(shaders.add(Shader::from_glsl(
+                ShaderStage::Fragment,
+                if cfg!(feature = "web") {
+                    include_str!("tile_map_webgl2.frag")
+                } else {
+                    include_str!("tile_map.frag")
+                },
+            ))),
+        },
+        premultiplied,
+        blend,
+        color_format,
+    )
+}
+
+/// Pipeline drawing chunks that index a `TileMapArrayMaterial` with their `Vertex_Layer`.
+/// Not available with the `web` feature, the array shaders have no WebGL2 variant.
+#[cfg(not(feature = "web"))]
+pub fn build_tile_map_array_pipeline(
+    shaders: &mut Assets<Shader>,
+    premultiplied: bool,
+    blend: LayerBlend,
+    color_format: TextureFormat,
+) -> PipelineDescriptor {
+    build_pipeline(
+        ShaderStages {
+            vertex: shaders.add(Shader::from_glsl(
+                ShaderStage::Vertex,
+                include_str!("tile_map_array.vert"),
+            )),
+            fragment: Some(shaders.add(Shader::from_glsl(
+                ShaderStage::Fragment,
+                include_str!("tile_map_array.frag"),
+            ))),
+        },
+        premultiplied,
+        blend,
+        color_format,
+    )
+}
+
+fn build_pipeline(
+    shader_stages: ShaderStages,
+    premultiplied: bool,
+    blend: LayerBlend,
+    color_format: TextureFormat,
+) -> PipelineDescriptor {
     PipelineDescriptor {
         depth_stencil: Some(DepthStencilState {
             format: TextureFormat::Depth32Float,
@@ -34,10 +241,19 @@ pub fn build_tile_map_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescript
             clamp_depth: false,
         }),
         color_target_states: vec![ColorTargetState {
-            format: TextureFormat::Bgra8UnormSrgb,
+            format: color_format,
             color_blend: BlendState {
-                src_factor: BlendFactor::SrcAlpha,
-                dst_factor: BlendFactor::OneMinusSrcAlpha,
+                // premultiplied colors are already scaled by their alpha
+                src_factor: if premultiplied {
+                    BlendFactor::One
+                } else {
+                    BlendFactor::SrcAlpha
+                },
+                // additive layers only brighten what is below them
+                dst_factor: match blend {
+                    LayerBlend::Alpha => BlendFactor::OneMinusSrcAlpha,
+                    LayerBlend::Additive => BlendFactor::One,
+                },
                 operation: BlendOperation::Add,
             },
             alpha_blend: BlendState {
@@ -47,34 +263,83 @@ pub fn build_tile_map_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescript
             },
             write_mask: ColorWrite::ALL,
         }],
-        ..PipelineDescriptor::new(ShaderStages {
-            vertex: shaders.add(Shader::from_glsl(
-                ShaderStage::Vertex,
-                if cfg!(feature = "web") {
-                    include_str!("tile_map_webgl2.vert")
-                } else {
-                    include_str!("tile_map.vert")
-                },
-            )),
-            fragment: Some(shaders.add(Shader::from_glsl(
-                ShaderStage::Fragment,
-                if cfg!(feature = "web") {
-                    include_str!("tile_map_webgl2.frag")
-                } else {
-                    include_str!("tile_map.frag")
-                },
-            ))),
-        })
+        // maps with `YAxis::KeepTiled` are mirrored, which turns their tiles around
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: CullMode::None,
+            polygon_mode: PolygonMode::Fill,
+        },
+        ..PipelineDescriptor::new(shader_stages)
     }
 }
 
-pub(crate) fn add_tile_map_graph(world: &mut World) {
+/// Adds the tile map pipelines, drawing to targets of `color_format`, the `LayerTint` node and the texture array
+/// material node.
+/// With the `web` feature there are no texture array pipelines nor node, see `can_use_texture_array`.
+#[cfg_attr(feature = "web", allow(unused_variables))]
+pub(crate) fn add_tile_map_graph(world: &mut World, color_format: TextureFormat) {
     world.resource_scope(|world, mut pipelines: Mut<Assets<PipelineDescriptor>>| {
         world.resource_scope(|_, mut shaders: Mut<Assets<Shader>>| {
-            pipelines.set_untracked(
-                TILE_MAP_PIPELINE_HANDLE,
-                build_tile_map_pipeline(&mut shaders),
-            );
+            let variants = [
+                (
+                    false,
+                    LayerBlend::Alpha,
+                    TILE_MAP_PIPELINE_HANDLE,
+                    TILE_MAP_ARRAY_PIPELINE_HANDLE,
+                ),
+                (
+                    true,
+                    LayerBlend::Alpha,
+                    TILE_MAP_PREMULTIPLIED_PIPELINE_HANDLE,
+                    TILE_MAP_ARRAY_PREMULTIPLIED_PIPELINE_HANDLE,
+                ),
+                (
+                    false,
+                    LayerBlend::Additive,
+                    TILE_MAP_ADDITIVE_PIPELINE_HANDLE,
+                    TILE_MAP_ARRAY_ADDITIVE_PIPELINE_HANDLE,
+                ),
+                (
+                    true,
+                    LayerBlend::Additive,
+                    TILE_MAP_PREMULTIPLIED_ADDITIVE_PIPELINE_HANDLE,
+                    TILE_MAP_ARRAY_PREMULTIPLIED_ADDITIVE_PIPELINE_HANDLE,
+                ),
+            ];
+            for (premultiplied, blend, handle, array_handle) in variants.iter() {
+                pipelines.set_untracked(
+                    handle.clone(),
+                    build_tile_map_pipeline(&mut shaders, *premultiplied, *blend, color_format),
+                );
+                #[cfg(not(feature = "web"))]
+                pipelines.set_untracked(
+                    array_handle.clone(),
+                    build_tile_map_array_pipeline(
+                        &mut shaders,
+                        *premultiplied,
+                        *blend,
+                        color_format,
+                    ),
+                );
+            }
         });
     });
+
+    let mut render_graph = world.get_resource_mut::<RenderGraph>().unwrap();
+    render_graph.add_system_node(LAYER_TINT_NODE, RenderResourcesNode::<LayerTint>::new(true));
+    render_graph
+        .add_node_edge(LAYER_TINT_NODE, base::node::MAIN_PASS)
+        .unwrap();
+    #[cfg(not(feature = "web"))]
+    {
+        render_graph.add_system_node(
+            TILE_MAP_ARRAY_MATERIAL_NODE,
+            AssetRenderResourcesNode::<TileMapArrayMaterial>::new(true),
+        );
+        render_graph
+            .add_node_edge(TILE_MAP_ARRAY_MATERIAL_NODE, base::node::MAIN_PASS)
+            .unwrap();
+    }
 }
@@ -1,6 +1,7 @@
-use bevy::prelude::*;
+use bevy::math::{Vec2, Vec4};
+#[cfg(not(feature = "headless"))]
 use bevy::{
-    math::{Vec2, Vec4},
+    prelude::*,
     render::{
         draw::Visible,
         mesh::{Indices, VertexAttributeValues},
@@ -10,76 +11,130 @@ use bevy::{
 };
 use tiled::{LayerTile, Tileset};
 
-use crate::{loader::TiledMapLoader, Map, TileMapChunk, TILE_MAP_PIPELINE_HANDLE};
+use crate::loader::TiledMapLoader;
+#[cfg(not(feature = "headless"))]
+use crate::{LayerTint, Map, PropertiesExt, TileMapChunk, TILE_MAP_PIPELINE_HANDLE};
 
 #[derive(Debug)]
 pub struct LayerChunk {
     pub position: Vec2,
     pub tiles: Vec<Vec<TileChunk>>,
+    pub bounds: Vec4, // layer space area of the chunk's tiles, as min x, min y, max x, max y
 }
 
 impl LayerChunk {
+    /// Layer space position the chunk's mesh is built relative to, the min corner of its bounds.
+    /// Chunk entities are placed there, so chunks with the same tiles get the same mesh.
+    pub fn origin(&self) -> Vec2 {
+        Vec2::new(self.bounds.x, self.bounds.y)
+    }
+}
+
+#[cfg(not(feature = "headless"))]
+impl LayerChunk {
+    /// Mesh of the chunk's tiles of the tileset, with positions relative to `origin`.
     pub fn build_uv_mesh(&self, tileset_guid: u32) -> Option<Mesh> {
-        let mut positions: Vec<[f32; 3]> = Vec::new();
-        let mut uvs: Vec<[f32; 2]> = Vec::new();
-        let mut indices: Vec<u32> = Vec::new();
+        // 4 vertices and 6 indices per tile
+        let tile_count = self.quad_count(tileset_guid);
+        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(tile_count * 4);
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(tile_count * 4);
+        let mut colors: Vec<[f32; 4]> = Vec::with_capacity(tile_count * 4);
+        let mut indices: Vec<u32> = Vec::with_capacity(tile_count * 6);
+
+        self.append_quads(
+            tileset_guid,
+            self.origin(),
+            &mut positions,
+            &mut uvs,
+            &mut colors,
+            &mut indices,
+        );
+
+        if positions.len() > 0 {
+            let vertex_count = positions.len();
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            mesh.set_attribute("Vertex_Position", VertexAttributeValues::Float3(positions));
+            mesh.set_attribute("Vertex_Uv", VertexAttributeValues::Float2(uvs));
+            mesh.set_attribute("Vertex_Color", VertexAttributeValues::Float4(colors));
+            mesh.set_indices(Some(compact_indices(indices, vertex_count)));
+            Some(mesh)
+        } else {
+            None
+        }
+    }
 
-        let mut i = 0;
+    /// Number of quads `append_quads` adds for the tileset.
+    pub fn quad_count(&self, tileset_guid: u32) -> usize {
+        self.tiles
+            .iter()
+            .flat_map(|tiles_y| tiles_y.iter())
+            .filter(|tile| tile.tile_id >= tileset_guid)
+            .count()
+    }
+
+    /// Appends a quad per tile of the tileset, indexed after the vertices already present,
+    /// with positions relative to `origin` in layer space.
+    pub fn append_quads(
+        &self,
+        tileset_guid: u32,
+        origin: Vec2,
+        positions: &mut Vec<[f32; 3]>,
+        uvs: &mut Vec<[f32; 2]>,
+        colors: &mut Vec<[f32; 4]>,
+        indices: &mut Vec<u32>,
+    ) {
+        let mut i = positions.len() as u32;
         for tile in self.tiles.iter().flat_map(|tiles_y| tiles_y.iter()) {
             if tile.tile_id < tileset_guid {
                 continue;
             }
 
+            let vertex = tile.vertex - Vec4::new(origin.x, origin.y, origin.x, origin.y);
             // X, Y
-            positions.push([tile.vertex.x, tile.vertex.y, 0.0]);
+            positions.push([vertex.x, vertex.y, 0.0]);
             // X, Y + 1
-            positions.push([tile.vertex.x, tile.vertex.w, 0.0]);
+            positions.push([vertex.x, vertex.w, 0.0]);
             // X + 1, Y + 1
-            positions.push([tile.vertex.z, tile.vertex.w, 0.0]);
+            positions.push([vertex.z, vertex.w, 0.0]);
             // X + 1, Y
-            positions.push([tile.vertex.z, tile.vertex.y, 0.0]);
-
-            let mut next_uvs = [
-                // X, Y
-                [tile.uv.x, tile.uv.w],
-                // X, Y + 1
-                [tile.uv.x, tile.uv.y],
-                // X + 1, Y + 1
-                [tile.uv.z, tile.uv.y],
-                // X + 1, Y
-                [tile.uv.z, tile.uv.w],
-            ];
-            if tile.flip_d {
-                next_uvs.swap(0, 2);
-            }
-            if tile.flip_h {
-                next_uvs.reverse();
-            }
-            if tile.flip_v {
-                next_uvs.reverse();
-                next_uvs.swap(0, 2);
-                next_uvs.swap(1, 3);
-            }
+            positions.push([vertex.z, vertex.y, 0.0]);
 
+            let next_uvs = flipped_uvs(tile.uv, tile.flip_d, tile.flip_h, tile.flip_v);
             next_uvs.iter().for_each(|uv| uvs.push(*uv));
+            colors.extend_from_slice(&[tile.color; 4]);
 
             indices.extend_from_slice(&[i + 0, i + 2, i + 1, i + 0, i + 3, i + 2]);
 
             i += 4;
         }
+    }
+}
 
-        if positions.len() > 0 {
-            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-            mesh.set_attribute("Vertex_Position", VertexAttributeValues::Float3(positions));
-            mesh.set_attribute("Vertex_Uv", VertexAttributeValues::Float2(uvs));
-            mesh.set_indices(Some(Indices::U32(indices)));
-            Some(mesh)
-        } else {
-            None
+/// Uvs of the quad corners X, Y / X, Y + 1 / X + 1, Y + 1 / X + 1, Y for the tile's flip flags.
+/// Tiled flips diagonally first, then horizontally, then vertically, so each corner samples
+/// the image at its own position with those flips undone in reverse order.
+#[cfg(not(feature = "headless"))]
+fn flipped_uvs(uv: Vec4, flip_d: bool, flip_h: bool, flip_v: bool) -> [[f32; 2]; 4] {
+    // corner positions within the tile, in image space where y points down
+    let corners = [[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]];
+    let mut uvs = [[0.0; 2]; 4];
+    for (corner, uv_out) in corners.iter().zip(uvs.iter_mut()) {
+        let [mut s, mut t] = *corner;
+        if flip_v {
+            t = 1.0 - t;
+        }
+        if flip_h {
+            s = 1.0 - s;
         }
+        if flip_d {
+            std::mem::swap(&mut s, &mut t);
+        }
+        *uv_out = [uv.x + (uv.z - uv.x) * s, uv.y + (uv.w - uv.y) * t];
     }
+    uvs
 }
 
+#[cfg(not(feature = "headless"))]
 #[derive(Bundle)]
 pub struct ChunkBundle {
     pub map_parent: Handle<Map>, // tmp:chunks should be child entities of a toplevel map entity.
@@ -87,6 +142,7 @@ pub struct ChunkBundle {
     pub main_pass: MainPass,
     pub material: Handle<ColorMaterial>,
     pub render_pipeline: RenderPipelines,
+    pub tint: LayerTint,
     pub visible: Visible,
     pub draw: Draw,
     pub mesh: Handle<Mesh>,
@@ -94,11 +150,13 @@ pub struct ChunkBundle {
     pub global_transform: GlobalTransform,
 }
 
+#[cfg(not(feature = "headless"))]
 impl Default for ChunkBundle {
     fn default() -> Self {
         Self {
             map_parent: Handle::default(),
             chunk: TileMapChunk,
+            tint: Default::default(),
             visible: Visible {
                 is_transparent: true,
                 ..Default::default()
@@ -125,6 +183,7 @@ pub struct TileChunk {
     pub flip_d: bool,
     pub flip_h: bool,
     pub flip_v: bool,
+    pub color: [f32; 4], // linear, from the tile's `tint` property, white without one
 }
 
 impl TileChunk {
@@ -137,26 +196,28 @@ impl TileChunk {
         let tile_width = tileset.tile_width as f32;
         let tile_height = tileset.tile_height as f32;
         let tile_space = tileset.spacing as f32;
+        let margin = tileset.margin as f32;
         let image = tileset.images.first().unwrap();
         let texture_width = image.width as f32;
         let texture_height = image.height as f32;
-        let columns = ((texture_width + tile_space) / (tile_width + tile_space)).floor(); // account for no end tile
 
-        let tile =
-            (TiledMapLoader::remove_tile_flags(layer_tile.gid) as f32) - tileset.first_gid as f32;
+        let tile = TiledMapLoader::remove_tile_flags(layer_tile.gid) - tileset.first_gid;
+        let (column, row) = atlas_cell(tileset, tile);
 
-        // This calculation is much simpler we only care about getting the remainder
-        // and multiplying that by the tile width.
-        let sprite_sheet_x: f32 =
-            ((tile % columns) * (tile_width + tile_space) - tile_space).floor();
+        #[cfg(not(feature = "headless"))]
+        let color = tileset
+            .tiles
+            .iter()
+            .find(|tileset_tile| tileset_tile.id == tile)
+            .and_then(|tileset_tile| tileset_tile.properties.get_color("tint"))
+            .map_or([1.0; 4], |tint| tint.as_linear_rgba_f32());
+        #[cfg(feature = "headless")]
+        let color = [1.0; 4];
 
-        // Calculation here is (tile / columns).round_down * (tile_space + tile_height) - tile_space
-        // Example: tile 30 / 28 columns = 1.0714 rounded down to 1 * 16 tile_height = 16 Y
-        // which is the 2nd row in the sprite sheet.
-        // Example2: tile 10 / 28 columns = 0.3571 rounded down to 0 * 16 tile_height = 0 Y
-        // which is the 1st row in the sprite sheet.
-        let sprite_sheet_y: f32 =
-            (tile / columns).floor() * (tile_height + tile_space) - tile_space;
+        // Example: tile 30 with 28 columns is in column 2 of row 1, the 2nd row in the sprite sheet.
+        // Tiles start after the margin and are separated by the spacing, like in tileset_atlas.
+        let sprite_sheet_x: f32 = margin + column as f32 * (tile_width + tile_space);
+        let sprite_sheet_y: f32 = margin + row as f32 * (tile_height + tile_space);
 
         // Calculate UV:
         let start_u: f32 = sprite_sheet_x / texture_width;
@@ -172,6 +233,121 @@ impl TileChunk {
             flip_d: layer_tile.flip_d,
             flip_h: layer_tile.flip_h,
             flip_v: layer_tile.flip_v,
+            color,
+        }
+    }
+}
+
+/// Column and row of a tile within its tileset image, given its id in the tileset.
+pub(crate) fn atlas_cell(tileset: &Tileset, tile_id: u32) -> (u32, u32) {
+    let image_width = tileset.images.first().map_or(0, |image| image.width);
+    let columns = grid_cells(image_width, tileset.tile_width, tileset).max(1);
+    (tile_id % columns, tile_id / columns)
+}
+
+/// Number of tiles in a tileset. Tilesets without a `tilecount` are sized by the cells of their image
+/// and the highest tile id they describe, whichever is larger.
+pub(crate) fn tile_count(tileset: &Tileset) -> u32 {
+    if let Some(tilecount) = tileset.tilecount {
+        return tilecount;
+    }
+    let image_cells = tileset.images.first().map_or(0, |image| {
+        grid_cells(image.width, tileset.tile_width, tileset)
+            * grid_cells(image.height, tileset.tile_height, tileset)
+    });
+    let described = tileset
+        .tiles
+        .iter()
+        .map(|tile| tile.id + 1)
+        .max()
+        .unwrap_or(0);
+    image_cells.max(described).max(1)
+}
+
+// tiles fitting along an image side, accounting for no spacing after the last one and the margin around them
+fn grid_cells(image_size: i32, tile_size: u32, tileset: &Tileset) -> u32 {
+    let tile_space = tileset.spacing as f32;
+    ((image_size as f32 - 2.0 * tileset.margin as f32 + tile_space)
+        / (tile_size as f32 + tile_space))
+        .floor()
+        .max(0.0) as u32
+}
+
+/// Index buffer for a mesh of `vertex_count` vertices, with 16 bit indices when they fit.
+#[cfg(not(feature = "headless"))]
+pub(crate) fn compact_indices(indices: Vec<u32>, vertex_count: usize) -> Indices {
+    if vertex_count <= u16::MAX as usize + 1 {
+        Indices::U16(indices.into_iter().map(|index| index as u16).collect())
+    } else {
+        Indices::U32(indices)
+    }
+}
+
+#[cfg(all(test, not(feature = "headless")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flipped_uvs_follow_tiled() {
+        // image corner sampled at the bottom left, top left, top right and bottom right of the tile,
+        // in image space where y points down
+        let cases = [
+            (
+                (false, false, false),
+                [[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]],
+            ),
+            (
+                (false, true, false),
+                [[1.0, 1.0], [1.0, 0.0], [0.0, 0.0], [0.0, 1.0]],
+            ),
+            (
+                (false, false, true),
+                [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+            ),
+            // rotated 180 degrees
+            (
+                (false, true, true),
+                [[1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]],
+            ),
+            // mirrored along the top left to bottom right diagonal
+            (
+                (true, false, false),
+                [[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]],
+            ),
+            // rotated 90 degrees clockwise
+            (
+                (true, true, false),
+                [[1.0, 1.0], [0.0, 1.0], [0.0, 0.0], [1.0, 0.0]],
+            ),
+            // rotated 90 degrees counterclockwise
+            (
+                (true, false, true),
+                [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            ),
+            // mirrored along the top right to bottom left diagonal
+            (
+                (true, true, true),
+                [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]],
+            ),
+        ];
+        for ((flip_d, flip_h, flip_v), expected) in cases.iter() {
+            assert_eq!(
+                flipped_uvs(Vec4::new(0.0, 0.0, 1.0, 1.0), *flip_d, *flip_h, *flip_v),
+                *expected,
+                "d: {}, h: {}, v: {}",
+                flip_d,
+                flip_h,
+                flip_v
+            );
         }
     }
+
+    #[test]
+    fn flipped_uvs_stay_within_the_tile() {
+        let uv = Vec4::new(0.25, 0.5, 0.5, 0.75);
+        assert_eq!(
+            flipped_uvs(uv, true, true, false),
+            [[0.5, 0.75], [0.25, 0.75], [0.25, 0.5], [0.5, 0.5]]
+        );
+    }
 }
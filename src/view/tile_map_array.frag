@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 v_Uv;
+layout(location = 1) in float v_Layer;
+layout(location = 2) in vec4 v_Color;
+
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 1, binding = 0) uniform texture2DArray TileMapArrayMaterial_texture;
+layout(set = 1, binding = 1) uniform sampler TileMapArrayMaterial_texture_sampler;
+
+void main() {
+    o_Target = texture(
+        sampler2DArray(TileMapArrayMaterial_texture, TileMapArrayMaterial_texture_sampler),
+        vec3(v_Uv, v_Layer)) * v_Color;
+}
\ No newline at end of file
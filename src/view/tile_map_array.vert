@@ -0,0 +1,29 @@
+#version 450
+
+layout(location = 0) in vec3 Vertex_Position;
+layout(location = 1) in float Vertex_Layer;
+layout(location = 2) in vec2 Vertex_Uv;
+layout(location = 3) in vec4 Vertex_Color;
+
+layout(location = 0) out vec2 v_Uv;
+layout(location = 1) out float v_Layer;
+layout(location = 2) out vec4 v_Color;
+
+layout(set = 0, binding = 0) uniform CameraViewProj {
+    mat4 ViewProj;
+};
+
+layout(set = 2, binding = 0) uniform Transform {
+    mat4 Model;
+};
+
+layout(set = 2, binding = 1) uniform LayerTint {
+    vec4 Tint;
+};
+
+void main() {
+    v_Uv = Vertex_Uv;
+    v_Layer = Vertex_Layer;
+    v_Color = Vertex_Color * Tint;
+    gl_Position = ViewProj * Model * vec4(Vertex_Position.xy, 0.0, 1.0);
+}
\ No newline at end of file
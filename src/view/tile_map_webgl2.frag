@@ -3,6 +3,7 @@
 precision highp float;
 
 in vec2 v_Uv;
+in vec4 v_Color;
 
 out vec4 o_Target;
 
@@ -24,7 +25,7 @@ vec4 encodeSRGB(vec4 linearRGB_in)
 }
 
 void main() {
-    vec4 color = Color;
+    vec4 color = Color * v_Color;
 # ifdef COLORMATERIAL_TEXTURE
     color *= texture(
         ColorMaterial_texture,
@@ -1,9 +1,11 @@
 #version 300 es
 
 layout(location = 0) in vec3 Vertex_Position;
+layout(location = 1) in vec4 Vertex_Color;
 layout(location = 2) in vec2 Vertex_Uv;
 
 out vec2 v_Uv;
+out vec4 v_Color;
 
 layout(std140) uniform CameraViewProj {
     mat4 ViewProj;
@@ -13,7 +15,12 @@ layout(std140) uniform Transform {  // set = 2, binding = 0
     mat4 Model;
 };
 
+layout(std140) uniform LayerTint {  // set = 2, binding = 1
+    vec4 Tint;
+};
+
 void main() {
     v_Uv = Vertex_Uv;
+    v_Color = Vertex_Color * Tint;
     gl_Position = ViewProj * Model * vec4(Vertex_Position.xy, 0.0, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,57 @@
+use bevy::{prelude::*, render::texture::TextureFormat};
+
+/// Inserted on a map bundle entity until the tileset images declaring a `transparentcolor`
+/// are loaded and their pixels of that color are made transparent.
+pub struct TransparentColorKeys {
+    pub keys: Vec<(Handle<Texture>, [u8; 3])>,
+}
+
+/// Color key of each tileset image that declares a transparent color.
+pub fn transparent_color_keys(
+    tilesets: &[tiled::Tileset],
+    mut load: impl FnMut(&tiled::Image) -> Handle<Texture>,
+) -> Vec<(Handle<Texture>, [u8; 3])> {
+    tilesets
+        .iter()
+        .flat_map(|tileset| tileset.images.iter())
+        .filter_map(|image| {
+            let color = image.transparent_colour.as_ref()?;
+            Some((load(image), [color.red, color.green, color.blue]))
+        })
+        .collect()
+}
+
+/// Clears the alpha of the pixels matching the transparent color once the tileset images are loaded.
+pub fn apply_transparent_colors(
+    mut commands: Commands,
+    mut textures: ResMut<Assets<Texture>>,
+    query: Query<(Entity, &TransparentColorKeys)>,
+) {
+    for (entity, color_keys) in query.iter() {
+        if color_keys
+            .keys
+            .iter()
+            .any(|(texture, _)| textures.get(texture).is_none())
+        {
+            continue;
+        }
+        commands.entity(entity).remove::<TransparentColorKeys>();
+
+        for (handle, key) in color_keys.keys.iter() {
+            let texture = textures.get_mut(handle).unwrap();
+            match texture.format {
+                TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Unorm => {
+                    for pixel in texture.data.chunks_exact_mut(4) {
+                        if pixel[..3] == key[..] {
+                            pixel[3] = 0;
+                        }
+                    }
+                }
+                format => warn!(
+                    "Can't apply the transparent color of a tileset image in {:?} format",
+                    format
+                ),
+            }
+        }
+    }
+}
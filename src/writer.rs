@@ -0,0 +1,352 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+use tiled::{Colour, Image, LayerData, LayerTile, ObjectShape, Properties, PropertyValue};
+
+use crate::loader::{FLIPPED_DIAGONALLY_FLAG, FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG};
+
+// layers of every kind, ordered by their layer index when writing
+enum MapLayerRef<'a> {
+    Tiles(&'a tiled::Layer),
+    Image(&'a tiled::ImageLayer),
+    Objects(&'a tiled::ObjectGroup),
+}
+
+/// Writes a parsed map as Tiled XML, with tilesets embedded and layer data CSV-encoded.
+pub fn write_tmx(map: &tiled::Map) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    write!(
+        out,
+        r#"<map version="{}" orientation="{}" renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="{}""#,
+        escape(&map.version),
+        orientation_name(map.orientation),
+        map.width,
+        map.height,
+        map.tile_width,
+        map.tile_height,
+        map.infinite as u8,
+    )?;
+    if let Some(colour) = &map.background_colour {
+        write!(out, r#" backgroundcolor="{}""#, colour_hex(colour))?;
+    }
+    writeln!(out, ">")?;
+    write_properties(&mut out, &map.properties, 1)?;
+
+    for tileset in map.tilesets.iter() {
+        write_tileset(&mut out, tileset)?;
+    }
+
+    let mut layers = Vec::new();
+    layers.extend(
+        map.layers
+            .iter()
+            .map(|l| (l.layer_index, MapLayerRef::Tiles(l))),
+    );
+    layers.extend(
+        map.image_layers
+            .iter()
+            .map(|l| (l.layer_index, MapLayerRef::Image(l))),
+    );
+    layers.extend(
+        map.object_groups
+            .iter()
+            .map(|g| (g.layer_index.unwrap_or(0), MapLayerRef::Objects(g))),
+    );
+    layers.sort_by_key(|(layer_index, _)| *layer_index);
+
+    for (_, layer) in layers.iter() {
+        match layer {
+            MapLayerRef::Tiles(layer) => write_layer(&mut out, map, layer)?,
+            MapLayerRef::Image(layer) => write_image_layer(&mut out, layer)?,
+            MapLayerRef::Objects(group) => write_object_group(&mut out, group, 1)?,
+        }
+    }
+
+    writeln!(out, "</map>")?;
+    Ok(out)
+}
+
+fn write_tileset(out: &mut String, tileset: &tiled::Tileset) -> Result<()> {
+    write!(
+        out,
+        r#" <tileset firstgid="{}" name="{}" tilewidth="{}" tileheight="{}" spacing="{}" margin="{}""#,
+        tileset.first_gid,
+        escape(&tileset.name),
+        tileset.tile_width,
+        tileset.tile_height,
+        tileset.spacing,
+        tileset.margin,
+    )?;
+    if let Some(tilecount) = tileset.tilecount {
+        write!(out, r#" tilecount="{}""#, tilecount)?;
+    }
+    writeln!(out, ">")?;
+    write_properties(out, &tileset.properties, 2)?;
+    for image in tileset.images.iter() {
+        write_image(out, image, 2)?;
+    }
+    for tile in tileset.tiles.iter() {
+        write!(out, r#"  <tile id="{}""#, tile.id)?;
+        if let Some(tile_type) = &tile.tile_type {
+            write!(out, r#" type="{}""#, escape(tile_type))?;
+        }
+        if (tile.probability - 1.0).abs() > f32::EPSILON {
+            write!(out, r#" probability="{}""#, tile.probability)?;
+        }
+        writeln!(out, ">")?;
+        write_properties(out, &tile.properties, 3)?;
+        for image in tile.images.iter() {
+            write_image(out, image, 3)?;
+        }
+        if let Some(group) = &tile.objectgroup {
+            write_object_group(out, group, 3)?;
+        }
+        if let Some(frames) = &tile.animation {
+            writeln!(out, "   <animation>")?;
+            for frame in frames.iter() {
+                writeln!(
+                    out,
+                    r#"    <frame tileid="{}" duration="{}"/>"#,
+                    frame.tile_id, frame.duration
+                )?;
+            }
+            writeln!(out, "   </animation>")?;
+        }
+        writeln!(out, "  </tile>")?;
+    }
+    writeln!(out, " </tileset>")?;
+    Ok(())
+}
+
+fn write_layer(out: &mut String, map: &tiled::Map, layer: &tiled::Layer) -> Result<()> {
+    writeln!(
+        out,
+        r#" <layer name="{}" width="{}" height="{}" opacity="{}" visible="{}" offsetx="{}" offsety="{}">"#,
+        escape(&layer.name),
+        map.width,
+        map.height,
+        layer.opacity,
+        layer.visible as u8,
+        layer.offset_x,
+        layer.offset_y,
+    )?;
+    write_properties(out, &layer.properties, 2)?;
+    match &layer.tiles {
+        LayerData::Finite(tiles) => {
+            writeln!(out, r#"  <data encoding="csv">"#)?;
+            write_csv(out, tiles)?;
+            writeln!(out, "  </data>")?;
+        }
+        LayerData::Infinite(chunks) => {
+            writeln!(out, r#"  <data encoding="csv">"#)?;
+            let mut chunks = chunks.values().collect::<Vec<_>>();
+            chunks.sort_by_key(|chunk| (chunk.y, chunk.x));
+            for chunk in chunks {
+                writeln!(
+                    out,
+                    r#"   <chunk x="{}" y="{}" width="{}" height="{}">"#,
+                    chunk.x, chunk.y, chunk.width, chunk.height
+                )?;
+                write_csv(out, &chunk.tiles)?;
+                writeln!(out, "   </chunk>")?;
+            }
+            writeln!(out, "  </data>")?;
+        }
+    }
+    writeln!(out, " </layer>")?;
+    Ok(())
+}
+
+fn write_csv(out: &mut String, tiles: &[Vec<LayerTile>]) -> Result<()> {
+    let rows = tiles
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|tile| tile_gid_with_flags(tile).to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>();
+    writeln!(out, "{}", rows.join(",\n"))?;
+    Ok(())
+}
+
+fn tile_gid_with_flags(tile: &LayerTile) -> u32 {
+    let mut gid = tile.gid;
+    if tile.flip_h {
+        gid |= FLIPPED_HORIZONTALLY_FLAG;
+    }
+    if tile.flip_v {
+        gid |= FLIPPED_VERTICALLY_FLAG;
+    }
+    if tile.flip_d {
+        gid |= FLIPPED_DIAGONALLY_FLAG;
+    }
+    gid
+}
+
+fn write_image_layer(out: &mut String, layer: &tiled::ImageLayer) -> Result<()> {
+    writeln!(
+        out,
+        r#" <imagelayer name="{}" opacity="{}" visible="{}" offsetx="{}" offsety="{}">"#,
+        escape(&layer.name),
+        layer.opacity,
+        layer.visible as u8,
+        layer.offset_x,
+        layer.offset_y,
+    )?;
+    write_properties(out, &layer.properties, 2)?;
+    if let Some(image) = &layer.image {
+        write_image(out, image, 2)?;
+    }
+    writeln!(out, " </imagelayer>")?;
+    Ok(())
+}
+
+fn write_object_group(out: &mut String, group: &tiled::ObjectGroup, depth: usize) -> Result<()> {
+    let indent = " ".repeat(depth);
+    write!(
+        out,
+        r#"{}<objectgroup name="{}" opacity="{}" visible="{}""#,
+        indent,
+        escape(&group.name),
+        group.opacity,
+        group.visible as u8,
+    )?;
+    if let Some(colour) = &group.colour {
+        write!(out, r#" color="{}""#, colour_hex(colour))?;
+    }
+    writeln!(out, ">")?;
+    write_properties(out, &group.properties, depth + 1)?;
+    for object in group.objects.iter() {
+        write_object(out, object, depth + 1)?;
+    }
+    writeln!(out, "{}</objectgroup>", indent)?;
+    Ok(())
+}
+
+fn write_object(out: &mut String, object: &tiled::Object, depth: usize) -> Result<()> {
+    let indent = " ".repeat(depth);
+    write!(out, r#"{}<object id="{}""#, indent, object.id)?;
+    if object.gid != 0 {
+        write!(out, r#" gid="{}""#, object.gid)?;
+    }
+    if !object.name.is_empty() {
+        write!(out, r#" name="{}""#, escape(&object.name))?;
+    }
+    if !object.obj_type.is_empty() {
+        write!(out, r#" type="{}""#, escape(&object.obj_type))?;
+    }
+    write!(out, r#" x="{}" y="{}""#, object.x, object.y)?;
+    if object.width != 0.0 || object.height != 0.0 {
+        write!(
+            out,
+            r#" width="{}" height="{}""#,
+            object.width, object.height
+        )?;
+    }
+    if object.rotation != 0.0 {
+        write!(out, r#" rotation="{}""#, object.rotation)?;
+    }
+    if !object.visible {
+        write!(out, r#" visible="0""#)?;
+    }
+    writeln!(out, ">")?;
+    write_properties(out, &object.properties, depth + 1)?;
+    let inner_indent = " ".repeat(depth + 1);
+    match &object.shape {
+        ObjectShape::Rect { .. } => {}
+        ObjectShape::Ellipse { .. } => writeln!(out, "{}<ellipse/>", inner_indent)?,
+        ObjectShape::Polyline { points } => writeln!(
+            out,
+            r#"{}<polyline points="{}"/>"#,
+            inner_indent,
+            points_string(points)
+        )?,
+        ObjectShape::Polygon { points } => writeln!(
+            out,
+            r#"{}<polygon points="{}"/>"#,
+            inner_indent,
+            points_string(points)
+        )?,
+        ObjectShape::Point(_, _) => writeln!(out, "{}<point/>", inner_indent)?,
+    }
+    writeln!(out, "{}</object>", indent)?;
+    Ok(())
+}
+
+fn write_image(out: &mut String, image: &Image, depth: usize) -> Result<()> {
+    write!(
+        out,
+        r#"{}<image source="{}" width="{}" height="{}""#,
+        " ".repeat(depth),
+        escape(&image.source),
+        image.width,
+        image.height,
+    )?;
+    if let Some(colour) = &image.transparent_colour {
+        write!(out, r#" trans="{}""#, &colour_hex(colour)[1..])?;
+    }
+    writeln!(out, "/>")?;
+    Ok(())
+}
+
+fn write_properties(out: &mut String, properties: &Properties, depth: usize) -> Result<()> {
+    if properties.is_empty() {
+        return Ok(());
+    }
+    let indent = " ".repeat(depth);
+    writeln!(out, "{}<properties>", indent)?;
+    // sort for stable output
+    let mut names = properties.keys().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        let (property_type, value) = match &properties[name] {
+            PropertyValue::BoolValue(value) => ("bool", value.to_string()),
+            PropertyValue::FloatValue(value) => ("float", value.to_string()),
+            PropertyValue::IntValue(value) => ("int", value.to_string()),
+            PropertyValue::ColorValue(value) => ("color", format!("#{:08x}", value)),
+            PropertyValue::StringValue(value) => ("string", value.clone()),
+        };
+        writeln!(
+            out,
+            r#"{} <property name="{}" type="{}" value="{}"/>"#,
+            indent,
+            escape(name),
+            property_type,
+            escape(&value)
+        )?;
+    }
+    writeln!(out, "{}</properties>", indent)?;
+    Ok(())
+}
+
+fn orientation_name(orientation: tiled::Orientation) -> &'static str {
+    match orientation {
+        tiled::Orientation::Orthogonal => "orthogonal",
+        tiled::Orientation::Isometric => "isometric",
+        tiled::Orientation::Staggered => "staggered",
+        tiled::Orientation::Hexagonal => "hexagonal",
+    }
+}
+
+fn colour_hex(colour: &Colour) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour.red, colour.green, colour.blue)
+}
+
+fn points_string(points: &[(f32, f32)]) -> String {
+    points
+        .iter()
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}